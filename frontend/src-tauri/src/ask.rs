@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::export::format_clock;
+use crate::ollama::{ChatMessage, ModelOptions, OllamaClient};
+use crate::semantic::{self, SemanticHit};
+
+const DEFAULT_PASSAGES: usize = 8;
+
+const ASK_SYSTEM_PROMPT: &str = "You answer questions about the user's past meetings using \
+only the numbered transcript excerpts provided. Cite every claim with the excerpt number in \
+square brackets, e.g. [2]. If the excerpts do not contain the answer, say so plainly instead \
+of guessing.";
+
+// Matches "[3]" as well as grouped citations like "[1, 4]"
+static CITATION: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").unwrap());
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AskScope {
+    // Limit retrieval to these sessions; all sessions when absent
+    pub session_ids: Option<Vec<String>>,
+    pub max_passages: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Citation {
+    pub index: usize,
+    pub session_id: String,
+    pub start: f32,
+    pub end: f32,
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    pub text: String,
+    pub score: f32,
+    // Whether the answer actually referenced this excerpt
+    pub cited: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MeetingAnswer {
+    pub question: String,
+    pub answer: String,
+    pub citations: Vec<Citation>,
+}
+
+fn build_context(hits: &[SemanticHit]) -> String {
+    hits.iter()
+        .enumerate()
+        .map(|(i, hit)| {
+            format!(
+                "[{}] Meeting {} at {}-{}:\n{}",
+                i + 1,
+                hit.session_id,
+                format_clock(hit.passage.start),
+                format_clock(hit.passage.end),
+                hit.passage.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn cited_indices(answer: &str) -> BTreeSet<usize> {
+    CITATION
+        .captures_iter(answer)
+        .flat_map(|caps| {
+            caps[1]
+                .split(',')
+                .filter_map(|n| n.trim().parse().ok())
+                .collect::<Vec<usize>>()
+        })
+        .collect()
+}
+
+// Numbers outside the excerpt list are ignored rather than attached to the wrong passage
+fn build_citations(hits: Vec<SemanticHit>, answer: &str) -> Vec<Citation> {
+    let cited = cited_indices(answer);
+    hits.into_iter()
+        .enumerate()
+        .map(|(i, hit)| Citation {
+            index: i + 1,
+            cited: cited.contains(&(i + 1)),
+            session_id: hit.session_id,
+            start: hit.passage.start,
+            end: hit.passage.end,
+            first_sequence: hit.passage.first_sequence,
+            last_sequence: hit.passage.last_sequence,
+            text: hit.passage.text,
+            score: hit.score,
+        })
+        .collect()
+}
+
+pub async fn ask_meetings(
+    client: &OllamaClient,
+    question: &str,
+    scope: AskScope,
+    chat_model: &str,
+    embedding_model: &str,
+    on_token: impl FnMut(&str),
+) -> Result<MeetingAnswer> {
+    if question.trim().is_empty() {
+        return Err(anyhow!("Question is empty"));
+    }
+
+    let hits = semantic::semantic_search(
+        client,
+        question,
+        embedding_model,
+        scope.session_ids,
+        Some(scope.max_passages.unwrap_or(DEFAULT_PASSAGES)),
+    )
+    .await?;
+    if hits.is_empty() {
+        return Err(anyhow!("No meeting transcripts available to answer from"));
+    }
+    info!("Answering question with {} retrieved passage(s)", hits.len());
+
+    let messages = [
+        ChatMessage::system(ASK_SYSTEM_PROMPT),
+        ChatMessage::user(format!(
+            "Transcript excerpts:\n\n{}\n\nQuestion: {}",
+            build_context(&hits),
+            question
+        )),
+    ];
+    let options = ModelOptions {
+        num_ctx: None,
+        temperature: Some(0.2),
+    };
+    let answer = client.chat(chat_model, &messages, None, &options, on_token).await?;
+
+    let citations = build_citations(hits, &answer);
+
+    Ok(MeetingAnswer {
+        question: question.to_string(),
+        answer,
+        citations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::Passage;
+
+    fn hit(session_id: &str, start: f32, text: &str) -> SemanticHit {
+        SemanticHit {
+            session_id: session_id.to_string(),
+            score: 0.5,
+            passage: Passage {
+                first_sequence: start as u64,
+                last_sequence: start as u64 + 1,
+                start,
+                end: start + 65.0,
+                text: text.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn citations_accept_single_and_grouped_numbers() {
+        let cited = cited_indices("Budget is set [1]. Dates moved [2,3] and [ 4 ]. See [1, 5 ,6].");
+        assert_eq!(cited.into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 5, 6]);
+        assert!(cited_indices("No [citation] here, nor [] or [1,] or [99999999999999999999]").is_empty());
+    }
+
+    #[test]
+    fn out_of_range_citations_are_ignored() {
+        let hits = vec![hit("s1", 0.0, "first"), hit("s2", 120.0, "second")];
+        let citations = build_citations(hits, "Only [2] and [0, 3, 7] are referenced");
+        assert_eq!(citations.len(), 2);
+        assert_eq!((citations[0].index, citations[0].cited), (1, false));
+        assert_eq!((citations[1].index, citations[1].cited), (2, true));
+        assert_eq!(citations[1].session_id, "s2");
+        assert_eq!((citations[1].start, citations[1].end), (120.0, 185.0));
+        assert_eq!((citations[1].first_sequence, citations[1].last_sequence), (120, 121));
+        assert_eq!(citations[1].text, "second");
+    }
+
+    #[test]
+    fn context_numbers_excerpts_from_one() {
+        let hits = vec![hit("s1", 0.0, "first"), hit("s2", 3661.0, "second")];
+        assert_eq!(
+            build_context(&hits),
+            "[1] Meeting s1 at 00:00:00-00:01:05:\nfirst\n\n[2] Meeting s2 at 01:01:01-01:02:06:\nsecond"
+        );
+        assert_eq!(build_context(&[]), "");
+    }
+}
@@ -8,7 +8,8 @@ use rubato::{
 };
 use std::path::PathBuf;
 
-use super::encode::encode_single_audio; // Correct path to encode module
+use super::encode::{encode_single_audio, EncodeError};
+use crate::vault;
 
 pub fn normalize_v2(audio: &[f32]) -> Vec<f32> {
     let rms = (audio.iter().map(|&x| x * x).sum::<f32>() / audio.len() as f32).sqrt();
@@ -155,23 +156,28 @@ pub fn write_audio_to_file(
     output_path: &PathBuf,
     device: &str,
     skip_encoding: bool,
-) -> Result<String> {
+) -> Result<String, EncodeError> {
     let new_file_name = Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
     let sanitized_device_name = device.replace(['/', '\\'], "_");
-    let file_path = PathBuf::from(output_path)
-        .join(format!("{}_{}.mp4", sanitized_device_name, new_file_name))
+    let path = PathBuf::from(output_path).join(format!("{}_{}.mp4", sanitized_device_name, new_file_name));
+    let file_path = path
         .to_str()
-        .expect("Failed to create valid path")
+        .ok_or_else(|| EncodeError::NonUtf8Path {
+            path: path.to_string_lossy().into_owned(),
+        })?
         .to_string();
-    let file_path_clone = file_path.clone();
-    // Run FFmpeg in a separate task
-    if !skip_encoding {
-        encode_single_audio(
-            bytemuck::cast_slice(audio),
-            sample_rate,
-            1,
-            &file_path.into(),
-        )?;
+    if skip_encoding {
+        return Ok(file_path);
     }
-    Ok(file_path_clone)
+    // Without ffmpeg this comes back as a .wav path
+    let written = encode_single_audio(
+        bytemuck::cast_slice(audio),
+        sample_rate,
+        1,
+        &file_path.into(),
+    )?;
+    vault::encrypt_file_in_place(&written).map_err(|e| EncodeError::Output {
+        message: e.to_string(),
+    })?;
+    Ok(written.to_string_lossy().into_owned())
 }
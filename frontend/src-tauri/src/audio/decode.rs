@@ -0,0 +1,311 @@
+use super::audio_processing::audio_to_mono;
+use super::encode::StderrTail;
+use super::ffmpeg::find_ffmpeg_path;
+use super::profile::{AudioTrack, TrackLayout};
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::vault;
+
+// Sample rate ffmpeg is asked to produce when it does the decoding
+const FFMPEG_DECODE_SAMPLE_RATE: u32 = 16000;
+// Samples read from the ffmpeg pipe per call (~0.5s at 16 kHz)
+const FFMPEG_READ_SAMPLES: usize = 8000;
+
+// Decodes an audio or video file into mono f32 blocks, preferring symphonia and
+// falling back to ffmpeg for containers and codecs symphonia does not handle.
+pub enum MediaDecoder {
+    Symphonia(SymphoniaDecoder),
+    Ffmpeg(FfmpegDecoder),
+}
+
+// Which part of a recording to decode: everything mixed down, or one side of a
+// recording made with separate mic and system tracks
+#[derive(Debug, Clone, Copy)]
+pub enum TrackSelection {
+    Mixed,
+    Channel(usize),
+    Stream(usize),
+}
+
+impl TrackSelection {
+    pub fn for_track(layout: TrackLayout, track: AudioTrack) -> Result<Self> {
+        match layout {
+            TrackLayout::Mixed => Err(anyhow!("Recording has mic and system audio mixed into one track")),
+            TrackLayout::StereoSplit => Ok(TrackSelection::Channel(track.index())),
+            TrackLayout::MultiTrack => Ok(TrackSelection::Stream(track.index())),
+        }
+    }
+}
+
+impl MediaDecoder {
+    // Only the first audio stream of a multi-track file is decoded
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_track(path, TrackSelection::Mixed)
+    }
+
+    pub fn open_track(path: &Path, selection: TrackSelection) -> Result<Self> {
+        match SymphoniaDecoder::open(path, selection) {
+            Ok(decoder) => Ok(MediaDecoder::Symphonia(decoder)),
+            Err(e) => {
+                warn!("symphonia could not open {:?} ({}), falling back to ffmpeg", path, e);
+                FfmpegDecoder::open(path, selection).map(MediaDecoder::Ffmpeg)
+            }
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match self {
+            MediaDecoder::Symphonia(d) => d.sample_rate,
+            MediaDecoder::Ffmpeg(_) => FFMPEG_DECODE_SAMPLE_RATE,
+        }
+    }
+
+    pub fn duration_secs(&self) -> Option<f64> {
+        match self {
+            MediaDecoder::Symphonia(d) => d.duration_secs,
+            MediaDecoder::Ffmpeg(d) => d.duration_secs,
+        }
+    }
+
+    // Returns the next block of mono samples, or None at end of stream
+    pub fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        match self {
+            MediaDecoder::Symphonia(d) => d.next_block(),
+            MediaDecoder::Ffmpeg(d) => d.next_block(),
+        }
+    }
+}
+
+pub struct SymphoniaDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    // Channel to keep instead of mixing down to mono
+    channel: Option<usize>,
+    sample_rate: u32,
+    duration_secs: Option<f64>,
+}
+
+impl SymphoniaDecoder {
+    pub fn open(path: &Path, selection: TrackSelection) -> Result<Self> {
+        // Encrypted recordings are decrypted into memory so no plain copy touches the disk
+        let source: Box<dyn MediaSource> = if vault::is_encrypted_file(path)? {
+            Box::new(Cursor::new(vault::read(path)?))
+        } else {
+            Box::new(File::open(path)?)
+        };
+        let mss = MediaSourceStream::new(source, Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+
+        // Video containers list the video track too; pick the first track that carries audio
+        let stream = match selection {
+            TrackSelection::Stream(index) => index,
+            _ => 0,
+        };
+        let track = format
+            .tracks()
+            .iter()
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL && t.codec_params.sample_rate.is_some())
+            .nth(stream)
+            .ok_or_else(|| anyhow!("No audio track {} found", stream))?;
+        let channel = match selection {
+            TrackSelection::Channel(channel) => {
+                let count = track.codec_params.channels.map(|c| c.count()).unwrap_or(1);
+                if channel >= count {
+                    return Err(anyhow!("Audio track has no channel {}", channel));
+                }
+                Some(channel)
+            }
+            _ => None,
+        };
+
+        let track_id = track.id;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or_else(|| anyhow!("Audio track has no sample rate"))?;
+        let duration_secs = track
+            .codec_params
+            .n_frames
+            .map(|frames| frames as f64 / sample_rate as f64);
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        debug!(
+            "Opened {:?} with symphonia: {} Hz, duration {:?}s",
+            path, sample_rate, duration_secs
+        );
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            channel,
+            sample_rate,
+            duration_secs,
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+                    let channels = spec.channels.count();
+                    return Ok(Some(match self.channel {
+                        Some(channel) => buffer.samples().iter().skip(channel).step_by(channels).copied().collect(),
+                        None => audio_to_mono(buffer.samples(), channels as u16),
+                    }));
+                }
+                // Corrupt packets are skipped rather than aborting the whole file
+                Err(SymphoniaError::DecodeError(e)) => warn!("Skipping undecodable packet: {}", e),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+pub struct FfmpegDecoder {
+    child: Child,
+    stdout: ChildStdout,
+    // Taken when the process is reaped, to explain a failed decode
+    stderr: Option<StderrTail>,
+    duration_secs: Option<f64>,
+}
+
+impl FfmpegDecoder {
+    pub fn open(path: &Path, selection: TrackSelection) -> Result<Self> {
+        let ffmpeg = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg not found"))?;
+        let input = path.to_str().ok_or_else(|| anyhow!("Path is not valid UTF-8: {:?}", path))?;
+        let duration_secs = probe_duration(&ffmpeg, input);
+
+        let select: Vec<String> = match selection {
+            TrackSelection::Mixed => Vec::new(),
+            TrackSelection::Channel(channel) => vec!["-af".to_string(), format!("pan=mono|c0=c{}", channel)],
+            TrackSelection::Stream(stream) => vec!["-map".to_string(), format!("0:a:{}", stream)],
+        };
+        let mut child = Command::new(&ffmpeg)
+            .args(["-nostdin", "-i", input, "-vn"])
+            .args(&select)
+            .args([
+                "-f",
+                "f32le",
+                "-ac",
+                "1",
+                "-ar",
+                &FFMPEG_DECODE_SAMPLE_RATE.to_string(),
+                "pipe:1",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("Failed to open ffmpeg pipes"));
+        };
+
+        debug!("Decoding {:?} with ffmpeg, duration {:?}s", path, duration_secs);
+        Ok(Self {
+            child,
+            stdout,
+            stderr: Some(StderrTail::spawn(stderr)),
+            duration_secs,
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        let mut bytes = vec![0u8; FFMPEG_READ_SAMPLES * 4];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let read = self.stdout.read(&mut bytes[filled..])?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+
+        if filled == 0 {
+            let status = self.child.wait()?;
+            let stderr = self.stderr.take().map(StderrTail::join).unwrap_or_default();
+            if !status.success() {
+                return Err(anyhow!("ffmpeg exited with status {}: {}", status, stderr));
+            }
+            return Ok(None);
+        }
+
+        bytes.truncate(filled - filled % 4);
+        Ok(Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        ))
+    }
+}
+
+impl Drop for FfmpegDecoder {
+    fn drop(&mut self) {
+        // Reap the process if decoding was abandoned part way through
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+// `ffmpeg -i` with no output prints the container duration to stderr and exits non-zero
+pub(crate) fn probe_duration(ffmpeg: &Path, input: &str) -> Option<f64> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-i", input])
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let value = stderr.split("Duration: ").nth(1)?.split(',').next()?.trim();
+
+    let mut parts = value.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
@@ -1,12 +1,18 @@
-use super::ffmpeg::find_ffmpeg_path; // Correct path to encode module
+use super::ffmpeg::{self, find_ffmpeg_path, FfmpegStatus};
+use super::profile::{AudioFormat, EncodingProfile};
 use super::AudioDevice;
-use std::io::Write;
-use std::sync::Arc;
+use crate::vault::SealingWriter;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
 use std::{
-    path::PathBuf,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio},
 };
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 pub struct AudioInput {
     pub data: Arc<Vec<f32>>,
@@ -15,17 +21,103 @@ pub struct AudioInput {
     pub device: Arc<AudioDevice>,
 }
 
-pub fn encode_single_audio(
-    data: &[u8],
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EncodeError {
+    SpawnFailed { message: String },
+    NonUtf8Path { path: String },
+    // ffmpeg stopped reading its input, usually because it crashed
+    BrokenPipe { stderr: String },
+    Exited { code: Option<i32>, stderr: String },
+    // Writing, finalizing or encrypting the output file failed
+    Output { message: String },
+    Stopped,
+}
+
+impl EncodeError {
+    fn output(error: impl fmt::Display) -> Self {
+        EncodeError::Output {
+            message: error.to_string(),
+        }
+    }
+
+    fn non_utf8(path: &Path) -> Self {
+        EncodeError::NonUtf8Path {
+            path: path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::SpawnFailed { message } => write!(f, "failed to start ffmpeg: {}", message),
+            EncodeError::NonUtf8Path { path } => write!(f, "output path is not valid UTF-8: {}", path),
+            EncodeError::BrokenPipe { stderr } => write!(f, "ffmpeg stopped reading audio: {}", stderr),
+            EncodeError::Exited { code: Some(code), stderr } => {
+                write!(f, "ffmpeg exited with code {}: {}", code, stderr)
+            }
+            EncodeError::Exited { code: None, stderr } => write!(f, "ffmpeg was killed: {}", stderr),
+            EncodeError::Output { message } => write!(f, "failed to write audio: {}", message),
+            EncodeError::Stopped => write!(f, "the encoder has already stopped"),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// ffmpeg blocks once its stderr pipe is full, so it is drained on a thread that keeps
+// the last lines for error reports
+pub(crate) struct StderrTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    reader: std::thread::JoinHandle<()>,
+}
+
+impl StderrTail {
+    pub(crate) fn spawn(stderr: ChildStderr) -> Self {
+        let lines = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LINES)));
+        let tail = lines.clone();
+        let reader = std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if let Ok(mut tail) = tail.lock() {
+                    if tail.len() == STDERR_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            }
+        });
+        Self { lines, reader }
+    }
+
+    // Waits for ffmpeg to close stderr; call after the process has exited
+    pub(crate) fn join(self) -> String {
+        let _ = self.reader.join();
+        self.lines
+            .lock()
+            .map(|tail| tail.iter().cloned().collect::<Vec<_>>().join("\n"))
+            .unwrap_or_default()
+    }
+}
+
+// With `output_path` unset ffmpeg writes to its stdout, which is returned
+fn spawn_ffmpeg(
+    ffmpeg_path: PathBuf,
     sample_rate: u32,
     channels: u16,
-    output_path: &PathBuf,
-) -> anyhow::Result<()> {
-    debug!("Starting FFmpeg process");
+    profile: &EncodingProfile,
+    output_path: Option<&Path>,
+    streaming: bool,
+) -> Result<(Child, ChildStdin, StderrTail, Option<ChildStdout>), EncodeError> {
+    let output = match output_path {
+        Some(path) => path.to_str().ok_or_else(|| EncodeError::non_utf8(path))?,
+        None => "pipe:1",
+    };
 
-    let mut command = Command::new(find_ffmpeg_path().unwrap());
+    let mut command = Command::new(ffmpeg_path);
     command
         .args([
+            "-y",
             "-f",
             "f32le",
             "-ar",
@@ -34,51 +126,759 @@ pub fn encode_single_audio(
             &channels.to_string(),
             "-i",
             "pipe:0",
-            "-c:a",
-            "aac",
-            "-b:a",
-            "64k", // Reduced bitrate for higher compression
-            "-profile:a",
-            "aac_low", // Use AAC-LC profile for better compatibility
-            "-movflags",
-            "+faststart", // Optimize for web streaming
-            "-f",
-            "mp4",
-            output_path.to_str().unwrap(),
         ])
+        .args(profile.ffmpeg_output_args(sample_rate, channels, streaming))
+        .arg(output)
         .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
+        .stdout(if output_path.is_some() { Stdio::null() } else { Stdio::piped() })
         .stderr(Stdio::piped());
-
     debug!("FFmpeg command: {:?}", command);
 
-    #[allow(clippy::zombie_processes)]
-    let mut ffmpeg = command.spawn().expect("Failed to spawn FFmpeg process");
-    debug!("FFmpeg process spawned");
-    let mut stdin = ffmpeg.stdin.take().expect("Failed to open stdin");
-
-    stdin.write_all(data)?;
-
-    debug!("Dropping stdin");
-    drop(stdin);
-    debug!("Waiting for FFmpeg process to exit");
-    let output = ffmpeg.wait_with_output().unwrap();
-    let status = output.status;
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut child = command.spawn().map_err(|e| EncodeError::SpawnFailed {
+        message: e.to_string(),
+    })?;
+    let (Some(stdin), Some(stderr)) = (child.stdin.take(), child.stderr.take()) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(EncodeError::SpawnFailed {
+            message: "ffmpeg pipes were not opened".to_string(),
+        });
+    };
+    let stdout = child.stdout.take();
+    Ok((child, stdin, StderrTail::spawn(stderr), stdout))
+}
 
+// Waits for ffmpeg after its input was closed and turns the exit status and any write
+// error into an `EncodeError`
+fn wait_ffmpeg(mut child: Child, stderr: StderrTail, write_result: std::io::Result<()>) -> Result<(), EncodeError> {
+    let status = child.wait().map_err(EncodeError::output)?;
+    let stderr = stderr.join();
     debug!("FFmpeg process exited with status: {}", status);
-    debug!("FFmpeg stdout: {}", stdout);
-    debug!("FFmpeg stderr: {}", stderr);
 
     if !status.success() {
         error!("FFmpeg process failed with status: {}", status);
         error!("FFmpeg stderr: {}", stderr);
-        return Err(anyhow::anyhow!(
-            "FFmpeg process failed with status: {}",
-            status
-        ));
+        return Err(EncodeError::Exited {
+            code: status.code(),
+            stderr,
+        });
+    }
+    match write_result {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Err(EncodeError::BrokenPipe { stderr }),
+        Err(e) => Err(EncodeError::output(e)),
+    }
+}
+
+// The profile that will actually be written. A format the ffmpeg build has no encoder
+// for falls back to AAC, which every accepted build has. Without ffmpeg the pure-Rust
+// writers take over: WAV always, FLAC for whole buffers when built with `flac-encoder`,
+// and anything else falls back to WAV.
+pub fn available_profile(profile: &EncodingProfile, streaming: bool) -> EncodingProfile {
+    profile_for(profile, streaming, &ffmpeg::status())
+}
+
+fn profile_for(profile: &EncodingProfile, streaming: bool, status: &FfmpegStatus) -> EncodingProfile {
+    if status.path.is_some() {
+        if status.supported_formats.contains(&profile.format) {
+            return profile.clone();
+        }
+        warn!(
+            "ffmpeg {} has no encoder for {:?}, writing AAC instead",
+            status.version.as_deref().unwrap_or("build"),
+            profile.format
+        );
+        return EncodingProfile {
+            format: AudioFormat::AacMp4,
+            ..profile.clone()
+        };
+    }
+    let format = match profile.format {
+        AudioFormat::Flac if cfg!(feature = "flac-encoder") && !streaming => AudioFormat::Flac,
+        AudioFormat::Wav => AudioFormat::Wav,
+        format => {
+            warn!("ffmpeg not found, writing WAV instead of {:?}", format);
+            AudioFormat::Wav
+        }
+    };
+    EncodingProfile {
+        format,
+        ..profile.clone()
+    }
+}
+
+// 64 kbps AAC-LC in MP4, or WAV when ffmpeg is unavailable; returns the path written
+pub fn encode_single_audio(
+    data: &[u8],
+    sample_rate: u32,
+    channels: u16,
+    output_path: &PathBuf,
+) -> Result<PathBuf, EncodeError> {
+    encode_audio(data, sample_rate, channels, output_path, &EncodingProfile::default())
+}
+
+// Encodes interleaved f32le samples with `profile`. When ffmpeg is missing and the
+// format has to change, the extension of `output_path` changes with it; the path
+// actually written is returned.
+pub fn encode_audio(
+    data: &[u8],
+    sample_rate: u32,
+    channels: u16,
+    output_path: &Path,
+    profile: &EncodingProfile,
+) -> Result<PathBuf, EncodeError> {
+    let available = available_profile(profile, false);
+    let output_path = if available.format == profile.format {
+        output_path.to_path_buf()
+    } else {
+        available.output_path(output_path)
+    };
+
+    let Some(ffmpeg_path) = find_ffmpeg_path() else {
+        let samples: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        #[cfg(feature = "flac-encoder")]
+        if available.format == AudioFormat::Flac {
+            encode_flac(&samples, sample_rate, channels, &available, &output_path)?;
+            return Ok(output_path);
+        }
+        let mut sink = WavSink::create(&output_path, sample_rate, channels, &available)?;
+        sink.write(&samples)?;
+        sink.finalize()?;
+        return Ok(output_path);
+    };
+    debug!("Starting FFmpeg process");
+
+    let (child, mut stdin, stderr, _) =
+        spawn_ffmpeg(ffmpeg_path, sample_rate, channels, &available, Some(&output_path), false)?;
+    let write_result = stdin.write_all(data);
+    // Closing stdin is what tells ffmpeg to flush and finalize the file
+    drop(stdin);
+    debug!("Waiting for FFmpeg process to exit");
+    wait_ffmpeg(child, stderr, write_result)?;
+    Ok(output_path)
+}
+
+// Up- or down-mixes interleaved frames to the profile's channel count
+fn remix(samples: &[f32], input_channels: usize, output_channels: usize) -> impl Iterator<Item = f32> + '_ {
+    samples.chunks(input_channels.max(1)).flat_map(move |frame| {
+        let mono = frame.iter().sum::<f32>() / frame.len() as f32;
+        (0..output_channels).map(move |channel| {
+            if output_channels == 1 {
+                mono
+            } else {
+                frame[channel.min(frame.len() - 1)]
+            }
+        })
+    })
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+#[cfg(feature = "flac-encoder")]
+fn encode_flac(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    profile: &EncodingProfile,
+    path: &Path,
+) -> Result<(), EncodeError> {
+    use flacenc::component::BitRepr;
+    use flacenc::error::Verify;
+
+    let output_channels = profile.output_channels(channels);
+    let pcm: Vec<i32> = remix(samples, channels as usize, output_channels as usize)
+        .map(|sample| to_i16(sample) as i32)
+        .collect();
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| EncodeError::output(format!("invalid FLAC config: {:?}", e)))?;
+    let source = flacenc::source::MemSource::from_samples(&pcm, output_channels as usize, 16, sample_rate as usize);
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| EncodeError::output(format!("FLAC encoding failed: {:?}", e)))?;
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|_| EncodeError::output("failed to serialize the FLAC stream"))?;
+    std::fs::write(path, sink.as_slice()).map_err(EncodeError::output)
+}
+
+// 16-bit PCM WAV, for when ffmpeg is not available. Changing the sample rate needs
+// ffmpeg.
+struct WavSink {
+    output: WavOutput,
+    input_channels: usize,
+    output_channels: usize,
+    data_len: u32,
+    // Blocks written since the lengths in the header were last brought up to date
+    unsized_blocks: usize,
+}
+
+enum WavOutput {
+    Plain(BufWriter<File>),
+    // Encryption is on; the header is patched through the sealed first record
+    Sealed(SealingWriter),
+}
+
+const WAV_HEADER_LEN: usize = 44;
+// How often the header lengths are rewritten while recording, so a crash leaves a WAV
+// that plays up to about the last second; at ~10 ms blocks this is about once a second
+const WAV_SIZE_REFRESH_BLOCKS: usize = 100;
+
+fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(WAV_HEADER_LEN);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+impl WavSink {
+    fn create(path: &Path, sample_rate: u32, channels: u16, profile: &EncodingProfile) -> Result<Self, EncodeError> {
+        if profile.output_sample_rate(sample_rate) != sample_rate {
+            warn!(
+                "ffmpeg not found, writing WAV at {} Hz instead of {} Hz",
+                sample_rate,
+                profile.output_sample_rate(sample_rate)
+            );
+        }
+        let output_channels = profile.output_channels(channels);
+        let mut output = match SealingWriter::create(path).map_err(EncodeError::output)? {
+            Some(sealer) => WavOutput::Sealed(sealer),
+            None => WavOutput::Plain(BufWriter::new(File::create(path).map_err(EncodeError::output)?)),
+        };
+        output
+            .write_all(&wav_header(sample_rate, output_channels, 0))
+            .map_err(EncodeError::output)?;
+        Ok(Self {
+            output,
+            input_channels: channels.max(1) as usize,
+            output_channels: output_channels as usize,
+            data_len: 0,
+            unsized_blocks: 0,
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), EncodeError> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in remix(samples, self.input_channels, self.output_channels) {
+            bytes.extend_from_slice(&to_i16(sample).to_le_bytes());
+        }
+        self.output.write_all(&bytes).map_err(EncodeError::output)?;
+        self.data_len = self.data_len.saturating_add(bytes.len() as u32);
+        self.unsized_blocks += 1;
+        if self.unsized_blocks >= WAV_SIZE_REFRESH_BLOCKS {
+            self.write_sizes()?;
+        }
+        Ok(())
+    }
+
+    // Rewrites the RIFF and data lengths for the samples written so far
+    fn write_sizes(&mut self) -> Result<(), EncodeError> {
+        self.unsized_blocks = 0;
+        let riff_len = (36 + self.data_len as u64).min(u32::MAX as u64) as u32;
+        match &mut self.output {
+            WavOutput::Plain(file) => {
+                let mut patch = |offset: u64, value: u32| -> std::io::Result<()> {
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.write_all(&value.to_le_bytes())
+                };
+                patch(4, riff_len)
+                    .and_then(|_| patch(40, self.data_len))
+                    .and_then(|_| file.seek(SeekFrom::End(0)).map(|_| ()))
+                    .and_then(|_| file.flush())
+                    .map_err(EncodeError::output)
+            }
+            WavOutput::Sealed(sealer) => sealer
+                .patch_start(4, &riff_len.to_le_bytes())
+                .and_then(|_| sealer.patch_start(40, &self.data_len.to_le_bytes()))
+                .map_err(EncodeError::output),
+        }
+    }
+
+    fn finalize(mut self) -> Result<(), EncodeError> {
+        self.write_sizes()?;
+        match self.output {
+            WavOutput::Plain(mut file) => file.flush().map_err(EncodeError::output),
+            WavOutput::Sealed(sealer) => sealer.finish().map_err(EncodeError::output),
+        }
+    }
+}
+
+impl Write for WavOutput {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        match self {
+            WavOutput::Plain(file) => file.write(bytes),
+            WavOutput::Sealed(sealer) => sealer.write(bytes),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            WavOutput::Plain(file) => file.flush(),
+            WavOutput::Sealed(sealer) => sealer.flush(),
+        }
     }
+}
+
+// Sample blocks queued for ffmpeg before `push` starts waiting; at the ~10 ms blocks the
+// recording loop produces this is well under a second of audio
+const ENCODER_QUEUE_BLOCKS: usize = 64;
+// A crashed ffmpeg is restarted into a new segment file at most this many times per session
+const MAX_ENCODER_RESTARTS: usize = 3;
+const STDERR_TAIL_LINES: usize = 20;
+
+pub enum PushOutcome {
+    Written,
+    // ffmpeg died; earlier audio is intact in the previous segment and recording
+    // continues in `segment`
+    Restarted { error: EncodeError, segment: PathBuf },
+}
+
+pub struct FinishedRecording {
+    pub segments: Vec<PathBuf>,
+    // Set when the last ffmpeg process did not exit cleanly
+    pub error: Option<EncodeError>,
+}
+
+struct EncoderProcess {
+    sender: tokio::sync::mpsc::Sender<Vec<f32>>,
+    writer: std::thread::JoinHandle<Result<(), EncodeError>>,
+}
+
+// Keeps one ffmpeg process open for the whole session and feeds it sample blocks as
+// they are captured, so the recording never has to be held in memory
+pub struct StreamingEncoder {
+    sample_rate: u32,
+    channels: u16,
+    profile: EncodingProfile,
+    base_path: PathBuf,
+    segments: Vec<PathBuf>,
+    process: Option<EncoderProcess>,
+    restarts: usize,
+}
+
+fn segment_path(base_path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return base_path.to_path_buf();
+    }
+    let stem = base_path.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let extension = base_path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    base_path.with_file_name(format!("{}.part{}.{}", stem, index + 1, extension))
+}
+
+fn spawn_encoder_process(
+    sample_rate: u32,
+    channels: u16,
+    profile: &EncodingProfile,
+    output_path: &Path,
+) -> Result<EncoderProcess, EncodeError> {
+    let Some(ffmpeg_path) = find_ffmpeg_path() else {
+        return spawn_wav_writer(sample_rate, channels, profile, output_path);
+    };
+    // With encryption on, ffmpeg writes to a pipe and the output is sealed on the way to disk
+    let sealer = SealingWriter::create(output_path).map_err(EncodeError::output)?;
+    // Fragmented MP4 is playable up to the last fragment, so a crash loses seconds, not the file
+    let (child, mut stdin, stderr, stdout) = spawn_ffmpeg(
+        ffmpeg_path,
+        sample_rate,
+        channels,
+        profile,
+        sealer.is_none().then_some(output_path),
+        true,
+    )?;
+    // Drained on its own thread so ffmpeg never blocks on a full stdout pipe
+    let sealing = sealer.zip(stdout).map(|(mut sealer, mut stdout)| {
+        std::thread::spawn(move || -> Result<(), EncodeError> {
+            std::io::copy(&mut stdout, &mut sealer).map_err(EncodeError::output)?;
+            sealer.finish().map_err(EncodeError::output)
+        })
+    });
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<Vec<f32>>(ENCODER_QUEUE_BLOCKS);
+    let writer = std::thread::spawn(move || {
+        let mut write_result = Ok(());
+        while let Some(block) = receiver.blocking_recv() {
+            if let Err(e) = stdin.write_all(bytemuck::cast_slice(&block)) {
+                write_result = Err(e);
+                break;
+            }
+        }
+        drop(stdin);
+        drop(receiver);
+        let result = wait_ffmpeg(child, stderr, write_result);
+        let sealed = match sealing.map(|sealing| sealing.join()) {
+            Some(Ok(sealed)) => sealed,
+            Some(Err(_)) => Err(EncodeError::output("recording encryption thread panicked")),
+            None => Ok(()),
+        };
+        result.and(sealed)
+    });
+
+    Ok(EncoderProcess { sender, writer })
+}
+
+fn spawn_wav_writer(
+    sample_rate: u32,
+    channels: u16,
+    profile: &EncodingProfile,
+    output_path: &Path,
+) -> Result<EncoderProcess, EncodeError> {
+    let mut sink = WavSink::create(output_path, sample_rate, channels, profile)?;
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<Vec<f32>>(ENCODER_QUEUE_BLOCKS);
+    let writer = std::thread::spawn(move || {
+        while let Some(block) = receiver.blocking_recv() {
+            sink.write(&block)?;
+        }
+        sink.finalize()
+    });
+    Ok(EncoderProcess { sender, writer })
+}
+
+async fn join_writer(process: EncoderProcess) -> Result<(), EncodeError> {
+    drop(process.sender);
+    let writer = process.writer;
+    match tokio::task::spawn_blocking(move || writer.join()).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err(EncodeError::output("encoder writer thread panicked")),
+        Err(e) => Err(EncodeError::output(e)),
+    }
+}
+
+impl StreamingEncoder {
+    // Pass the profile through `available_profile` first and name `output_path` after
+    // it; the extension is corrected here if ffmpeg has gone missing since
+    pub fn start(
+        output_path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        profile: EncodingProfile,
+    ) -> Result<Self, EncodeError> {
+        let available = available_profile(&profile, true);
+        let output_path = if available.format == profile.format {
+            output_path.to_path_buf()
+        } else {
+            available.output_path(output_path)
+        };
+        let profile = available;
+        let process = spawn_encoder_process(sample_rate, channels, &profile, &output_path)?;
+        debug!("Started streaming {:?} encoder for {:?}", profile.format, output_path);
+        Ok(Self {
+            sample_rate,
+            channels,
+            profile,
+            segments: vec![output_path.clone()],
+            base_path: output_path,
+            process: Some(process),
+            restarts: 0,
+        })
+    }
+
+    pub fn segments(&self) -> &[PathBuf] {
+        &self.segments
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.process.is_some()
+    }
+
+    // Queues interleaved samples, waiting while ffmpeg is behind. If ffmpeg has died
+    // the block is sent to a fresh process writing the next segment instead.
+    pub async fn push(&mut self, samples: Vec<f32>) -> Result<PushOutcome, EncodeError> {
+        let process = self.process.as_ref().ok_or(EncodeError::Stopped)?;
+        let samples = match process.sender.send(samples).await {
+            Ok(()) => return Ok(PushOutcome::Written),
+            Err(tokio::sync::mpsc::error::SendError(samples)) => samples,
+        };
+
+        // The writer only drops its receiver when ffmpeg has gone away
+        let error = match self.process.take() {
+            Some(process) => join_writer(process).await.err(),
+            None => None,
+        }
+        .unwrap_or(EncodeError::Exited {
+            code: None,
+            stderr: String::new(),
+        });
+        error!("Streaming encoder failed: {}", error);
+
+        if self.restarts >= MAX_ENCODER_RESTARTS {
+            error!("ffmpeg failed {} times, giving up", self.restarts + 1);
+            return Err(error);
+        }
+        self.restarts += 1;
+        let segment = segment_path(&self.base_path, self.segments.len());
+        let process = spawn_encoder_process(self.sample_rate, self.channels, &self.profile, &segment)?;
+        self.segments.push(segment.clone());
+        if process.sender.send(samples).await.is_err() {
+            // The new process died straight away; report why
+            return Err(join_writer(process).await.err().unwrap_or(EncodeError::Stopped));
+        }
+        self.process = Some(process);
+        Ok(PushOutcome::Restarted { error, segment })
+    }
+
+    // Closes ffmpeg's input and waits for it to write out the file
+    pub async fn finish(mut self) -> FinishedRecording {
+        let error = match self.process.take() {
+            Some(process) => join_writer(process).await.err(),
+            None => None,
+        };
+        if let Some(error) = &error {
+            error!("Streaming encoder did not finish cleanly: {}", error);
+        }
+        FinishedRecording {
+            segments: self.segments.into_iter().filter(|path| path.exists()).collect(),
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use once_cell::sync::Lazy;
 
-    Ok(())
+    // Stub scripts are written, made executable and configured as the ffmpeg to use one
+    // test at a time; a process forked while another script is open for writing would
+    // make exec fail with "text file busy"
+    static STUB_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+    // A shell script standing in for ffmpeg: it answers the version and encoder probes,
+    // then runs `body` with `$last` set to the output path
+    #[cfg(unix)]
+    fn stub_ffmpeg(name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = test_path(name);
+        let script = format!(
+            "#!/bin/sh\n\
+             case \"$*\" in\n\
+             *-version*) echo 'ffmpeg version 6.0 Copyright (c) stub'; exit 0 ;;\n\
+             *-encoders*) printf 'Encoders:\\n ------\\n A....D aac  AAC\\n A....D pcm_s16le  PCM\\n'; exit 0 ;;\n\
+             esac\n\
+             for last; do :; done\n\
+             {}\n",
+            body
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    // Makes the streaming encoder pick up `stub`
+    #[cfg(unix)]
+    fn use_ffmpeg(stub: &Path) {
+        ffmpeg::save_settings(&ffmpeg::FfmpegSettings {
+            path: Some(stub.to_string_lossy().into_owned()),
+            ..ffmpeg::FfmpegSettings::default()
+        })
+        .unwrap();
+        assert_eq!(find_ffmpeg_path().as_deref(), Some(stub));
+    }
+
+    // Feeds `data` to a one-shot ffmpeg run, as `encode_audio` does
+    fn run_once(ffmpeg_path: PathBuf, output: &Path, data: &[u8]) -> Result<(), EncodeError> {
+        let (child, mut stdin, stderr, _) = spawn_ffmpeg(ffmpeg_path, 16000, 1, &wav_profile(), Some(output), false)?;
+        let write_result = stdin.write_all(data);
+        drop(stdin);
+        wait_ffmpeg(child, stderr, write_result)
+    }
+
+    fn test_path(name: &str) -> PathBuf {
+        let dir = crate::journal::app_data_dir().join("encode-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn wav_profile() -> EncodingProfile {
+        EncodingProfile {
+            format: AudioFormat::Wav,
+            ..EncodingProfile::default()
+        }
+    }
+
+    #[test]
+    fn profile_falls_back_when_the_codec_is_missing() {
+        let opus = EncodingProfile {
+            format: AudioFormat::OpusOgg,
+            ..EncodingProfile::default()
+        };
+        let mut status = FfmpegStatus {
+            path: Some("/usr/bin/ffmpeg".to_string()),
+            supported_formats: vec![AudioFormat::AacMp4, AudioFormat::OpusOgg, AudioFormat::Wav],
+            ..FfmpegStatus::default()
+        };
+        assert_eq!(profile_for(&opus, true, &status).format, AudioFormat::OpusOgg);
+
+        // A build without libopus
+        status.supported_formats = vec![AudioFormat::AacMp4, AudioFormat::Wav];
+        assert_eq!(profile_for(&opus, true, &status).format, AudioFormat::AacMp4);
+
+        status.path = None;
+        assert_eq!(profile_for(&opus, true, &status).format, AudioFormat::Wav);
+        assert_eq!(profile_for(&wav_profile(), true, &status).format, AudioFormat::Wav);
+    }
+
+    #[test]
+    fn unfinished_wav_has_current_lengths() {
+        let path = test_path("unfinished.wav");
+        let mut sink = WavSink::create(&path, 16000, 1, &wav_profile()).unwrap();
+        let channels = sink.output_channels;
+        let block = vec![0.25f32; 160];
+        for _ in 0..WAV_SIZE_REFRESH_BLOCKS * 2 + 5 {
+            sink.write(&block).unwrap();
+        }
+        // Left unfinalized, as after a crash; the blocks since the last refresh are not counted
+        drop(sink);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        let expected = (WAV_SIZE_REFRESH_BLOCKS * 2 * block.len() * channels) as u32;
+        assert_eq!(reader.len(), expected);
+        assert_eq!(reader.spec().sample_rate, 16000);
+    }
+
+    #[test]
+    fn finalized_wav_reads_back() {
+        let path = test_path("finished.wav");
+        let mut sink = WavSink::create(&path, 8000, 1, &wav_profile()).unwrap();
+        let channels = sink.output_channels;
+        sink.write(&[0.5, -0.5, 0.0]).unwrap();
+        sink.finalize().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).step_by(channels).collect();
+        assert_eq!(samples, vec![to_i16(0.5), to_i16(-0.5), 0]);
+    }
+
+    #[test]
+    fn missing_binary_is_a_spawn_failure() {
+        let output = test_path("never.wav");
+        let result = run_once(PathBuf::from("/nonexistent/ffmpeg"), &output, &[0; 16]);
+        assert!(matches!(result, Err(EncodeError::SpawnFailed { .. })), "{:?}", result);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_output_path_is_rejected() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let output = test_path("x").with_file_name(std::ffi::OsStr::from_bytes(b"\xff.wav"));
+        let result = run_once(PathBuf::from("ffmpeg"), &output, &[]);
+        assert!(matches!(result, Err(EncodeError::NonUtf8Path { .. })), "{:?}", result);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_zero_exit_reports_stderr() {
+        let _lock = STUB_LOCK.blocking_lock();
+        let stub = stub_ffmpeg("ffmpeg-fail", "echo 'stub failure' >&2; exit 3");
+        let result = run_once(stub, &test_path("failed.wav"), &[0; 16]);
+        match result {
+            Err(EncodeError::Exited { code, stderr }) => {
+                assert_eq!(code, Some(3));
+                assert!(stderr.contains("stub failure"), "{}", stderr);
+            }
+            other => panic!("expected an exit error, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn closed_input_is_a_broken_pipe() {
+        let _lock = STUB_LOCK.blocking_lock();
+        let stub = stub_ffmpeg("ffmpeg-eof", "echo 'stub stopped reading' >&2; exit 0");
+        // Far more than a pipe buffer, so the write can't complete before the stub exits
+        let result = run_once(stub, &test_path("eof.wav"), &vec![0; 4 * 1024 * 1024]);
+        match result {
+            Err(EncodeError::BrokenPipe { stderr }) => assert!(stderr.contains("stub stopped reading"), "{}", stderr),
+            other => panic!("expected a broken pipe, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unwritable_output_is_an_output_error() {
+        let path = test_path("missing-dir").join("nested").join("out.wav");
+        let result = WavSink::create(&path, 16000, 1, &wav_profile());
+        assert!(matches!(result, Err(EncodeError::Output { .. })));
+    }
+
+    // Pushes blocks until the encoder reports something other than a plain write
+    async fn push_until_event(encoder: &mut StreamingEncoder) -> Result<PushOutcome, EncodeError> {
+        for _ in 0..10_000 {
+            match encoder.push(vec![0.1; 1024]).await? {
+                PushOutcome::Written => tokio::task::yield_now().await,
+                restarted => return Ok(restarted),
+            }
+        }
+        panic!("the stub never stopped reading");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn crashed_encoder_restarts_into_a_new_segment() {
+        let _lock = STUB_LOCK.lock().await;
+        // Only the first process crashes, after writing part of its file
+        let stub = stub_ffmpeg(
+            "ffmpeg-crash-once",
+            "case \"$last\" in *.part*) cat > \"$last\" ;; *) head -c 4096 > \"$last\"; echo 'stub crashed' >&2; exit 1 ;; esac",
+        );
+        use_ffmpeg(&stub);
+
+        let base = test_path("restart.wav");
+        let mut encoder = StreamingEncoder::start(&base, 16000, 1, wav_profile()).unwrap();
+        let part = match push_until_event(&mut encoder).await.unwrap() {
+            PushOutcome::Restarted { error, segment } => {
+                assert!(matches!(&error, EncodeError::Exited { code: Some(1), stderr } if stderr.contains("stub crashed")));
+                segment
+            }
+            PushOutcome::Written => unreachable!(),
+        };
+        assert_eq!(part, test_path("restart.part2.wav"));
+        for _ in 0..10 {
+            assert!(matches!(encoder.push(vec![0.2; 1024]).await.unwrap(), PushOutcome::Written));
+        }
+
+        let finished = encoder.finish().await;
+        assert!(finished.error.is_none(), "{:?}", finished.error);
+        assert_eq!(finished.segments, vec![base.clone(), part.clone()]);
+        assert_eq!(std::fs::metadata(&base).unwrap().len(), 4096);
+        assert!(std::fs::metadata(&part).unwrap().len() >= 10 * 1024 * 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn encoder_gives_up_after_repeated_crashes() {
+        let _lock = STUB_LOCK.lock().await;
+        let stub = stub_ffmpeg("ffmpeg-always-crash", "echo 'stub crashed' >&2; exit 1");
+        use_ffmpeg(&stub);
+
+        let mut encoder = StreamingEncoder::start(&test_path("give-up.wav"), 16000, 1, wav_profile()).unwrap();
+        let mut restarts = 0;
+        let error = loop {
+            match push_until_event(&mut encoder).await {
+                Ok(PushOutcome::Restarted { .. }) => restarts += 1,
+                Ok(PushOutcome::Written) => unreachable!(),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(restarts, MAX_ENCODER_RESTARTS);
+        assert!(matches!(error, EncodeError::Exited { code: Some(1), .. }), "{:?}", error);
+        assert!(!encoder.is_running());
+        assert!(matches!(encoder.push(vec![0.0; 16]).await, Err(EncodeError::Stopped)));
+    }
 }
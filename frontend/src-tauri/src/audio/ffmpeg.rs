@@ -1,198 +1,431 @@
-use ffmpeg_sidecar::{
-    command::ffmpeg_is_installed,
-    download::{check_latest_version, download_ffmpeg_package, ffmpeg_download_url, unpack_ffmpeg},
-    paths::sidecar_dir,
-    version::ffmpeg_version,
-};
-use log::{debug, error};
+use ffmpeg_sidecar::paths::sidecar_dir;
+use log::{debug, info, warn};
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::RwLock;
 use which::which;
 
+use super::profile::AudioFormat;
+use crate::journal;
+
 #[cfg(not(windows))]
 const EXECUTABLE_NAME: &str = "ffmpeg";
 
 #[cfg(windows)]
 const EXECUTABLE_NAME: &str = "ffmpeg.exe";
 
-static FFMPEG_PATH: Lazy<Option<PathBuf>> = Lazy::new(find_ffmpeg_path_internal);
+const SETTINGS_FILE_NAME: &str = "ffmpeg_settings.json";
+// Shipped next to a bundled binary, and written next to a sidecar one when first seen
+const CHECKSUM_SUFFIX: &str = ".sha256";
+// Oldest release with every option the encoder passes (fragmented MP4, channelsplit)
+const MIN_VERSION: (u32, u32) = (4, 0);
+// The default AAC/MP4 profile must always work
+const REQUIRED_ENCODERS: &[&str] = &["aac"];
+
+// Resolved once and reused; cleared when the settings change
+static RESOLVED: Lazy<RwLock<Option<FfmpegStatus>>> = Lazy::new(|| RwLock::new(None));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FfmpegSettings {
+    // Explicit binary to use; checked before anything else
+    pub path: Option<String>,
+    // Reject bundled binaries that have no checksum file
+    pub require_checksum: bool,
+}
+
+impl Default for FfmpegSettings {
+    fn default() -> Self {
+        Self {
+            path: None,
+            require_checksum: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FfmpegSource {
+    Configured,
+    Bundled,
+    Sidecar,
+    SystemPath,
+    LocalBin,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CandidateReport {
+    pub source: FfmpegSource,
+    pub path: String,
+    // Why the candidate was used or skipped
+    pub outcome: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FfmpegStatus {
+    pub path: Option<String>,
+    pub source: Option<FfmpegSource>,
+    pub version: Option<String>,
+    pub checksum_verified: bool,
+    pub encoders: Vec<String>,
+    // Encoding profile formats the resolved binary can write
+    pub supported_formats: Vec<AudioFormat>,
+    pub candidates: Vec<CandidateReport>,
+}
+
+struct Verified {
+    version: String,
+    checksum_verified: bool,
+    encoders: Vec<String>,
+}
+
+fn settings_path() -> PathBuf {
+    journal::app_data_dir().join(SETTINGS_FILE_NAME)
+}
+
+pub fn load_settings() -> anyhow::Result<FfmpegSettings> {
+    let path = settings_path();
+    if !path.exists() {
+        return Ok(FfmpegSettings::default());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+pub fn save_settings(settings: &FfmpegSettings) -> anyhow::Result<()> {
+    let path = settings_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(settings)?)?;
+    refresh();
+    Ok(())
+}
 
 pub fn find_ffmpeg_path() -> Option<PathBuf> {
-    FFMPEG_PATH.as_ref().map(|p| p.clone())
+    status().path.map(PathBuf::from)
+}
+
+// What was found and why, resolving on first use
+pub fn status() -> FfmpegStatus {
+    if let Ok(resolved) = RESOLVED.read() {
+        if let Some(status) = resolved.as_ref() {
+            return status.clone();
+        }
+    }
+    let settings = load_settings().unwrap_or_else(|e| {
+        warn!("Failed to load ffmpeg settings, using defaults: {}", e);
+        FfmpegSettings::default()
+    });
+    let status = FfmpegProvider::new(settings).resolve();
+    if let Ok(mut resolved) = RESOLVED.write() {
+        *resolved = Some(status.clone());
+    }
+    status
 }
 
-fn find_ffmpeg_path_internal() -> Option<PathBuf> {
-    debug!("Starting search for ffmpeg executable");
+// Forgets the resolved binary so the next lookup searches again
+pub fn refresh() {
+    if let Ok(mut resolved) = RESOLVED.write() {
+        *resolved = None;
+    }
+}
 
-    // Check if `ffmpeg` is in the PATH environment variable
-    if let Ok(path) = which(EXECUTABLE_NAME) {
-        debug!("Found ffmpeg in PATH: {:?}", path);
-        return Some(path);
+// Finds a usable ffmpeg in a fixed order: the configured path, a binary bundled with
+// the app, the sidecar directory, PATH, then ~/.local/bin on macOS. It never downloads
+// anything or touches shell configuration files; without ffmpeg, recordings fall back
+// to the built-in WAV and FLAC writers.
+pub struct FfmpegProvider {
+    settings: FfmpegSettings,
+}
+
+impl FfmpegProvider {
+    pub fn new(settings: FfmpegSettings) -> Self {
+        Self { settings }
     }
-    debug!("ffmpeg not found in PATH");
 
-    // Check in $HOME/.local/bin on macOS
-    #[cfg(target_os = "macos")]
-    {
-        if let Ok(home) = std::env::var("HOME") {
-            let local_bin = PathBuf::from(home).join(".local").join("bin");
-            debug!("Checking $HOME/.local/bin: {:?}", local_bin);
-            let ffmpeg_in_local_bin = local_bin.join(EXECUTABLE_NAME);
-            if ffmpeg_in_local_bin.exists() {
-                debug!("Found ffmpeg in $HOME/.local/bin: {:?}", ffmpeg_in_local_bin);
-                return Some(ffmpeg_in_local_bin);
-            }
-            debug!("ffmpeg not found in $HOME/.local/bin");
+    fn candidates(&self) -> Vec<(FfmpegSource, PathBuf)> {
+        let mut candidates = Vec::new();
+        if let Some(path) = self.settings.path.as_deref().filter(|p| !p.trim().is_empty()) {
+            candidates.push((FfmpegSource::Configured, PathBuf::from(path)));
+        }
+        if let Some(exe_folder) = std::env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+            candidates.push((FfmpegSource::Bundled, exe_folder.join(EXECUTABLE_NAME)));
+            #[cfg(target_os = "macos")]
+            candidates.push((FfmpegSource::Bundled, exe_folder.join("../Resources").join(EXECUTABLE_NAME)));
+            #[cfg(target_os = "linux")]
+            candidates.push((FfmpegSource::Bundled, exe_folder.join("lib").join(EXECUTABLE_NAME)));
+        }
+        if let Ok(dir) = sidecar_dir() {
+            candidates.push((FfmpegSource::Sidecar, dir.join(EXECUTABLE_NAME)));
+        }
+        if let Ok(path) = which(EXECUTABLE_NAME) {
+            candidates.push((FfmpegSource::SystemPath, path));
         }
+        // Where earlier versions of the app installed it on macOS
+        #[cfg(target_os = "macos")]
+        if let Some(home) = dirs::home_dir() {
+            candidates.push((FfmpegSource::LocalBin, home.join(".local").join("bin").join(EXECUTABLE_NAME)));
+        }
+        candidates
     }
 
-    // Check in current working directory
-    if let Ok(cwd) = std::env::current_dir() {
-        debug!("Current working directory: {:?}", cwd);
-        let ffmpeg_in_cwd = cwd.join(EXECUTABLE_NAME);
-        if ffmpeg_in_cwd.is_file() && ffmpeg_in_cwd.exists() {
-            debug!(
-                "Found ffmpeg in current working directory: {:?}",
-                ffmpeg_in_cwd
-            );
-            return Some(ffmpeg_in_cwd);
+    // Checksums are enforced for binaries the app ships or keeps in its sidecar
+    // directory; a configured path or one on PATH is the user's own choice
+    fn verify(&self, source: FfmpegSource, path: &Path) -> Result<Verified, String> {
+        if !path.is_file() {
+            return Err("not found".to_string());
         }
-        debug!("ffmpeg not found in current working directory");
-    }
-
-    // Check in the same folder as the executable
-    if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(exe_folder) = exe_path.parent() {
-            debug!("Executable folder: {:?}", exe_folder);
-            let ffmpeg_in_exe_folder = exe_folder.join(EXECUTABLE_NAME);
-            if ffmpeg_in_exe_folder.exists() {
-                debug!(
-                    "Found ffmpeg in executable folder: {:?}",
-                    ffmpeg_in_exe_folder
-                );
-                return Some(ffmpeg_in_exe_folder);
-            }
-            debug!("ffmpeg not found in executable folder");
+        let checksum_verified = self.check_checksum(source, path)?;
 
-            // Platform-specific checks
-            #[cfg(target_os = "macos")]
-            {
-                let resources_folder = exe_folder.join("../Resources");
-                debug!("Resources folder: {:?}", resources_folder);
-                let ffmpeg_in_resources = resources_folder.join(EXECUTABLE_NAME);
-                if ffmpeg_in_resources.exists() {
-                    debug!(
-                        "Found ffmpeg in Resources folder: {:?}",
-                        ffmpeg_in_resources
-                    );
-                    return Some(ffmpeg_in_resources);
-                }
-                debug!("ffmpeg not found in Resources folder");
+        let version = probe_version(path)?;
+        match parse_version(&version) {
+            Some(found) if found < MIN_VERSION => {
+                return Err(format!(
+                    "version {} is older than {}.{}",
+                    version, MIN_VERSION.0, MIN_VERSION.1
+                ));
             }
+            Some(_) => {}
+            // Git builds report a revision instead of a release number
+            None => warn!("Could not parse ffmpeg version {:?}, assuming it is recent", version),
+        }
 
-            #[cfg(target_os = "linux")]
-            {
-                let lib_folder = exe_folder.join("lib");
-                debug!("Lib folder: {:?}", lib_folder);
-                let ffmpeg_in_lib = lib_folder.join(EXECUTABLE_NAME);
-                if ffmpeg_in_lib.exists() {
-                    debug!("Found ffmpeg in lib folder: {:?}", ffmpeg_in_lib);
-                    return Some(ffmpeg_in_lib);
+        let encoders = probe_encoders(path)?;
+        if let Some(missing) = REQUIRED_ENCODERS.iter().find(|e| !encoders.iter().any(|found| found == *e)) {
+            return Err(format!("missing the {} encoder", missing));
+        }
+        Ok(Verified {
+            version,
+            checksum_verified,
+            encoders,
+        })
+    }
+
+    fn check_checksum(&self, source: FfmpegSource, path: &Path) -> Result<bool, String> {
+        match source {
+            FfmpegSource::Bundled | FfmpegSource::Sidecar => match verify_checksum(path)? {
+                true => Ok(true),
+                // Earlier versions unpacked ffmpeg here without a checksum; record one now
+                // so a later swap is caught, rather than dropping a binary that worked
+                false if source == FfmpegSource::Sidecar => {
+                    record_checksum(path)?;
+                    Ok(false)
+                }
+                false if self.settings.require_checksum => {
+                    Err("no checksum file and checksums are required".to_string())
                 }
-                debug!("ffmpeg not found in lib folder");
+                false => Ok(false),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    pub fn resolve(&self) -> FfmpegStatus {
+        let mut status = FfmpegStatus::default();
+        for (source, path) in self.candidates() {
+            if self.accept(&mut status, source, &path) {
+                return status;
             }
         }
+        info!("No usable ffmpeg found");
+        status
     }
 
-    debug!("ffmpeg not found. installing...");
+    fn accept(&self, status: &mut FfmpegStatus, source: FfmpegSource, path: &Path) -> bool {
+        let verified = self.verify(source, path);
+        debug!("ffmpeg candidate {:?} {:?}: {:?}", source, path, verified.as_ref().err());
+        status.candidates.push(CandidateReport {
+            source,
+            path: path.to_string_lossy().into_owned(),
+            outcome: match &verified {
+                Ok(_) => "used".to_string(),
+                Err(reason) => reason.clone(),
+            },
+        });
+        let Ok(verified) = verified else {
+            return false;
+        };
 
-    if let Err(error) = handle_ffmpeg_installation() {
-        error!("failed to install ffmpeg: {}", error);
-        return None;
+        info!("Using ffmpeg {} from {:?} ({:?})", verified.version, path, source);
+        status.supported_formats = supported_formats(&verified.encoders);
+        status.path = Some(path.to_string_lossy().into_owned());
+        status.source = Some(source);
+        status.version = Some(verified.version);
+        status.checksum_verified = verified.checksum_verified;
+        status.encoders = verified.encoders;
+        true
     }
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(CHECKSUM_SUFFIX);
+    path.with_file_name(name)
+}
 
-    if let Ok(path) = which(EXECUTABLE_NAME) {
-        debug!("found ffmpeg after installation: {:?}", path);
-        return Some(path);
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
     }
+    Ok(context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect())
+}
 
-    let installation_dir = sidecar_dir().map_err(|e| e.to_string()).unwrap();
-    let ffmpeg_in_installation = installation_dir.join(EXECUTABLE_NAME);
-    if ffmpeg_in_installation.is_file() {
-        debug!("found ffmpeg in directory: {:?}", ffmpeg_in_installation);
-        return Some(ffmpeg_in_installation);
+// Ok(false) when there is no checksum file; Err when there is one and it does not match
+fn verify_checksum(path: &Path) -> Result<bool, String> {
+    let Ok(expected) = std::fs::read_to_string(checksum_path(path)) else {
+        return Ok(false);
+    };
+    // Accepts both a bare hash and `sha256sum` output
+    let expected = expected.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+    let actual = sha256_file(path).map_err(|e| format!("could not hash binary: {}", e))?;
+    if expected != actual {
+        return Err(format!("checksum mismatch: expected {}, got {}", expected, actual));
     }
+    Ok(true)
+}
 
-    error!("ffmpeg not found even after installation");
-    None // Return None if ffmpeg is not found
+fn record_checksum(path: &Path) -> Result<(), String> {
+    let hash = sha256_file(path).map_err(|e| format!("could not hash binary: {}", e))?;
+    std::fs::write(checksum_path(path), format!("{}\n", hash))
+        .map_err(|e| format!("could not record checksum: {}", e))?;
+    info!("Recorded checksum of {:?}", path);
+    Ok(())
 }
 
-fn handle_ffmpeg_installation() -> Result<(), anyhow::Error> {
-    if ffmpeg_is_installed() {
-        debug!("ffmpeg is already installed");
-        return Ok(());
+fn run(path: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(path)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("`{}` exited with {}", args.join(" "), output.status));
     }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
 
-    debug!("ffmpeg not found. installing...");
-    match check_latest_version() {
-        Ok(version) => debug!("latest version: {}", version),
-        Err(e) => debug!("skipping version check due to error: {e}"),
-    }
+fn probe_version(path: &Path) -> Result<String, String> {
+    let output = run(path, &["-hide_banner", "-version"])?;
+    output
+        .split_whitespace()
+        .skip_while(|word| *word != "version")
+        .nth(1)
+        .map(str::to_string)
+        .ok_or_else(|| "could not read the version".to_string())
+}
 
-    let download_url = ffmpeg_download_url()?;
-    let destination = get_ffmpeg_install_dir()?;
+// "6.1.1", "n6.0" or "4.4.2-0ubuntu0.22.04.1" -> (major, minor)
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim_start_matches('n').split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
 
-    debug!("downloading from: {:?}", download_url);
-    let archive_path = download_ffmpeg_package(download_url, &destination)?;
-    debug!("downloaded package: {:?}", archive_path);
+// Lines look like " A....D aac                  AAC (Advanced Audio Coding)"; the
+// listing starts after the " ------" separator
+fn probe_encoders(path: &Path) -> Result<Vec<String>, String> {
+    let output = run(path, &["-hide_banner", "-encoders"])?;
+    Ok(output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("------"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.next()?;
+            let name = fields.next()?;
+            flags.starts_with('A').then(|| name.to_string())
+        })
+        .collect())
+}
 
-    debug!("extracting...");
-    unpack_ffmpeg(&archive_path, &destination)?;
+fn supported_formats(encoders: &[String]) -> Vec<AudioFormat> {
+    let has = |name: &str| encoders.iter().any(|e| e == name);
+    [
+        (AudioFormat::AacMp4, "aac"),
+        (AudioFormat::OpusOgg, "libopus"),
+        (AudioFormat::OpusWebm, "libopus"),
+        (AudioFormat::Flac, "flac"),
+        (AudioFormat::Mp3, "libmp3lame"),
+        (AudioFormat::Wav, "pcm_s16le"),
+    ]
+    .into_iter()
+    .filter(|(_, encoder)| has(encoder))
+    .map(|(format, _)| format)
+    .collect()
+}
 
-    let version = ffmpeg_version()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    debug!("done! installed ffmpeg version {}", version);
-    Ok(())
-}
+    fn test_file(name: &str, contents: &[u8]) -> PathBuf {
+        let dir = journal::app_data_dir().join("ffmpeg");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
 
-#[cfg(target_os = "macos")]
-fn get_ffmpeg_install_dir() -> Result<PathBuf, anyhow::Error> {
-    let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("couldn't find home directory"))?;
-
-    let local_bin = home.join(".local").join("bin");
-
-    // Create directory if it doesn't exist
-    if !local_bin.exists() {
-        debug!("creating .local/bin directory");
-        std::fs::create_dir_all(&local_bin)?;
-
-        // Check both .bashrc and .zshrc
-        let shell_configs = vec![
-            home.join(".bashrc"),
-            home.join(".bash_profile"), // macOS often uses .bash_profile instead of .bashrc
-            home.join(".zshrc"),
-        ];
-
-        for config in shell_configs {
-            if config.exists() {
-                let content = std::fs::read_to_string(&config)?;
-                if !content.contains(".local/bin") {
-                    debug!("adding .local/bin to PATH in {:?}", config);
-                    std::fs::write(
-                        config,
-                        format!("{}\nexport PATH=\"$HOME/.local/bin:$PATH\"\n", content),
-                    )?;
-                }
-            }
-        }
+    #[test]
+    fn checksums_are_required_by_default() {
+        assert!(FfmpegSettings::default().require_checksum);
+        // Settings written by versions that could download ffmpeg still load
+        let settings: FfmpegSettings = serde_json::from_str(r#"{"allow_download": true}"#).unwrap();
+        assert!(settings.require_checksum);
     }
 
-    Ok(local_bin)
-}
+    #[test]
+    fn checksum_files_are_checked() {
+        let binary = test_file("checked-ffmpeg", b"ffmpeg");
+        assert_eq!(verify_checksum(&binary), Ok(false));
+        // `sha256sum` output, in the upper case some release pages use
+        std::fs::write(
+            checksum_path(&binary),
+            "6862FA01D6F0BC4C9601C1A0A9D170CB49CF255B25BFC66A02F958FA47BE43A2  ffmpeg\n",
+        )
+        .unwrap();
+        assert_eq!(verify_checksum(&binary), Ok(true));
+
+        std::fs::write(&binary, b"replaced").unwrap();
+        let error = verify_checksum(&binary).unwrap_err();
+        assert!(error.contains("checksum mismatch"), "{}", error);
+    }
 
-// For other platforms, keep your existing installation directory logic
-#[cfg(not(target_os = "macos"))]
-fn get_ffmpeg_install_dir() -> Result<PathBuf, anyhow::Error> {
-    // Your existing logic for other platforms
-    sidecar_dir().map_err(|e| anyhow::anyhow!(e))
+    #[test]
+    fn bundled_binaries_need_a_checksum_file() {
+        let binary = test_file("bundled-ffmpeg", b"bundled");
+        let strict = FfmpegProvider::new(FfmpegSettings::default());
+        assert!(strict.check_checksum(FfmpegSource::Bundled, &binary).is_err());
+        let lenient = FfmpegProvider::new(FfmpegSettings {
+            require_checksum: false,
+            ..FfmpegSettings::default()
+        });
+        assert_eq!(lenient.check_checksum(FfmpegSource::Bundled, &binary), Ok(false));
+        assert!(!checksum_path(&binary).exists());
+        // Binaries the user picked are never hashed
+        assert_eq!(strict.check_checksum(FfmpegSource::SystemPath, &binary), Ok(false));
+    }
+
+    #[test]
+    fn sidecar_checksum_is_recorded_on_first_use() {
+        let binary = test_file("sidecar-ffmpeg", b"unpacked by an earlier version");
+        let provider = FfmpegProvider::new(FfmpegSettings::default());
+        assert_eq!(provider.check_checksum(FfmpegSource::Sidecar, &binary), Ok(false));
+        assert!(checksum_path(&binary).exists());
+        assert_eq!(provider.check_checksum(FfmpegSource::Sidecar, &binary), Ok(true));
+
+        std::fs::write(&binary, b"swapped").unwrap();
+        assert!(provider.check_checksum(FfmpegSource::Sidecar, &binary).is_err());
+    }
 }
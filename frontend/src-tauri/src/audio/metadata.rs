@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::Serialize;
+
+use super::decode::probe_duration;
+use super::ffmpeg::find_ffmpeg_path;
+use super::profile::AudioFormat;
+
+const CHAPTER_TITLE_CHARS: usize = 80;
+// Given to the last chapter when the duration of the file is unknown
+const FALLBACK_CHAPTER_SECS: f32 = 60.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Chapter {
+    // Seconds from the start of the recording
+    pub start: f32,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingMetadata {
+    pub title: String,
+    pub date: Option<DateTime<Utc>>,
+    pub participants: Vec<String>,
+    pub app_name: String,
+    pub app_version: String,
+    pub chapters: Vec<Chapter>,
+}
+
+// ffmetadata reserves `=`, `;`, `#`, `\` and newlines
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn chapter_title(title: &str) -> String {
+    let title = title.trim();
+    if title.chars().count() <= CHAPTER_TITLE_CHARS {
+        return title.to_string();
+    }
+    let cut: String = title.chars().take(CHAPTER_TITLE_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+impl RecordingMetadata {
+    // Renders an ffmetadata file; each chapter runs until the next one starts
+    pub fn to_ffmetadata(&self, duration_secs: Option<f32>) -> String {
+        let mut out = String::from(";FFMETADATA1\n");
+        out.push_str(&format!("title={}\n", escape(&self.title)));
+        if let Some(date) = self.date {
+            out.push_str(&format!("date={}\n", date.format("%Y-%m-%d")));
+            out.push_str(&format!("creation_time={}\n", date.to_rfc3339()));
+        }
+        if !self.participants.is_empty() {
+            let participants = escape(&self.participants.join(", "));
+            out.push_str(&format!("artist={}\n", participants));
+            out.push_str(&format!("participants={}\n", participants));
+        }
+        out.push_str(&format!("encoded_by={}\n", escape(&format!("{} {}", self.app_name, self.app_version))));
+
+        let mut chapters: Vec<&Chapter> = self.chapters.iter().filter(|c| c.start >= 0.0).collect();
+        chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+        chapters.dedup_by(|a, b| (a.start * 1000.0) as u64 == (b.start * 1000.0) as u64);
+        for (index, chapter) in chapters.iter().enumerate() {
+            let end = match chapters.get(index + 1) {
+                Some(next) => next.start,
+                None => duration_secs
+                    .filter(|duration| *duration > chapter.start)
+                    .unwrap_or(chapter.start + FALLBACK_CHAPTER_SECS),
+            };
+            out.push_str("\n[CHAPTER]\nTIMEBASE=1/1000\n");
+            out.push_str(&format!("START={}\n", (chapter.start * 1000.0) as u64));
+            out.push_str(&format!("END={}\n", (end * 1000.0) as u64));
+            out.push_str(&format!("title={}\n", escape(&chapter_title(&chapter.title))));
+        }
+        out
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("recording");
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+// Rewrites `path` with the tags and chapters in `metadata`. The streams are copied, not
+// re-encoded; the file is replaced only once ffmpeg has succeeded.
+pub fn embed(path: &Path, metadata: &RecordingMetadata) -> Result<()> {
+    let ffmpeg = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg is needed to embed metadata"))?;
+    let input = path.to_str().ok_or_else(|| anyhow!("Path is not valid UTF-8: {:?}", path))?;
+    let duration = probe_duration(&ffmpeg, input).map(|d| d as f32);
+
+    let metadata_path = sibling(path, "ffmetadata");
+    // Same extension as the input so ffmpeg picks the same container
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let output_path = sibling(path, &format!("tagged.{}", extension));
+    std::fs::write(&metadata_path, metadata.to_ffmetadata(duration))?;
+
+    let mut command = Command::new(&ffmpeg);
+    command
+        .args(["-y", "-nostdin", "-hide_banner", "-i", input, "-f", "ffmetadata", "-i"])
+        .arg(&metadata_path)
+        .args(["-map", "0", "-map_metadata", "1", "-map_chapters", "1", "-c", "copy"]);
+    if AudioFormat::from_extension(extension) == Some(AudioFormat::AacMp4) {
+        // Keeps custom keys such as participants, and moves the index to the front for sharing
+        command.args(["-movflags", "+faststart+use_metadata_tags"]);
+    }
+    command.arg(&output_path).stdin(Stdio::null()).stdout(Stdio::null());
+    debug!("Metadata FFmpeg command: {:?}", command);
+
+    let result = command.output();
+    let _ = std::fs::remove_file(&metadata_path);
+    let output = result?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&output_path);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.lines().rev().take(5).collect();
+        return Err(anyhow!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        ));
+    }
+
+    std::fs::rename(&output_path, path)?;
+    info!("Embedded metadata and {} chapter(s) in {:?}", metadata.chapters.len(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metadata(chapters: &[(f32, &str)]) -> RecordingMetadata {
+        RecordingMetadata {
+            title: "Plan; a=b #1 \\ next\nline".to_string(),
+            date: Some(Utc.with_ymd_and_hms(2024, 5, 2, 9, 30, 0).unwrap()),
+            participants: vec!["Ana".to_string(), "Bob=B".to_string()],
+            app_name: "Meetily".to_string(),
+            app_version: "0.0.4".to_string(),
+            chapters: chapters
+                .iter()
+                .map(|(start, title)| Chapter {
+                    start: *start,
+                    title: title.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    // (START, END, title) of every chapter block
+    fn chapters(rendered: &str) -> Vec<(u64, u64, String)> {
+        rendered
+            .split("\n[CHAPTER]\n")
+            .skip(1)
+            .map(|block| {
+                let value = |key: &str| {
+                    block
+                        .lines()
+                        .find_map(|line| line.strip_prefix(key))
+                        .unwrap()
+                        .to_string()
+                };
+                (value("START=").parse().unwrap(), value("END=").parse().unwrap(), value("title="))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reserved_characters_are_escaped() {
+        assert_eq!(escape("a=b;c#d\\e\nf"), "a\\=b\\;c\\#d\\\\e\\\nf");
+        let rendered = metadata(&[]).to_ffmetadata(None);
+        assert_eq!(
+            rendered,
+            ";FFMETADATA1\n\
+             title=Plan\\; a\\=b \\#1 \\\\ next\\\nline\n\
+             date=2024-05-02\n\
+             creation_time=2024-05-02T09:30:00+00:00\n\
+             artist=Ana, Bob\\=B\n\
+             participants=Ana, Bob\\=B\n\
+             encoded_by=Meetily 0.0.4\n"
+        );
+    }
+
+    #[test]
+    fn chapters_are_sorted_and_deduplicated() {
+        let rendered = metadata(&[
+            (30.0, "Third"),
+            (-1.0, "Before the start"),
+            (5.0, "First"),
+            (5.0004, "Same time"),
+            (12.5, "Second"),
+        ])
+        .to_ffmetadata(Some(90.0));
+        assert_eq!(
+            chapters(&rendered),
+            vec![
+                (5000, 12500, "First".to_string()),
+                (12500, 30000, "Second".to_string()),
+                // The last chapter runs to the end of the file
+                (30000, 90000, "Third".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_chapter_falls_back_without_a_usable_duration() {
+        for duration in [None, Some(20.0), Some(0.0)] {
+            let rendered = metadata(&[(20.0, "Only")]).to_ffmetadata(duration);
+            assert_eq!(chapters(&rendered), vec![(20000, 80000, "Only".to_string())], "{:?}", duration);
+        }
+    }
+
+    #[test]
+    fn long_chapter_titles_are_shortened() {
+        let long = format!("  {}  ", "word ".repeat(40));
+        let title = chapter_title(&long);
+        assert_eq!(title.chars().count(), CHAPTER_TITLE_CHARS);
+        assert!(title.ends_with("word…"), "{}", title);
+        assert_eq!(chapter_title(" Short = fine "), "Short = fine");
+        let rendered = metadata(&[(0.0, "a=b")]).to_ffmetadata(Some(10.0));
+        assert_eq!(chapters(&rendered), vec![(0, 10000, "a\\=b".to_string())]);
+    }
+}
@@ -1,8 +1,11 @@
 // src/audio/mod.rs
 pub mod core;
 pub mod audio_processing;
+pub mod decode;
 pub mod encode;
 pub mod ffmpeg;
+pub mod metadata;
+pub mod profile;
 
 pub use core::{
     default_input_device, default_output_device, get_device_and_config, list_audio_devices,
@@ -11,5 +14,6 @@ pub use core::{
     LAST_AUDIO_CAPTURE,
 };
 pub use encode::{
-    encode_single_audio, AudioInput
-};
\ No newline at end of file
+    available_profile, encode_audio, encode_single_audio, AudioInput, EncodeError, FinishedRecording, PushOutcome, StreamingEncoder
+};
+pub use profile::{AudioFormat, AudioTrack, EncodingProfile, TrackLayout};
\ No newline at end of file
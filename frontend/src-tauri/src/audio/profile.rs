@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::journal;
+
+const PROFILE_FILE_NAME: &str = "encoding_profile.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    #[default]
+    AacMp4,
+    OpusOgg,
+    OpusWebm,
+    Flac,
+    Mp3,
+    Wav,
+}
+
+impl AudioFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::AacMp4 => "mp4",
+            AudioFormat::OpusOgg => "ogg",
+            AudioFormat::OpusWebm => "webm",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "mp4" | "m4a" | "aac" => Some(AudioFormat::AacMp4),
+            "ogg" | "opus" => Some(AudioFormat::OpusOgg),
+            "webm" => Some(AudioFormat::OpusWebm),
+            "flac" => Some(AudioFormat::Flac),
+            "mp3" => Some(AudioFormat::Mp3),
+            "wav" => Some(AudioFormat::Wav),
+            _ => None,
+        }
+    }
+
+    fn is_lossless(self) -> bool {
+        matches!(self, AudioFormat::Flac | AudioFormat::Wav)
+    }
+
+    fn holds_multiple_streams(self) -> bool {
+        matches!(self, AudioFormat::AacMp4 | AudioFormat::OpusOgg | AudioFormat::OpusWebm)
+    }
+}
+
+// How the microphone and system audio are laid out in a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackLayout {
+    // One channel with both sides mixed together
+    #[default]
+    Mixed,
+    // Stereo with the microphone on the left and system audio on the right
+    StereoSplit,
+    // Two mono audio streams in one container; falls back to a stereo split for formats
+    // that hold a single stream
+    MultiTrack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioTrack {
+    Mic,
+    System,
+}
+
+impl AudioTrack {
+    // Channel in a stereo split, or stream in a multi-track file
+    pub fn index(self) -> usize {
+        match self {
+            AudioTrack::Mic => 0,
+            AudioTrack::System => 1,
+        }
+    }
+}
+
+// How recordings and exported audio are encoded. Unset fields keep the input's own
+// sample rate and channel count, and the format's default bitrate.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncodingProfile {
+    pub format: AudioFormat,
+    // Ignored for FLAC and WAV
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub tracks: TrackLayout,
+}
+
+impl EncodingProfile {
+    // Picks the format from the file extension, falling back to AAC/MP4
+    pub fn for_path(path: &Path) -> Self {
+        let format = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(AudioFormat::from_extension)
+            .unwrap_or_default();
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.format.extension()
+    }
+
+    // `path` with its extension replaced by the one this profile writes
+    pub fn output_path(&self, path: &Path) -> PathBuf {
+        path.with_extension(self.extension())
+    }
+
+    fn bitrate_kbps(&self) -> Option<u32> {
+        if self.format.is_lossless() {
+            return None;
+        }
+        Some(self.bitrate_kbps.unwrap_or(match self.format {
+            AudioFormat::AacMp4 | AudioFormat::Mp3 => 64,
+            // Opus holds up better than AAC at the same rate
+            _ => 32,
+        }))
+    }
+
+    pub fn output_sample_rate(&self, input_rate: u32) -> u32 {
+        match (self.format, self.sample_rate) {
+            (_, Some(rate)) => rate,
+            // libopus only accepts 8, 12, 16, 24 and 48 kHz
+            (AudioFormat::OpusOgg | AudioFormat::OpusWebm, None) => 48000,
+            (_, None) => input_rate,
+        }
+    }
+
+    // The layout actually written, after falling back for single-stream formats
+    pub fn track_layout(&self) -> TrackLayout {
+        match self.tracks {
+            TrackLayout::MultiTrack if !self.format.holds_multiple_streams() => TrackLayout::StereoSplit,
+            layout => layout,
+        }
+    }
+
+    // Channels the encoder expects per frame: mic and system interleaved unless mixed
+    pub fn input_channels(&self) -> u16 {
+        match self.tracks {
+            TrackLayout::Mixed => 1,
+            TrackLayout::StereoSplit | TrackLayout::MultiTrack => 2,
+        }
+    }
+
+    // Channels per output stream; the channel setting only applies to mixed recordings
+    pub fn output_channels(&self, input_channels: u16) -> u16 {
+        match self.track_layout() {
+            TrackLayout::Mixed => self.channels.unwrap_or(input_channels).max(1),
+            TrackLayout::StereoSplit => 2,
+            TrackLayout::MultiTrack => 1,
+        }
+    }
+
+    // ffmpeg output options, everything after `-i`. `streaming` keeps MP4 playable
+    // up to the last fragment if the process dies before it finishes.
+    pub fn ffmpeg_output_args(&self, input_rate: u32, input_channels: u16, streaming: bool) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        let codec = match self.format {
+            AudioFormat::AacMp4 => "aac",
+            AudioFormat::OpusOgg | AudioFormat::OpusWebm => "libopus",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Mp3 => "libmp3lame",
+            AudioFormat::Wav => "pcm_s16le",
+        };
+        args.extend(["-c:a".to_string(), codec.to_string()]);
+        if let Some(bitrate) = self.bitrate_kbps() {
+            args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+        }
+        if self.track_layout() == TrackLayout::MultiTrack {
+            args.extend([
+                "-filter_complex".to_string(),
+                "[0:a]channelsplit=channel_layout=stereo[mic][system]".to_string(),
+                "-map".to_string(),
+                "[mic]".to_string(),
+                "-map".to_string(),
+                "[system]".to_string(),
+                "-metadata:s:a:0".to_string(),
+                "title=Microphone".to_string(),
+                "-metadata:s:a:1".to_string(),
+                "title=System audio".to_string(),
+            ]);
+        }
+        args.extend([
+            "-ar".to_string(),
+            self.output_sample_rate(input_rate).to_string(),
+            "-ac".to_string(),
+            self.output_channels(input_channels).to_string(),
+        ]);
+
+        let container = match self.format {
+            AudioFormat::AacMp4 => {
+                // AAC-LC for compatibility
+                args.extend(["-profile:a".to_string(), "aac_low".to_string(), "-movflags".to_string()]);
+                args.push(if streaming {
+                    "+frag_keyframe+empty_moov+default_base_moof".to_string()
+                } else {
+                    "+faststart".to_string()
+                });
+                "mp4"
+            }
+            AudioFormat::OpusOgg => "ogg",
+            AudioFormat::OpusWebm => "webm",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+        };
+        args.extend(["-f".to_string(), container.to_string()]);
+        args
+    }
+}
+
+fn profile_path() -> PathBuf {
+    journal::app_data_dir().join(PROFILE_FILE_NAME)
+}
+
+// The profile new recordings use unless one is given when recording starts
+pub fn load_default_profile() -> Result<EncodingProfile> {
+    let path = profile_path();
+    if !path.exists() {
+        return Ok(EncodingProfile::default());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+pub fn save_default_profile(profile: &EncodingProfile) -> Result<()> {
+    let path = profile_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(profile)?)?;
+    Ok(())
+}
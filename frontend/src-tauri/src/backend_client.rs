@@ -0,0 +1,718 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, Emitter, Runtime};
+
+use crate::journal;
+use crate::vault;
+
+pub const DEFAULT_BACKEND_URL: &str = "http://localhost:5167";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const SYNC_QUEUE_FILE_NAME: &str = "sync_queue.json";
+// How often the background task retries queued requests
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// Guards read-modify-write cycles on the queue file
+static QUEUE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+// Only one replay runs at a time so a request is never sent twice
+static FLUSH_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeetingListItem {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendTranscript {
+    pub id: String,
+    pub text: String,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeetingDetails {
+    pub id: String,
+    pub title: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub transcripts: Vec<BackendTranscript>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveTranscriptRequest {
+    pub meeting_title: String,
+    pub transcripts: Vec<BackendTranscript>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveTranscriptResponse {
+    pub status: String,
+    pub message: String,
+    pub meeting_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessTranscriptRequest {
+    pub text: String,
+    // Provider name, e.g. "ollama" or "claude"
+    pub model: String,
+    pub model_name: String,
+    pub meeting_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessTranscriptResponse {
+    pub message: String,
+    pub process_id: String,
+}
+
+// /get-summary answers 202 while processing and 400 on failure, always with this body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryStatus {
+    pub status: String,
+    #[serde(rename = "meetingName")]
+    pub meeting_name: Option<String>,
+    pub meeting_id: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    pub provider: String,
+    pub model: String,
+    #[serde(rename = "whisperModel")]
+    pub whisper_model: String,
+    #[serde(rename = "apiKey", default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageResponse {
+    message: String,
+}
+
+// A non-success response from the backend, with FastAPI's `detail` when present
+#[derive(Debug)]
+pub struct BackendStatusError {
+    pub status: u16,
+    pub detail: String,
+}
+
+impl fmt::Display for BackendStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backend responded with status {}: {}", self.status, self.detail)
+    }
+}
+
+impl std::error::Error for BackendStatusError {}
+
+// True when the backend could not be reached at all, as opposed to rejecting the request
+pub fn is_offline(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+// Server-side failures are worth retrying later; client errors never will succeed
+fn is_retryable(error: &anyhow::Error) -> bool {
+    is_offline(error)
+        || error
+            .downcast_ref::<BackendStatusError>()
+            .is_some_and(|e| e.status >= 500)
+}
+
+pub struct BackendClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl Default for BackendClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_BACKEND_URL)
+    }
+}
+
+impl BackendClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let detail = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v.get("detail").and_then(|d| d.as_str()).map(str::to_string))
+                .unwrap_or(body);
+            return Err(BackendStatusError { status: status.as_u16(), detail }.into());
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Self::parse(self.http.get(self.url(path)).send().await?).await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        Self::parse(self.http.post(self.url(path)).json(body).send().await?).await
+    }
+
+    pub async fn get_meetings(&self) -> Result<Vec<MeetingListItem>> {
+        self.get("/get-meetings").await
+    }
+
+    pub async fn get_meeting(&self, meeting_id: &str) -> Result<MeetingDetails> {
+        self.get(&format!("/get-meeting/{}", meeting_id)).await
+    }
+
+    pub async fn save_meeting_title(&self, meeting_id: &str, title: &str) -> Result<String> {
+        let body = serde_json::json!({ "meeting_id": meeting_id, "title": title });
+        Ok(self.post::<_, MessageResponse>("/save-meeting-title", &body).await?.message)
+    }
+
+    pub async fn delete_meeting(&self, meeting_id: &str) -> Result<String> {
+        let body = serde_json::json!({ "meeting_id": meeting_id });
+        Ok(self.post::<_, MessageResponse>("/delete-meeting", &body).await?.message)
+    }
+
+    pub async fn save_transcript(
+        &self,
+        request: &SaveTranscriptRequest,
+    ) -> Result<SaveTranscriptResponse> {
+        self.post("/save-transcript", request).await
+    }
+
+    pub async fn process_transcript(
+        &self,
+        request: &ProcessTranscriptRequest,
+    ) -> Result<ProcessTranscriptResponse> {
+        self.post("/process-transcript", request).await
+    }
+
+    pub async fn get_summary(&self, meeting_id: &str) -> Result<SummaryStatus> {
+        let response = self
+            .http
+            .get(self.url(&format!("/get-summary/{}", meeting_id)))
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        serde_json::from_str(&body).map_err(|_| {
+            BackendStatusError {
+                status: status.as_u16(),
+                detail: body,
+            }
+            .into()
+        })
+    }
+
+    pub async fn get_model_config(&self) -> Result<ModelConfig> {
+        self.get("/get-model-config").await
+    }
+
+    pub async fn save_model_config(&self, config: &ModelConfig) -> Result<()> {
+        self.post::<_, serde_json::Value>("/save-model-config", config).await?;
+        Ok(())
+    }
+
+    pub async fn get_api_key(&self, provider: &str) -> Result<Option<String>> {
+        let body = serde_json::json!({ "provider": provider });
+        self.post("/get-api-key", &body).await
+    }
+}
+
+// Requests that can wait for the backend to come back. Both refer to a local
+// session; the backend meeting id is only known once the transcript has been saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SyncRequest {
+    SaveTranscript {
+        request: SaveTranscriptRequest,
+    },
+    ProcessTranscript {
+        text: String,
+        model: String,
+        model_name: String,
+        chunk_size: Option<u32>,
+        overlap: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOperation {
+    pub id: u64,
+    pub session_id: String,
+    pub request: SyncRequest,
+    // Backend the request was submitted to; replayed there rather than to the default.
+    // Absent in queues written before it was recorded.
+    #[serde(default)]
+    pub backend_url: Option<String>,
+    pub enqueued_at: DateTime<Utc>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncQueue {
+    next_id: u64,
+    operations: Vec<QueuedOperation>,
+    // Local session id -> backend meeting id, learned from /save-transcript responses
+    #[serde(default)]
+    meeting_ids: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SyncOutcome {
+    Sent { meeting_id: String },
+    Queued { operation_id: u64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStatus {
+    pub backend_url: String,
+    pub reachable: bool,
+    pub pending: Vec<QueuedOperation>,
+}
+
+fn queue_path() -> PathBuf {
+    journal::app_data_dir().join(SYNC_QUEUE_FILE_NAME)
+}
+
+fn load_queue() -> Result<SyncQueue> {
+    let path = queue_path();
+    if !path.exists() {
+        return Ok(SyncQueue::default());
+    }
+    Ok(serde_json::from_slice(&vault::read(path)?)?)
+}
+
+fn save_queue(queue: &SyncQueue) -> Result<()> {
+    let path = queue_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    // Queued requests carry transcript text
+    vault::write(&tmp, &serde_json::to_vec_pretty(queue)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn with_queue<T>(change: impl FnOnce(&mut SyncQueue) -> T) -> Result<T> {
+    let _guard = QUEUE_LOCK.lock().map_err(|_| anyhow!("Sync queue lock poisoned"))?;
+    let mut queue = load_queue()?;
+    let result = change(&mut queue);
+    save_queue(&queue)?;
+    Ok(result)
+}
+
+fn enqueue(session_id: &str, backend_url: &str, request: SyncRequest) -> Result<u64> {
+    let id = with_queue(|queue| {
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.operations.push(QueuedOperation {
+            id,
+            session_id: session_id.to_string(),
+            request,
+            backend_url: Some(backend_url.to_string()),
+            enqueued_at: Utc::now(),
+            attempts: 0,
+            last_error: None,
+        });
+        id
+    })?;
+    info!("Queued backend request {} for session {}", id, session_id);
+    Ok(id)
+}
+
+pub fn pending_operations() -> Result<Vec<QueuedOperation>> {
+    let _guard = QUEUE_LOCK.lock().map_err(|_| anyhow!("Sync queue lock poisoned"))?;
+    Ok(load_queue()?.operations)
+}
+
+pub fn backend_meeting_id(session_id: &str) -> Result<Option<String>> {
+    let _guard = QUEUE_LOCK.lock().map_err(|_| anyhow!("Sync queue lock poisoned"))?;
+    Ok(load_queue()?.meeting_ids.get(session_id).cloned())
+}
+
+// The local session a backend meeting was saved from, if it was synced from this machine
+pub fn session_for_meeting(meeting_id: &str) -> Result<Option<String>> {
+    let _guard = QUEUE_LOCK.lock().map_err(|_| anyhow!("Sync queue lock poisoned"))?;
+    Ok(load_queue()?
+        .meeting_ids
+        .into_iter()
+        .find_map(|(session_id, id)| (id == meeting_id).then_some(session_id)))
+}
+
+fn has_pending(session_id: &str) -> Result<bool> {
+    Ok(pending_operations()?.iter().any(|op| op.session_id == session_id))
+}
+
+pub fn transcript_request(session_id: &str, meeting_title: &str) -> Result<SaveTranscriptRequest> {
+    let transcript = journal::load_transcript(session_id)?;
+    Ok(SaveTranscriptRequest {
+        meeting_title: meeting_title.to_string(),
+        transcripts: transcript
+            .lines
+            .iter()
+            .map(|line| BackendTranscript {
+                id: format!("{}-{}", session_id, line.sequence),
+                text: line.text.clone(),
+                timestamp: format!("{:.1} - {:.1}", line.start, line.end),
+            })
+            .collect(),
+    })
+}
+
+async fn send(client: &BackendClient, session_id: &str, request: &SyncRequest) -> Result<String> {
+    match request {
+        SyncRequest::SaveTranscript { request } => {
+            let meeting_id = client.save_transcript(request).await?.meeting_id;
+            with_queue(|queue| {
+                queue.meeting_ids.insert(session_id.to_string(), meeting_id.clone())
+            })?;
+            Ok(meeting_id)
+        }
+        SyncRequest::ProcessTranscript { text, model, model_name, chunk_size, overlap } => {
+            let meeting_id = backend_meeting_id(session_id)?
+                .ok_or_else(|| anyhow!("Session {} has not been saved to the backend", session_id))?;
+            let request = ProcessTranscriptRequest {
+                text: text.clone(),
+                model: model.clone(),
+                model_name: model_name.clone(),
+                meeting_id: meeting_id.clone(),
+                chunk_size: *chunk_size,
+                overlap: *overlap,
+            };
+            client.process_transcript(&request).await?;
+            Ok(meeting_id)
+        }
+    }
+}
+
+// Sends now when the backend is up and nothing for this session is waiting ahead of
+// it; otherwise appends to the queue so requests for a session keep their order
+pub async fn submit(client: &BackendClient, session_id: &str, request: SyncRequest) -> Result<SyncOutcome> {
+    if !has_pending(session_id)? {
+        match send(client, session_id, &request).await {
+            Ok(meeting_id) => return Ok(SyncOutcome::Sent { meeting_id }),
+            Err(e) if is_retryable(&e) => {
+                warn!("Backend unavailable, queueing request for session {}: {}", session_id, e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let operation_id = enqueue(session_id, client.base_url(), request)?;
+    Ok(SyncOutcome::Queued { operation_id })
+}
+
+// Replays queued operations oldest first, each to the backend it was submitted to, and
+// stops at the first one that can't be taken yet. `fallback` serves operations queued
+// without a URL. Returns how many were delivered.
+pub async fn flush_queue(fallback: &BackendClient) -> Result<usize> {
+    let _flush = FLUSH_LOCK.lock().await;
+    let mut delivered = 0;
+
+    loop {
+        let Some(operation) = pending_operations()?.into_iter().next() else {
+            break;
+        };
+
+        let stored;
+        let client = match &operation.backend_url {
+            Some(url) if url != fallback.base_url() => {
+                stored = BackendClient::new(url.as_str());
+                &stored
+            }
+            _ => fallback,
+        };
+        match send(client, &operation.session_id, &operation.request).await {
+            Ok(_) => {
+                with_queue(|queue| queue.operations.retain(|op| op.id != operation.id))?;
+                info!("Replayed backend request {} for session {}", operation.id, operation.session_id);
+                delivered += 1;
+            }
+            Err(e) if is_retryable(&e) => {
+                with_queue(|queue| {
+                    if let Some(op) = queue.operations.iter_mut().find(|op| op.id == operation.id) {
+                        op.attempts += 1;
+                        op.last_error = Some(e.to_string());
+                    }
+                })?;
+                return Ok(delivered);
+            }
+            Err(e) => {
+                // The backend rejected it outright; retrying would block the queue forever
+                warn!("Dropping backend request {} for session {}: {}", operation.id, operation.session_id, e);
+                with_queue(|queue| queue.operations.retain(|op| op.id != operation.id))?;
+            }
+        }
+    }
+
+    Ok(delivered)
+}
+
+// Started once from setup; retries the queue periodically while it has work. Each
+// operation carries its own backend URL, so the default only covers older entries.
+pub async fn run_sync_loop<R: Runtime>(app: AppHandle<R>) {
+    let client = BackendClient::default();
+    loop {
+        tokio::time::sleep(SYNC_INTERVAL).await;
+        match pending_operations() {
+            Ok(pending) if pending.is_empty() => continue,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to read sync queue: {}", e);
+                continue;
+            }
+        }
+        match flush_queue(&client).await {
+            Ok(0) => {}
+            Ok(delivered) => {
+                info!("Synced {} queued request(s) to the backend", delivered);
+                if let Ok(pending) = pending_operations() {
+                    if let Err(e) = app.emit("backend-sync-status", pending) {
+                        warn!("Failed to emit sync status: {}", e);
+                    }
+                }
+            }
+            Err(e) => warn!("Backend sync failed: {}", e),
+        }
+    }
+}
+
+pub(crate) fn client_for(backend_url: Option<String>) -> BackendClient {
+    backend_url.map(BackendClient::new).unwrap_or_default()
+}
+
+#[command]
+pub async fn sync_save_transcript(
+    session_id: String,
+    meeting_title: String,
+    backend_url: Option<String>,
+) -> Result<SyncOutcome, String> {
+    let request = transcript_request(&session_id, &meeting_title)
+        .map_err(|e| format!("Failed to load transcript {}: {}", session_id, e))?;
+    submit(&client_for(backend_url), &session_id, SyncRequest::SaveTranscript { request })
+        .await
+        .map_err(|e| format!("Failed to save transcript to backend: {}", e))
+}
+
+#[command]
+pub async fn sync_process_transcript(
+    session_id: String,
+    model: String,
+    model_name: String,
+    chunk_size: Option<u32>,
+    overlap: Option<u32>,
+    backend_url: Option<String>,
+) -> Result<SyncOutcome, String> {
+    let transcript = journal::load_transcript(&session_id)
+        .map_err(|e| format!("Failed to load transcript {}: {}", session_id, e))?;
+    let text = transcript
+        .lines
+        .iter()
+        .map(|line| line.text.trim())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let request = SyncRequest::ProcessTranscript { text, model, model_name, chunk_size, overlap };
+    submit(&client_for(backend_url), &session_id, request)
+        .await
+        .map_err(|e| format!("Failed to request summary from backend: {}", e))
+}
+
+#[command]
+pub async fn backend_sync_status(backend_url: Option<String>) -> Result<SyncStatus, String> {
+    let client = client_for(backend_url);
+    let pending =
+        pending_operations().map_err(|e| format!("Failed to read sync queue: {}", e))?;
+    let reachable = match client.get_meetings().await {
+        Ok(_) => true,
+        Err(e) => !is_offline(&e),
+    };
+    Ok(SyncStatus {
+        backend_url: client.base_url().to_string(),
+        reachable,
+        pending,
+    })
+}
+
+#[command]
+pub async fn flush_backend_queue(backend_url: Option<String>) -> Result<usize, String> {
+    flush_queue(&client_for(backend_url))
+        .await
+        .map_err(|e| format!("Failed to sync with backend: {}", e))
+}
+
+#[command]
+pub fn get_meeting_session(meeting_id: String) -> Result<Option<String>, String> {
+    session_for_meeting(&meeting_id).map_err(|e| format!("Failed to read sync queue: {}", e))
+}
+
+#[command]
+pub async fn get_backend_summary(
+    session_id: String,
+    backend_url: Option<String>,
+) -> Result<SummaryStatus, String> {
+    let meeting_id = backend_meeting_id(&session_id)
+        .map_err(|e| format!("Failed to read sync queue: {}", e))?
+        .ok_or_else(|| format!("Session {} has not been synced to the backend yet", session_id))?;
+    client_for(backend_url)
+        .get_summary(&meeting_id)
+        .await
+        .map_err(|e| format!("Failed to fetch summary: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+
+    // The queue file is shared, so tests that touch it run one at a time
+    static TEST_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+    // Answers every request with `body` and reports each request line it saw
+    fn serve(listener: TcpListener, body: &'static str) -> mpsc::Receiver<String> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; content_length]).unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+                let _ = tx.send(request_line.trim().to_string());
+            }
+        });
+        rx
+    }
+
+    // An address nothing listens on, so connecting is refused
+    fn closed_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    fn save_request() -> SyncRequest {
+        SyncRequest::SaveTranscript {
+            request: SaveTranscriptRequest {
+                meeting_title: "Standup".to_string(),
+                transcripts: Vec::new(),
+            },
+        }
+    }
+
+    const SAVED: &str = r#"{"status": "success", "message": "saved", "meeting_id": "meeting-1"}"#;
+
+    #[tokio::test]
+    async fn submit_sends_when_backend_is_up() {
+        let _lock = TEST_LOCK.lock().await;
+        with_queue(|queue| queue.operations.clear()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = BackendClient::new(format!("http://{}", listener.local_addr().unwrap()));
+        let requests = serve(listener, SAVED);
+
+        let outcome = submit(&client, "sync-up", save_request()).await.unwrap();
+        assert!(matches!(outcome, SyncOutcome::Sent { meeting_id } if meeting_id == "meeting-1"));
+        assert_eq!(requests.recv().unwrap(), "POST /save-transcript HTTP/1.1");
+        assert_eq!(backend_meeting_id("sync-up").unwrap().as_deref(), Some("meeting-1"));
+        assert!(pending_operations().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn submit_queues_when_connection_is_refused() {
+        let _lock = TEST_LOCK.lock().await;
+        with_queue(|queue| queue.operations.clear()).unwrap();
+        let client = BackendClient::new(format!("http://{}", closed_addr()));
+
+        let outcome = submit(&client, "sync-refused", save_request()).await.unwrap();
+        assert!(matches!(outcome, SyncOutcome::Queued { .. }));
+        let pending = pending_operations().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].session_id, "sync-refused");
+        assert_eq!(pending[0].backend_url.as_deref(), Some(client.base_url()));
+
+        // Nothing is delivered while the backend stays down
+        assert_eq!(flush_queue(&client).await.unwrap(), 0);
+        assert_eq!(pending_operations().unwrap()[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn flush_replays_to_the_recorded_backend_once_it_recovers() {
+        let _lock = TEST_LOCK.lock().await;
+        with_queue(|queue| queue.operations.clear()).unwrap();
+        let addr = closed_addr();
+        let client = BackendClient::new(format!("http://{}", addr));
+        submit(&client, "sync-recovered", save_request()).await.unwrap();
+
+        let requests = serve(TcpListener::bind(addr).unwrap(), SAVED);
+        // The default client points elsewhere; the queued URL must win
+        assert_eq!(flush_queue(&BackendClient::default()).await.unwrap(), 1);
+        assert_eq!(requests.recv().unwrap(), "POST /save-transcript HTTP/1.1");
+        assert!(pending_operations().unwrap().is_empty());
+        assert_eq!(backend_meeting_id("sync-recovered").unwrap().as_deref(), Some("meeting-1"));
+    }
+
+    #[test]
+    fn meetings_map_back_to_their_session() {
+        let _lock = TEST_LOCK.blocking_lock();
+        with_queue(|queue| queue.meeting_ids.insert("sync-player".to_string(), "meeting-player".to_string()))
+            .unwrap();
+        assert_eq!(session_for_meeting("meeting-player").unwrap().as_deref(), Some("sync-player"));
+        assert_eq!(session_for_meeting("meeting-unknown").unwrap(), None);
+    }
+}
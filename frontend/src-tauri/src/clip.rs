@@ -0,0 +1,446 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::decode::{MediaDecoder, TrackSelection};
+use crate::audio::{encode_audio, AudioFormat, AudioTrack, EncodingProfile, TrackLayout};
+use crate::journal;
+use crate::library::{self, MeetingSource};
+
+// Same weights the recording loop uses when it mixes the two sides
+const MIC_MIX_WEIGHT: f32 = 0.7;
+const SYSTEM_MIX_WEIGHT: f32 = 0.3;
+// Target for loudness normalization, measured as gated RMS rather than full EBU R128
+const TARGET_LOUDNESS_DB: f32 = -16.0;
+const PEAK_CEILING_DB: f32 = -1.0;
+// Blocks quieter than this are left out of the loudness measurement
+const SILENCE_GATE_DB: f32 = -50.0;
+const LOUDNESS_BLOCK_SECS: f32 = 0.4;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipOptions {
+    // One side of a recording made with separate tracks; both sides mixed when unset
+    pub track: Option<AudioTrack>,
+    // Transcript line sequences; the clip runs from the first to the last of them
+    pub lines: Vec<u64>,
+    pub fade_in_secs: f32,
+    pub fade_out_secs: f32,
+    pub normalize: bool,
+}
+
+// Resolves the clip range from explicit times or the selected transcript lines
+fn clip_range(session_id: &str, start: Option<f32>, end: Option<f32>, lines: &[u64]) -> Result<(f32, f32)> {
+    let (start, end) = if lines.is_empty() {
+        match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Err(anyhow!("A clip needs a start and end time or transcript lines")),
+        }
+    } else {
+        let wanted: HashSet<u64> = lines.iter().copied().collect();
+        let transcript = journal::load_transcript(session_id)?;
+        let selected: Vec<_> = transcript.lines.iter().filter(|line| wanted.contains(&line.sequence)).collect();
+        if selected.is_empty() {
+            return Err(anyhow!("None of the selected lines are in session {}", session_id));
+        }
+        (
+            selected.iter().map(|line| line.start).fold(f32::MAX, f32::min),
+            selected.iter().map(|line| line.end).fold(0.0, f32::max),
+        )
+    };
+    let start = start.max(0.0);
+    if end <= start {
+        return Err(anyhow!("Clip end {:.2}s is not after its start {:.2}s", end, start));
+    }
+    Ok((start, end))
+}
+
+// The recording segments in order, or the original file of an imported meeting
+pub(crate) fn session_files(session_id: &str) -> Result<(Vec<PathBuf>, TrackLayout)> {
+    let record = library::get(session_id)?;
+    if let MeetingSource::Import { path } = &record.source {
+        if Path::new(path).is_file() {
+            return Ok((vec![PathBuf::from(path)], TrackLayout::Mixed));
+        }
+    }
+    let files: Vec<PathBuf> = record.audio_files.iter().map(PathBuf::from).filter(|p| p.is_file()).collect();
+    if files.is_empty() {
+        return Err(anyhow!("Session {} has no audio file", session_id));
+    }
+    Ok((files, record.track_layout))
+}
+
+// Decodes `start..end` seconds across the segments, stopping once the range is covered.
+// Positions are counted in whole samples so a cut never drifts at segment boundaries.
+fn decode_range(files: &[PathBuf], selection: TrackSelection, start: f32, end: f32) -> Result<(Vec<f32>, u32)> {
+    let mut samples = Vec::new();
+    let mut sample_rate = None;
+    // Samples before the current block
+    let mut position = 0usize;
+    for path in files {
+        let mut decoder = MediaDecoder::open_track(path, selection)?;
+        let rate = decoder.sample_rate();
+        if *sample_rate.get_or_insert(rate) != rate {
+            return Err(anyhow!("Recording segments have different sample rates"));
+        }
+        let first = (start as f64 * rate as f64).round() as usize;
+        let last = (end as f64 * rate as f64).round() as usize;
+        while let Some(block) = decoder.next_block()? {
+            let block_start = position;
+            position += block.len();
+            if position <= first {
+                continue;
+            }
+            let from = first.saturating_sub(block_start);
+            let to = last.saturating_sub(block_start).min(block.len());
+            if from < to {
+                samples.extend_from_slice(&block[from..to]);
+            }
+            if position >= last {
+                return Ok((samples, rate));
+            }
+        }
+    }
+    let sample_rate = sample_rate.ok_or_else(|| anyhow!("No audio to clip"))?;
+    if samples.is_empty() {
+        return Err(anyhow!("Clip starts after the end of the recording"));
+    }
+    Ok((samples, sample_rate))
+}
+
+// Decodes `start..end` seconds as one mono signal; the two streams of a multi-track
+// recording are mixed, since a mixed decode would only see the first
+fn decode_mixed(files: &[PathBuf], layout: TrackLayout, start: f32, end: f32) -> Result<(Vec<f32>, u32)> {
+    if layout != TrackLayout::MultiTrack {
+        return decode_range(files, TrackSelection::Mixed, start, end);
+    }
+    let (mic, rate) = decode_range(files, TrackSelection::Stream(AudioTrack::Mic.index()), start, end)?;
+    let (system, _) = decode_range(files, TrackSelection::Stream(AudioTrack::System.index()), start, end)?;
+    let mixed = (0..mic.len().max(system.len()))
+        .map(|i| {
+            mic.get(i).copied().unwrap_or(0.0) * MIC_MIX_WEIGHT + system.get(i).copied().unwrap_or(0.0) * SYSTEM_MIX_WEIGHT
+        })
+        .collect();
+    Ok((mixed, rate))
+}
+
+// Reads one track selection across the recording segments in order, a block at a time
+struct SegmentReader {
+    files: Vec<PathBuf>,
+    next_file: usize,
+    selection: TrackSelection,
+    decoder: Option<MediaDecoder>,
+    sample_rate: u32,
+}
+
+impl SegmentReader {
+    fn open(files: &[PathBuf], selection: TrackSelection) -> Result<Self> {
+        let first = files.first().ok_or_else(|| anyhow!("No audio to read"))?;
+        let decoder = MediaDecoder::open_track(first, selection)?;
+        Ok(Self {
+            files: files.to_vec(),
+            next_file: 1,
+            selection,
+            sample_rate: decoder.sample_rate(),
+            decoder: Some(decoder),
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        loop {
+            if let Some(decoder) = self.decoder.as_mut() {
+                if let Some(block) = decoder.next_block()? {
+                    return Ok(Some(block));
+                }
+                self.decoder = None;
+            }
+            let Some(path) = self.files.get(self.next_file) else {
+                return Ok(None);
+            };
+            self.next_file += 1;
+            let decoder = MediaDecoder::open_track(path, self.selection)?;
+            if decoder.sample_rate() != self.sample_rate {
+                return Err(anyhow!("Recording segments have different sample rates"));
+            }
+            self.decoder = Some(decoder);
+        }
+    }
+}
+
+// The session audio as a stream of mono blocks, so a whole recording never has to be
+// held in memory; the sides of a multi-track recording are mixed as in `decode_mixed`
+pub(crate) struct SessionAudio {
+    mic: SegmentReader,
+    system: Option<SegmentReader>,
+    // Samples decoded from one side but not yet matched by the other
+    pending_mic: Vec<f32>,
+    pending_system: Vec<f32>,
+}
+
+impl SessionAudio {
+    pub(crate) fn open(files: &[PathBuf], layout: TrackLayout) -> Result<Self> {
+        if layout != TrackLayout::MultiTrack {
+            return Ok(Self {
+                mic: SegmentReader::open(files, TrackSelection::Mixed)?,
+                system: None,
+                pending_mic: Vec::new(),
+                pending_system: Vec::new(),
+            });
+        }
+        let mic = SegmentReader::open(files, TrackSelection::Stream(AudioTrack::Mic.index()))?;
+        let system = SegmentReader::open(files, TrackSelection::Stream(AudioTrack::System.index()))?;
+        if mic.sample_rate != system.sample_rate {
+            return Err(anyhow!("Recording tracks have different sample rates"));
+        }
+        Ok(Self {
+            mic,
+            system: Some(system),
+            pending_mic: Vec::new(),
+            pending_system: Vec::new(),
+        })
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.mic.sample_rate
+    }
+
+    pub(crate) fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        let Some(system) = self.system.as_mut() else {
+            return self.mic.next_block();
+        };
+        if self.pending_mic.is_empty() {
+            self.pending_mic = self.mic.next_block()?.unwrap_or_default();
+        }
+        if self.pending_system.is_empty() {
+            self.pending_system = system.next_block()?.unwrap_or_default();
+        }
+        // A side that is still empty has ended; the other plays on alone
+        let len = match (self.pending_mic.len(), self.pending_system.len()) {
+            (0, 0) => return Ok(None),
+            (0, len) | (len, 0) => len,
+            (mic, system) => mic.min(system),
+        };
+        let mixed = (0..len)
+            .map(|i| {
+                self.pending_mic.get(i).copied().unwrap_or(0.0) * MIC_MIX_WEIGHT
+                    + self.pending_system.get(i).copied().unwrap_or(0.0) * SYSTEM_MIX_WEIGHT
+            })
+            .collect();
+        self.pending_mic.drain(..len.min(self.pending_mic.len()));
+        self.pending_system.drain(..len.min(self.pending_system.len()));
+        Ok(Some(mixed))
+    }
+}
+
+fn apply_fades(samples: &mut [f32], sample_rate: u32, fade_in_secs: f32, fade_out_secs: f32) {
+    let half = samples.len() / 2;
+    let fade_in = ((fade_in_secs.max(0.0) * sample_rate as f32) as usize).min(half);
+    let fade_out = ((fade_out_secs.max(0.0) * sample_rate as f32) as usize).min(half);
+    for (i, sample) in samples[..fade_in].iter_mut().enumerate() {
+        *sample *= i as f32 / fade_in as f32;
+    }
+    for (i, sample) in samples.iter_mut().rev().take(fade_out).enumerate() {
+        *sample *= i as f32 / fade_out as f32;
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    20.0 * level.max(1e-9).log10()
+}
+
+// Scales the clip towards the target loudness without letting peaks pass the ceiling
+fn normalize(samples: &mut [f32], sample_rate: u32) {
+    let block = ((LOUDNESS_BLOCK_SECS * sample_rate as f32) as usize).max(1);
+    let mut energy = 0.0f64;
+    let mut counted = 0usize;
+    for chunk in samples.chunks(block) {
+        let sum: f64 = chunk.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+        if to_db((sum / chunk.len() as f64).sqrt() as f32) > SILENCE_GATE_DB {
+            energy += sum;
+            counted += chunk.len();
+        }
+    }
+    if counted == 0 {
+        return;
+    }
+    let loudness = to_db((energy / counted as f64).sqrt() as f32);
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let gain_db = (TARGET_LOUDNESS_DB - loudness).min(PEAK_CEILING_DB - to_db(peak));
+    let gain = 10f32.powf(gain_db / 20.0);
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+    info!("Normalized clip by {:.1} dB", gain_db);
+}
+
+// Cuts part of a session's recording and encodes it to `path`. The extension follows
+// `format` when one is given, otherwise `path`'s own. Returns the path actually written.
+pub fn export_clip(
+    session_id: &str,
+    start: Option<f32>,
+    end: Option<f32>,
+    format: Option<AudioFormat>,
+    path: &Path,
+    options: &ClipOptions,
+) -> Result<PathBuf> {
+    let (start, end) = clip_range(session_id, start, end, &options.lines)?;
+    let (files, layout) = session_files(session_id)?;
+
+    let (mut samples, sample_rate) = match options.track {
+        Some(track) => decode_range(&files, TrackSelection::for_track(layout, track)?, start, end)?,
+        None => decode_mixed(&files, layout, start, end)?,
+    };
+
+    if options.normalize {
+        normalize(&mut samples, sample_rate);
+    }
+    apply_fades(&mut samples, sample_rate, options.fade_in_secs, options.fade_out_secs);
+
+    let profile = match format {
+        Some(format) => EncodingProfile {
+            format,
+            ..EncodingProfile::default()
+        },
+        None => EncodingProfile::for_path(path),
+    };
+    let path = profile.output_path(path);
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let written = encode_audio(bytemuck::cast_slice(&samples), sample_rate, 1, &path, &profile)?;
+    info!(
+        "Exported {:.1}s-{:.1}s of session {} to {:?}",
+        start, end, session_id, written
+    );
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::SessionJournal;
+
+    const RATE: u32 = 1000;
+
+    // A 16-bit WAV whose samples count up from `first`, so each value tells its position
+    fn ramp_wav(name: &str, first: i16, len: i16) -> PathBuf {
+        let dir = journal::app_data_dir().join("clip");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for value in first..first + len {
+            writer.write_sample(value).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn segments() -> Vec<PathBuf> {
+        vec![ramp_wav("recording.wav", 0, 1500), ramp_wav("recording.part2.wav", 1500, 1500)]
+    }
+
+    fn values(samples: &[f32]) -> Vec<i32> {
+        samples.iter().map(|s| (s * 32768.0).round() as i32).collect()
+    }
+
+    #[test]
+    fn range_comes_from_times_or_lines() {
+        assert_eq!(clip_range("clip-range", Some(-2.0), Some(4.0), &[]).unwrap(), (0.0, 4.0));
+        assert!(clip_range("clip-range", Some(1.0), None, &[]).is_err());
+        assert!(clip_range("clip-range", Some(4.0), Some(4.0), &[]).is_err());
+
+        let session_id = "clip-range";
+        let journal = SessionJournal::create(session_id).unwrap();
+        journal.append_line("one", 2.0, 5.0, "mic").unwrap();
+        journal.append_line("two", 5.0, 9.0, "mic").unwrap();
+        journal.append_line("three", 9.0, 12.0, "mic").unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+        // Lines win over explicit times and may be given in any order
+        assert_eq!(clip_range(session_id, Some(0.0), Some(1.0), &[2, 0]).unwrap(), (2.0, 12.0));
+        assert_eq!(clip_range(session_id, None, None, &[1, 99]).unwrap(), (5.0, 9.0));
+        assert!(clip_range(session_id, None, None, &[99]).is_err());
+    }
+
+    #[test]
+    fn range_is_cut_across_a_segment_boundary() {
+        let files = segments();
+        let (samples, rate) = decode_range(&files, TrackSelection::Mixed, 1.2, 1.8).unwrap();
+        assert_eq!(rate, RATE);
+        assert_eq!(values(&samples), (1200..1800).collect::<Vec<_>>());
+
+        // Entirely inside the second segment
+        let (samples, _) = decode_range(&files, TrackSelection::Mixed, 2.0, 2.25).unwrap();
+        assert_eq!(values(&samples), (2000..2250).collect::<Vec<_>>());
+        // Past the end it stops with what there is
+        let (samples, _) = decode_range(&files, TrackSelection::Mixed, 2.9, 10.0).unwrap();
+        assert_eq!(values(&samples), (2900..3000).collect::<Vec<_>>());
+        assert!(decode_range(&files, TrackSelection::Mixed, 3.5, 4.0).is_err());
+    }
+
+    #[test]
+    fn session_audio_streams_every_segment() {
+        let files = segments();
+        let mut audio = SessionAudio::open(&files, TrackLayout::Mixed).unwrap();
+        assert_eq!(audio.sample_rate(), RATE);
+        let mut samples = Vec::new();
+        while let Some(block) = audio.next_block().unwrap() {
+            samples.extend(block);
+        }
+        assert_eq!(values(&samples), (0..3000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn fades_ramp_the_ends_and_stay_within_half_the_clip() {
+        let mut samples = vec![1.0f32; 10];
+        apply_fades(&mut samples, 10, 0.4, 0.2);
+        assert_eq!(samples, vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 1.0, 0.5, 0.0]);
+
+        let mut samples = vec![1.0f32; 6];
+        apply_fades(&mut samples, 10, 5.0, 5.0);
+        let expected = [0.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 1.0 / 3.0, 0.0];
+        assert!(samples.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", samples);
+
+        let mut samples = vec![1.0f32; 4];
+        apply_fades(&mut samples, 10, 0.0, -1.0);
+        assert_eq!(samples, vec![1.0; 4]);
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        to_db((samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt())
+    }
+
+    #[test]
+    fn normalize_reaches_the_target_under_the_ceiling() {
+        let sine = |amplitude: f32| -> Vec<f32> {
+            (0..8000)
+                .map(|i| amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 8000.0).sin())
+                .collect()
+        };
+        let mut quiet = sine(0.01);
+        normalize(&mut quiet, 8000);
+        assert!((rms_db(&quiet) - TARGET_LOUDNESS_DB).abs() < 0.1, "{}", rms_db(&quiet));
+
+        // A loud click limits the gain so it stays below the ceiling
+        let mut spiky = sine(0.01);
+        spiky[100] = 0.5;
+        normalize(&mut spiky, 8000);
+        let peak = spiky.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((to_db(peak) - PEAK_CEILING_DB).abs() < 0.01, "{}", to_db(peak));
+
+        let mut silence = vec![0.0f32; 8000];
+        normalize(&mut silence, 8000);
+        assert!(silence.iter().all(|s| *s == 0.0));
+    }
+}
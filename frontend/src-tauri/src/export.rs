@@ -0,0 +1,482 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::extract::{self, Extraction, SourceRef};
+use crate::journal::{self, Bookmark, SessionTranscript, TranscriptLine};
+
+// Bumped whenever a field in `TranscriptDocument` changes meaning or is removed
+pub const TRANSCRIPT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Srt,
+    #[serde(alias = "webvtt")]
+    Vtt,
+    #[serde(alias = "md")]
+    Markdown,
+    #[serde(alias = "txt")]
+    Text,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "srt",
+            ExportFormat::Vtt => "vtt",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Text => "txt",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// JSON export layout (`schema_version` 1):
+///
+/// ```json
+/// {
+///   "schema_version": 1,
+///   "session_id": "20250101-093000-1a2b",
+///   "started_at": "2025-01-01T09:30:00Z",
+///   "stopped_at": "2025-01-01T10:05:12Z",
+///   "lines": [
+///     { "sequence": 0, "start": 0.0, "end": 4.2, "speaker": null, "source": "Mixed Audio", "text": "..." }
+///   ],
+///   "extraction": {
+///     "action_items": [{ "task": "...", "assignee": "Sam", "due": "next Friday",
+///                        "source": { "line_sequence": 12, "start": 81.0, "end": 85.5 } }],
+///     "decisions": [{ "text": "...", "source": null }],
+///     "open_questions": [{ "text": "...", "source": null }]
+///   }
+/// }
+/// ```
+///
+/// `start`/`end` are seconds from the start of the session; `speaker` is null when unlabeled.
+/// `extraction` is omitted until action items have been extracted for the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptDocument {
+    pub schema_version: u32,
+    pub session_id: String,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub lines: Vec<DocumentLine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extraction: Option<Extraction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentLine {
+    pub sequence: u64,
+    pub start: f32,
+    pub end: f32,
+    pub speaker: Option<String>,
+    pub source: String,
+    pub text: String,
+}
+
+impl TranscriptDocument {
+    pub fn new(transcript: &SessionTranscript, extraction: Option<&Extraction>) -> Self {
+        TranscriptDocument {
+            schema_version: TRANSCRIPT_SCHEMA_VERSION,
+            session_id: transcript.session_id.clone(),
+            started_at: transcript.started_at,
+            stopped_at: transcript.stopped_at,
+            lines: transcript
+                .lines
+                .iter()
+                .map(|line| DocumentLine {
+                    sequence: line.sequence,
+                    start: line.start,
+                    end: line.end,
+                    speaker: line.speaker.clone(),
+                    source: line.source.clone(),
+                    text: line.text.clone(),
+                })
+                .collect(),
+            extraction: extraction.cloned(),
+            bookmarks: transcript.bookmarks.clone(),
+        }
+    }
+}
+
+pub fn render(
+    transcript: &SessionTranscript,
+    extraction: Option<&Extraction>,
+    format: ExportFormat,
+) -> Result<String> {
+    Ok(match format {
+        ExportFormat::Srt => render_srt(&transcript.lines),
+        ExportFormat::Vtt => render_vtt(&transcript.lines),
+        ExportFormat::Markdown => render_markdown(transcript, extraction),
+        ExportFormat::Text => render_text(&transcript.lines),
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&TranscriptDocument::new(transcript, extraction))?
+        }
+    })
+}
+
+// Formats seconds as HH:MM:SS followed by `separator` and milliseconds
+fn format_timestamp(seconds: f32, separator: char) -> String {
+    let total_ms = (seconds.max(0.0) as f64 * 1000.0).round() as u64;
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms / 60_000) % 60;
+    let secs = (total_ms / 1000) % 60;
+    let millis = total_ms % 1000;
+    format!("{:02}:{:02}:{:02}{}{:03}", hours, minutes, secs, separator, millis)
+}
+
+// Bookmarks can be added without a label
+pub fn bookmark_label(bookmark: &Bookmark) -> &str {
+    match bookmark.label.trim() {
+        "" => "Bookmark",
+        label => label,
+    }
+}
+
+// HH:MM:SS, as used in Markdown, prompts and citations
+pub(crate) fn format_clock(seconds: f32) -> String {
+    let total = seconds.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", total / 3600, (total / 60) % 60, total % 60)
+}
+
+// Subtitle cues may not contain blank lines, which would end the cue early, or "-->",
+// which both SRT and WebVTT read as a timing line
+fn cue_text(line: &TranscriptLine) -> String {
+    let text = line
+        .text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = match &line.speaker {
+        Some(speaker) => format!("{}: {}", speaker, text),
+        None => text,
+    };
+    text.replace("-->", "->")
+}
+
+pub fn render_srt(lines: &[TranscriptLine]) -> String {
+    let mut out = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(line.start, ','),
+            format_timestamp(line.end.max(line.start), ','),
+            cue_text(line)
+        );
+    }
+    out
+}
+
+// WebVTT cue text is markup, so a literal `&` or `<` would start an entity or a tag
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn render_vtt(lines: &[TranscriptLine]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for line in lines {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            line.sequence,
+            format_timestamp(line.start, '.'),
+            format_timestamp(line.end.max(line.start), '.'),
+            escape_vtt(&cue_text(line))
+        );
+    }
+    out
+}
+
+pub fn render_text(lines: &[TranscriptLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        match &line.speaker {
+            Some(speaker) => {
+                let _ = writeln!(out, "[{}] {}: {}", format_clock(line.start), speaker, line.text);
+            }
+            None => {
+                let _ = writeln!(out, "[{}] {}", format_clock(line.start), line.text);
+            }
+        }
+    }
+    out
+}
+
+fn source_suffix(source: &Option<SourceRef>) -> String {
+    match source {
+        Some(source) => format!(" _({})_", format_clock(source.start)),
+        None => String::new(),
+    }
+}
+
+fn render_extraction(out: &mut String, extraction: &Extraction) {
+    if !extraction.action_items.is_empty() {
+        out.push_str("\n## Action Items\n\n");
+        for item in &extraction.action_items {
+            let mut details = Vec::new();
+            if let Some(assignee) = &item.assignee {
+                details.push(format!("owner: {}", assignee));
+            }
+            if let Some(due) = &item.due {
+                details.push(format!("due: {}", due));
+            }
+            let details = if details.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", details.join(", "))
+            };
+            let _ = writeln!(out, "- [ ] {}{}{}", item.task, details, source_suffix(&item.source));
+        }
+    }
+
+    for (heading, items) in [("Decisions", &extraction.decisions), ("Open Questions", &extraction.open_questions)] {
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n## {}\n", heading);
+        for item in items {
+            let _ = writeln!(out, "- {}{}", item.text, source_suffix(&item.source));
+        }
+    }
+}
+
+pub fn render_markdown(transcript: &SessionTranscript, extraction: Option<&Extraction>) -> String {
+    let mut out = String::from("# Meeting Minutes\n\n");
+
+    let _ = writeln!(out, "- **Session:** {}", transcript.session_id);
+    if let Some(started_at) = transcript.started_at {
+        let _ = writeln!(out, "- **Date:** {}", started_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    if let Some(last) = transcript.lines.last() {
+        let _ = writeln!(out, "- **Duration:** {}", format_clock(last.end));
+    }
+
+    let mut speakers: Vec<&str> = transcript
+        .lines
+        .iter()
+        .filter_map(|line| line.speaker.as_deref())
+        .collect();
+    speakers.sort_unstable();
+    speakers.dedup();
+    if !speakers.is_empty() {
+        let _ = writeln!(out, "- **Speakers:** {}", speakers.join(", "));
+    }
+
+    if let Some(extraction) = extraction {
+        render_extraction(&mut out, extraction);
+    }
+
+    if !transcript.bookmarks.is_empty() {
+        out.push_str("\n## Highlights\n\n");
+        for bookmark in &transcript.bookmarks {
+            let _ = writeln!(out, "- **[{}]** {}", format_clock(bookmark.offset), bookmark_label(bookmark));
+        }
+    }
+
+    out.push_str("\n## Transcript\n\n");
+
+    // Consecutive lines from the same speaker are grouped under one heading
+    let mut current_speaker: Option<&str> = None;
+    for line in &transcript.lines {
+        let speaker = line.speaker.as_deref();
+        if speaker.is_some() && speaker != current_speaker {
+            let _ = writeln!(out, "### {}\n", speaker.unwrap_or_default());
+            current_speaker = speaker;
+        }
+        let _ = writeln!(out, "**[{}]** {}\n", format_clock(line.start), line.text.trim());
+    }
+
+    out
+}
+
+pub fn export_session(session_id: &str, format: ExportFormat, path: &Path) -> Result<()> {
+    let transcript = journal::load_transcript(session_id)?;
+    let extraction = extract::load_extraction(session_id)?;
+    let rendered = render(&transcript, extraction.as_ref(), format)?;
+
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(path, rendered)?;
+
+    journal::mark_exported(session_id, &path.to_string_lossy())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn line(sequence: u64, start: f32, end: f32, speaker: Option<&str>, text: &str) -> TranscriptLine {
+        TranscriptLine {
+            sequence,
+            text: text.to_string(),
+            start,
+            end,
+            source: "mic".to_string(),
+            speaker: speaker.map(str::to_string),
+            recorded_at: Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn timestamps_round_to_the_next_hour() {
+        assert_eq!(format_timestamp(3599.9996, ','), "01:00:00,000");
+        assert_eq!(format_timestamp(3599.999, '.'), "00:59:59.999");
+        assert_eq!(format_timestamp(-1.0, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn srt_matches_golden_output() {
+        let lines = [
+            line(1, 0.0, 2.5, Some("Ana"), "Hello --> there"),
+            line(2, 3599.9996, 3601.25, None, "first line\n\nsecond line"),
+        ];
+        assert_eq!(
+            render_srt(&lines),
+            "1\n00:00:00,000 --> 00:00:02,500\nAna: Hello -> there\n\n\
+             2\n01:00:00,000 --> 01:00:01,250\nfirst line\nsecond line\n\n"
+        );
+    }
+
+    #[test]
+    fn vtt_matches_golden_output() {
+        let lines = [
+            line(7, 59.5, 61.0, None, "a --> b"),
+            // An end before the start is clamped to the start
+            line(8, 3661.001, 3600.0, Some("Bo"), "late"),
+        ];
+        assert_eq!(
+            render_vtt(&lines),
+            "WEBVTT\n\n\
+             7\n00:00:59.500 --> 00:01:01.000\na -&gt; b\n\n\
+             8\n01:01:01.001 --> 01:01:01.001\nBo: late\n\n"
+        );
+    }
+
+    // What a subtitle player reads back from one cue
+    #[derive(Debug, PartialEq)]
+    struct Cue {
+        start: f32,
+        end: f32,
+        speaker: Option<String>,
+        text: String,
+    }
+
+    fn parse_timestamp(text: &str) -> f32 {
+        let (clock, millis) = text.trim().split_once([',', '.']).unwrap();
+        let mut parts = clock.split(':').map(|p| p.parse::<u32>().unwrap());
+        let (hours, minutes, seconds) = (parts.next().unwrap(), parts.next().unwrap(), parts.next().unwrap());
+        (hours * 3600 + minutes * 60 + seconds) as f32 + millis.parse::<u32>().unwrap() as f32 / 1000.0
+    }
+
+    fn parse_cues(blocks: &str, unescape: fn(&str) -> String) -> Vec<Cue> {
+        blocks
+            .split("\n\n")
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| {
+                let mut lines = block.lines().skip(1);
+                let (start, end) = lines.next().unwrap().split_once(" --> ").unwrap();
+                let text = unescape(&lines.collect::<Vec<_>>().join("\n"));
+                let (speaker, text) = match text.split_once(": ") {
+                    Some((speaker, text)) => (Some(speaker.to_string()), text.to_string()),
+                    None => (None, text),
+                };
+                Cue {
+                    start: parse_timestamp(start),
+                    end: parse_timestamp(end),
+                    speaker,
+                    text,
+                }
+            })
+            .collect()
+    }
+
+    fn parse_srt(srt: &str) -> Vec<Cue> {
+        parse_cues(srt, str::to_string)
+    }
+
+    fn parse_vtt(vtt: &str) -> Vec<Cue> {
+        let body = vtt.strip_prefix("WEBVTT\n\n").expect("missing WEBVTT header");
+        parse_cues(body, |text| text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&"))
+    }
+
+    fn expected_cues(lines: &[TranscriptLine]) -> Vec<Cue> {
+        lines
+            .iter()
+            .map(|line| Cue {
+                start: line.start,
+                end: line.end,
+                speaker: line.speaker.clone(),
+                text: line.text.clone(),
+            })
+            .collect()
+    }
+
+    fn round_trip_lines() -> Vec<TranscriptLine> {
+        vec![
+            line(0, 0.0, 2.5, Some("Ana"), "Good morning"),
+            line(1, 2.5, 7.125, None, "first line\nsecond line"),
+            line(2, 3725.25, 3730.0, Some("Bo"), "Is 2 < 3 & 5 > 4?"),
+        ]
+    }
+
+    #[test]
+    fn srt_round_trips_timing_speaker_and_text() {
+        let lines = round_trip_lines();
+        assert_eq!(parse_srt(&render_srt(&lines)), expected_cues(&lines));
+    }
+
+    #[test]
+    fn vtt_round_trips_timing_speaker_and_text() {
+        let lines = round_trip_lines();
+        assert_eq!(parse_vtt(&render_vtt(&lines)), expected_cues(&lines));
+    }
+
+    #[test]
+    fn vtt_escapes_markup_characters() {
+        let vtt = render_vtt(&[line(0, 0.0, 1.0, None, "a <b & c")]);
+        assert!(vtt.contains("\na &lt;b &amp; c\n"), "{}", vtt);
+        // SRT has no entities to escape with
+        assert!(render_srt(&[line(0, 0.0, 1.0, None, "a <b & c")]).contains("\na <b & c\n"));
+    }
+
+    #[test]
+    fn transcript_document_round_trips_through_json() {
+        let transcript = SessionTranscript {
+            session_id: "session-1".to_string(),
+            started_at: Some(Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap()),
+            stopped_at: None,
+            interrupted: false,
+            exported: false,
+            lines: vec![line(1, 0.0, 1.5, Some("Ana"), "Hi"), line(2, 1.5, 4.0, None, "Bye")],
+            bookmarks: Vec::new(),
+            missing_lines: 0,
+        };
+        let json = render(&transcript, None, ExportFormat::Json).unwrap();
+        let document: TranscriptDocument = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(document.schema_version, TRANSCRIPT_SCHEMA_VERSION);
+        assert_eq!(document.session_id, "session-1");
+        assert_eq!(document.started_at, transcript.started_at);
+        assert_eq!(document.lines.len(), 2);
+        assert_eq!(document.lines[0].speaker.as_deref(), Some("Ana"));
+        assert_eq!(document.lines[1].end, 4.0);
+        assert!(document.extraction.is_none());
+        assert_eq!(serde_json::to_string_pretty(&document).unwrap(), json);
+    }
+}
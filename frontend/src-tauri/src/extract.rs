@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::journal::{self, SessionTranscript, TranscriptLine};
+use crate::ollama::{parse_json_reply, ChatMessage, ModelOptions, OllamaClient};
+use crate::summary::{self, chunk_texts, DEFAULT_CONTEXT_TOKENS};
+use crate::vault;
+
+const EXTRACTION_FILE_NAME: &str = "extraction.json";
+// The extraction prompt and its JSON reply are longer than the summary's
+const PROMPT_RESERVE_TOKENS: usize = 1536;
+
+const EXTRACT_SYSTEM_PROMPT: &str = "You extract structured items from meeting transcripts. \
+Each transcript line starts with an id like L12. Return JSON with three arrays: \
+\"action_items\" (task, assignee, due, source_line), \"decisions\" (text, source_line) and \
+\"open_questions\" (text, source_line). Use null for an unknown assignee or due date, copy \
+due dates as the phrase used in the meeting (e.g. \"next Friday\"), and set source_line to \
+the id number of the line the item comes from. Only include items that are actually in the \
+transcript.";
+
+const REPAIR_SYSTEM_PROMPT: &str = "The following text was supposed to be JSON matching the \
+schema you were given but could not be parsed. Reply with the corrected JSON only.";
+
+static LINE_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").unwrap());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceRef {
+    pub line_sequence: u64,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedActionItem {
+    pub task: String,
+    pub assignee: Option<String>,
+    pub due: Option<String>,
+    pub source: Option<SourceRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedItem {
+    pub text: String,
+    pub source: Option<SourceRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extraction {
+    pub session_id: String,
+    pub model: String,
+    pub generated_at: DateTime<Utc>,
+    pub action_items: Vec<ExtractedActionItem>,
+    pub decisions: Vec<ExtractedItem>,
+    pub open_questions: Vec<ExtractedItem>,
+}
+
+// What the model returns. Every field is lenient: models routinely omit keys, return
+// numbers as strings, names as objects or lists, or write "L12" instead of 12.
+#[derive(Debug, Default, Deserialize)]
+struct RawExtraction {
+    #[serde(default)]
+    action_items: Vec<RawActionItem>,
+    #[serde(default)]
+    decisions: Vec<RawItem>,
+    #[serde(default)]
+    open_questions: Vec<RawItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawActionItem {
+    #[serde(default, alias = "description", deserialize_with = "lenient_text")]
+    task: String,
+    #[serde(default, alias = "owner", deserialize_with = "lenient_string")]
+    assignee: Option<String>,
+    #[serde(default, alias = "due_date", deserialize_with = "lenient_string")]
+    due: Option<String>,
+    #[serde(default)]
+    source_line: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawItem {
+    #[serde(default, alias = "decision", alias = "question", deserialize_with = "lenient_text")]
+    text: String,
+    #[serde(default)]
+    source_line: Option<Value>,
+}
+
+// Flattens whatever the model put in a text field: objects give their name or text,
+// lists are joined and scalars are written out
+fn value_text(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(items) => {
+            let parts: Vec<String> = items.into_iter().filter_map(value_text).collect();
+            (!parts.is_empty()).then(|| parts.join(", "))
+        }
+        Value::Object(mut map) => ["name", "text", "value", "date"]
+            .iter()
+            .find_map(|key| map.remove(*key).and_then(value_text))
+            .or_else(|| (!map.is_empty()).then(|| Value::Object(map).to_string())),
+    }
+}
+
+fn lenient_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(value_text(Value::deserialize(deserializer)?))
+}
+
+fn lenient_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(lenient_string(deserializer)?.unwrap_or_default())
+}
+
+pub fn extraction_schema() -> Value {
+    let line = json!({ "type": "integer" });
+    let nullable = json!({ "type": ["string", "null"] });
+    json!({
+        "type": "object",
+        "properties": {
+            "action_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task": { "type": "string" },
+                        "assignee": nullable,
+                        "due": nullable,
+                        "source_line": line
+                    },
+                    "required": ["task", "assignee", "due", "source_line"]
+                }
+            },
+            "decisions": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": { "text": { "type": "string" }, "source_line": line },
+                    "required": ["text", "source_line"]
+                }
+            },
+            "open_questions": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": { "text": { "type": "string" }, "source_line": line },
+                    "required": ["text", "source_line"]
+                }
+            }
+        },
+        "required": ["action_items", "decisions", "open_questions"]
+    })
+}
+
+pub fn extraction_path(session_id: &str) -> PathBuf {
+    journal::session_dir(session_id).join(EXTRACTION_FILE_NAME)
+}
+
+pub fn load_extraction(session_id: &str) -> Result<Option<Extraction>> {
+    journal::validate_session_id(session_id)?;
+    let path = extraction_path(session_id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&vault::read(path)?)?))
+}
+
+fn line_id(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => LINE_ID.find(s)?.as_str().parse().ok(),
+        _ => None,
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("null") && !v.eq_ignore_ascii_case("unknown"))
+}
+
+// Prefixed with the id the model cites items by
+fn format_line(line: &TranscriptLine) -> String {
+    format!("L{} {}", line.sequence, summary::format_line(line))
+}
+
+// Accumulates validated items across transcript chunks, dropping duplicates
+struct ExtractionBuilder<'a> {
+    transcript: &'a SessionTranscript,
+    action_items: Vec<ExtractedActionItem>,
+    decisions: Vec<ExtractedItem>,
+    open_questions: Vec<ExtractedItem>,
+    seen: HashSet<String>,
+}
+
+impl<'a> ExtractionBuilder<'a> {
+    fn new(transcript: &'a SessionTranscript) -> Self {
+        Self {
+            transcript,
+            action_items: Vec::new(),
+            decisions: Vec::new(),
+            open_questions: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    // Ids that don't name a real transcript line are dropped rather than trusted
+    fn source(&self, value: Option<&Value>) -> Option<SourceRef> {
+        let sequence = line_id(value?)?;
+        self.transcript
+            .lines
+            .iter()
+            .find(|line| line.sequence == sequence)
+            .map(|line| SourceRef {
+                line_sequence: line.sequence,
+                start: line.start,
+                end: line.end,
+            })
+    }
+
+    fn first_sighting(&mut self, kind: &str, text: &str) -> bool {
+        self.seen.insert(format!("{}:{}", kind, text.to_lowercase()))
+    }
+
+    fn add(&mut self, raw: RawExtraction) {
+        for item in raw.action_items {
+            let task = item.task.trim().to_string();
+            if task.is_empty() || !self.first_sighting("action", &task) {
+                continue;
+            }
+            let source = self.source(item.source_line.as_ref());
+            self.action_items.push(ExtractedActionItem {
+                task,
+                assignee: non_empty(item.assignee),
+                due: non_empty(item.due),
+                source,
+            });
+        }
+        for (kind, items) in [("decision", raw.decisions), ("question", raw.open_questions)] {
+            for item in items {
+                let text = item.text.trim().to_string();
+                if text.is_empty() || !self.first_sighting(kind, &text) {
+                    continue;
+                }
+                let extracted = ExtractedItem {
+                    source: self.source(item.source_line.as_ref()),
+                    text,
+                };
+                if kind == "decision" {
+                    self.decisions.push(extracted);
+                } else {
+                    self.open_questions.push(extracted);
+                }
+            }
+        }
+    }
+}
+
+async fn request_extraction(
+    client: &OllamaClient,
+    model: &str,
+    chunk: &str,
+    schema: &Value,
+    options: &ModelOptions,
+) -> Result<RawExtraction> {
+    let messages = [ChatMessage::system(EXTRACT_SYSTEM_PROMPT), ChatMessage::user(chunk)];
+    let reply = client.chat(model, &messages, Some(schema), options, |_| {}).await?;
+
+    match parse_json_reply::<RawExtraction>(&reply) {
+        Ok(raw) => Ok(raw),
+        Err(e) => {
+            // One corrective round trip before giving up on this chunk
+            warn!("Extraction reply was not valid JSON ({}), asking the model to repair it", e);
+            let messages = [ChatMessage::system(REPAIR_SYSTEM_PROMPT), ChatMessage::user(reply)];
+            let repaired = client.chat(model, &messages, Some(schema), options, |_| {}).await?;
+            parse_json_reply(&repaired)
+        }
+    }
+}
+
+pub async fn extract_session(
+    client: &OllamaClient,
+    session_id: &str,
+    model: &str,
+    context_tokens: Option<usize>,
+) -> Result<Extraction> {
+    let transcript = journal::load_transcript(session_id)?;
+    if transcript.lines.is_empty() {
+        return Err(anyhow!("Session {} has no transcript to analyze", session_id));
+    }
+
+    let context_tokens = context_tokens.unwrap_or(DEFAULT_CONTEXT_TOKENS);
+    let chunk_chars = summary::chunk_chars(context_tokens, PROMPT_RESERVE_TOKENS);
+    let options = ModelOptions {
+        num_ctx: Some(context_tokens),
+        temperature: Some(0.0),
+    };
+    let schema = extraction_schema();
+
+    let chunks = chunk_texts(transcript.lines.iter().map(format_line), chunk_chars);
+    info!("Extracting action items for session {} in {} chunk(s)", session_id, chunks.len());
+
+    let mut builder = ExtractionBuilder::new(&transcript);
+    for chunk in &chunks {
+        let raw = request_extraction(client, model, chunk, &schema, &options).await?;
+        builder.add(raw);
+    }
+
+    let extraction = Extraction {
+        session_id: session_id.to_string(),
+        model: model.to_string(),
+        generated_at: Utc::now(),
+        action_items: builder.action_items,
+        decisions: builder.decisions,
+        open_questions: builder.open_questions,
+    };
+
+    vault::write(extraction_path(session_id), &serde_json::to_vec_pretty(&extraction)?)?;
+    Ok(extraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn transcript() -> SessionTranscript {
+        let line = |sequence: u64, start: f32, text: &str| TranscriptLine {
+            sequence,
+            text: text.to_string(),
+            start,
+            end: start + 2.0,
+            source: "mic".to_string(),
+            speaker: None,
+            recorded_at: Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap(),
+        };
+        SessionTranscript {
+            session_id: "session-1".to_string(),
+            started_at: None,
+            stopped_at: None,
+            interrupted: false,
+            exported: false,
+            lines: vec![line(3, 10.0, "Dana will send the deck"), line(4, 12.0, "We ship Friday")],
+            bookmarks: Vec::new(),
+            missing_lines: 0,
+        }
+    }
+
+    #[test]
+    fn prompt_lines_carry_the_id_items_cite() {
+        let mut transcript = transcript();
+        transcript.lines[0].speaker = Some("Dana".to_string());
+        assert_eq!(format_line(&transcript.lines[0]), "L3 [00:00:10] Dana: Dana will send the deck");
+        assert_eq!(format_line(&transcript.lines[1]), "L4 [00:00:12] We ship Friday");
+        // The longer extraction prompt leaves less room for transcript than a summary
+        assert!(summary::chunk_chars(DEFAULT_CONTEXT_TOKENS, PROMPT_RESERVE_TOKENS) < summary::chunk_chars(DEFAULT_CONTEXT_TOKENS, 1024));
+    }
+
+    #[test]
+    fn malformed_reply_is_repaired_and_normalized() {
+        let reply = r#"Here you go:
+```json
+{
+  "action_items": [
+    {"task": "Send the deck", "assignee": {"name": "Dana"}, "due": 20240510, "source_line": "L3",},
+    {"description": "Book a room", "owner": ["Ana", "Bo"], "due_date": null, "source_line": 99},
+    {"task": "send the DECK", "assignee": "unknown"},
+  ],
+  "decisions": [{"decision": "Ship on Friday", "source_line": 4}],
+}
+```"#;
+        let raw: RawExtraction = parse_json_reply(reply).unwrap();
+        let transcript = transcript();
+        let mut builder = ExtractionBuilder::new(&transcript);
+        builder.add(raw);
+
+        assert_eq!(builder.action_items.len(), 2, "duplicate task should be dropped");
+        let deck = &builder.action_items[0];
+        assert_eq!(deck.assignee.as_deref(), Some("Dana"));
+        assert_eq!(deck.due.as_deref(), Some("20240510"));
+        assert_eq!(deck.source.as_ref().map(|s| s.line_sequence), Some(3));
+
+        let room = &builder.action_items[1];
+        assert_eq!(room.task, "Book a room");
+        assert_eq!(room.assignee.as_deref(), Some("Ana, Bo"));
+        assert!(room.due.is_none());
+        assert!(room.source.is_none(), "line 99 is not in the transcript");
+
+        assert_eq!(builder.decisions[0].text, "Ship on Friday");
+        assert_eq!(builder.decisions[0].source.as_ref().map(|s| s.start), Some(12.0));
+        assert!(builder.open_questions.is_empty());
+    }
+
+    #[test]
+    fn value_text_flattens_model_shapes() {
+        assert_eq!(value_text(json!("x")), Some("x".to_string()));
+        assert_eq!(value_text(json!(3.5)), Some("3.5".to_string()));
+        assert_eq!(value_text(json!([])), None);
+        assert_eq!(value_text(json!({"text": "next week"})), Some("next week".to_string()));
+        assert_eq!(value_text(json!({"who": "Ana"})), Some("{\"who\":\"Ana\"}".to_string()));
+        assert_eq!(value_text(Value::Null), None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::mpsc;
+
+use crate::audio::audio_processing::resample;
+use crate::audio::decode::MediaDecoder;
+use crate::journal::{self, JournalEntry, SessionJournal};
+use crate::library::{self, MeetingRecord, MeetingSource};
+use crate::{
+    publish_transcript_update, send_audio_chunk, TranscriptAccumulator, CHUNK_DURATION_MS,
+    WHISPER_SAMPLE_RATE,
+};
+
+// Decoded chunks waiting for transcription; keeps the decoder from racing ahead of the server
+const PENDING_CHUNKS: usize = 4;
+
+#[derive(Debug, Serialize, Clone)]
+struct ImportProgress {
+    session_id: String,
+    percent: f32,
+    processed_secs: f64,
+    total_secs: Option<f64>,
+    eta_secs: Option<f64>,
+}
+
+impl ImportProgress {
+    fn new(session_id: &str, processed_secs: f64, total_secs: Option<f64>, elapsed_secs: f64) -> Self {
+        let total = total_secs.filter(|total| *total > 0.0);
+        ImportProgress {
+            session_id: session_id.to_string(),
+            percent: total
+                .map(|total| ((processed_secs / total) * 100.0).min(100.0) as f32)
+                .unwrap_or(0.0),
+            processed_secs,
+            total_secs,
+            // Extrapolated from the rate so far, once there is one
+            eta_secs: total.filter(|_| processed_secs > 0.0).map(|total| {
+                let remaining = (total - processed_secs).max(0.0);
+                elapsed_secs / processed_secs * remaining
+            }),
+        }
+    }
+}
+
+struct DecodedChunk {
+    samples: Vec<f32>,
+    offset_secs: f32,
+    decoded_secs: f64,
+    total_secs: Option<f64>,
+}
+
+// Runs on a blocking thread: decodes the file and hands 16 kHz chunks to the transcription loop
+fn decode_chunks(path: PathBuf, tx: mpsc::Sender<Result<DecodedChunk>>) {
+    let mut decoder = match MediaDecoder::open(&path) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            let _ = tx.blocking_send(Err(e));
+            return;
+        }
+    };
+
+    let source_rate = decoder.sample_rate();
+    let total_secs = decoder.duration_secs();
+    let chunk_len = (source_rate as u64 * CHUNK_DURATION_MS as u64 / 1000) as usize;
+    let mut pending: Vec<f32> = Vec::with_capacity(chunk_len);
+    let mut consumed: usize = 0;
+
+    loop {
+        let block = match decoder.next_block() {
+            Ok(block) => block,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+        let finished = block.is_none();
+        if let Some(samples) = block {
+            pending.extend(samples);
+        }
+
+        while pending.len() >= chunk_len || (finished && !pending.is_empty()) {
+            let take = pending.len().min(chunk_len);
+            let source: Vec<f32> = pending.drain(..take).collect();
+            let offset_secs = consumed as f32 / source_rate as f32;
+            consumed += source.len();
+
+            let samples = if source_rate != WHISPER_SAMPLE_RATE {
+                match resample(&source, source_rate, WHISPER_SAMPLE_RATE) {
+                    Ok(samples) => samples,
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e));
+                        return;
+                    }
+                }
+            } else {
+                source
+            };
+
+            let chunk = DecodedChunk {
+                samples,
+                offset_secs,
+                decoded_secs: consumed as f64 / source_rate as f64,
+                total_secs,
+            };
+            if tx.blocking_send(Ok(chunk)).is_err() {
+                // Receiver gone: the import was abandoned
+                return;
+            }
+        }
+
+        if finished {
+            return;
+        }
+    }
+}
+
+pub async fn import_media<R: Runtime>(app: &AppHandle<R>, path: &Path) -> Result<String> {
+    if !path.is_file() {
+        return Err(anyhow!("File not found: {:?}", path));
+    }
+
+    let session_id = journal::generate_session_id();
+    let session_journal = Arc::new(SessionJournal::create(&session_id)?);
+    info!("Importing {:?} as session {}", path, session_id);
+    let mut result = transcribe(app, path, &session_journal).await;
+    if let Some(update) = result.accumulator.flush() {
+        publish_transcript_update(app, &session_journal, update);
+        result.lines += 1;
+    }
+
+    match result.error {
+        None => {
+            session_journal.finish()?;
+            library::finish(&session_id)?;
+            Ok(session_id)
+        }
+        Some(e) => {
+            abandon(&session_journal, result.lines > 0);
+            Err(e)
+        }
+    }
+}
+
+struct TranscribeResult {
+    accumulator: TranscriptAccumulator,
+    // Transcript updates written to the journal
+    lines: usize,
+    error: Option<anyhow::Error>,
+}
+
+// A failed import keeps whatever was transcribed, closed like a session cut short by a
+// crash so it is offered for recovery; with nothing transcribed the session is removed
+fn abandon(session_journal: &SessionJournal, keep: bool) {
+    let session_id = session_journal.session_id();
+    if !keep {
+        if library::get(session_id).is_ok() {
+            if let Err(e) = library::delete(session_id, false) {
+                error!("Failed to remove failed import {}: {}", session_id, e);
+            }
+        } else if let Err(e) = std::fs::remove_dir_all(session_journal.dir()) {
+            error!("Failed to remove failed import {}: {}", session_id, e);
+        }
+        return;
+    }
+    let stopped = JournalEntry::Stopped {
+        stopped_at: chrono::Utc::now(),
+        recovered: true,
+    };
+    if let Err(e) = session_journal.append(&stopped) {
+        error!("Failed to close journal of failed import {}: {}", session_id, e);
+    }
+    if let Err(e) = library::finish(session_id) {
+        error!("Failed to update library entry for {}: {}", session_id, e);
+    }
+}
+
+async fn transcribe<R: Runtime>(app: &AppHandle<R>, path: &Path, session_journal: &SessionJournal) -> TranscribeResult {
+    let session_id = session_journal.session_id().to_string();
+    let mut result = TranscribeResult {
+        accumulator: TranscriptAccumulator::new(),
+        lines: 0,
+        error: None,
+    };
+
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| library::default_title(chrono::Utc::now()));
+    let mut meeting = MeetingRecord::new(
+        &session_id,
+        title,
+        MeetingSource::Import { path: path.to_string_lossy().into_owned() },
+    );
+    meeting.audio_files.push(path.to_string_lossy().into_owned());
+    if let Err(e) = library::create(meeting) {
+        result.error = Some(e);
+        return result;
+    }
+
+    let (tx, mut rx) = mpsc::channel(PENDING_CHUNKS);
+    let decode_path = path.to_path_buf();
+    let decode_task = tokio::task::spawn_blocking(move || decode_chunks(decode_path, tx));
+
+    let client = reqwest::Client::new();
+    let started = Instant::now();
+
+    while let Some(chunk) = rx.recv().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                result.error = Some(e);
+                break;
+            }
+        };
+
+        result.accumulator.set_chunk_offset(chunk.offset_secs);
+        match send_audio_chunk(chunk.samples, &client).await {
+            Ok(response) => {
+                for segment in response.segments {
+                    if let Some(update) = result.accumulator.add_segment(&segment) {
+                        publish_transcript_update(app, session_journal, update);
+                        result.lines += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                result.error = Some(anyhow!("Transcription failed: {}", e));
+                break;
+            }
+        }
+
+        let progress = ImportProgress::new(
+            &session_id,
+            chunk.decoded_secs,
+            chunk.total_secs,
+            started.elapsed().as_secs_f64(),
+        );
+        if let Err(e) = app.emit("import-progress", progress) {
+            error!("Failed to emit import progress: {}", e);
+        }
+    }
+
+    // Dropping the receiver stops the decoder if we bailed out early
+    drop(rx);
+    if let Err(e) = decode_task.await {
+        warn!("Decode task ended abnormally: {}", e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_reports_percent_and_eta() {
+        // A quarter of an hour decoded in 30 s leaves 90 s for the rest
+        let progress = ImportProgress::new("import-progress", 900.0, Some(3600.0), 30.0);
+        assert_eq!(progress.percent, 25.0);
+        assert_eq!(progress.eta_secs, Some(90.0));
+
+        let done = ImportProgress::new("import-progress", 3601.0, Some(3600.0), 120.0);
+        assert_eq!(done.percent, 100.0);
+        assert_eq!(done.eta_secs, Some(0.0));
+    }
+
+    #[test]
+    fn progress_without_a_known_length_has_no_eta() {
+        let unknown = ImportProgress::new("import-progress", 60.0, None, 5.0);
+        assert_eq!(unknown.percent, 0.0);
+        assert_eq!(unknown.eta_secs, None);
+
+        let empty = ImportProgress::new("import-progress", 60.0, Some(0.0), 5.0);
+        assert_eq!(empty.eta_secs, None);
+
+        // Nothing decoded yet gives no rate to extrapolate from
+        let starting = ImportProgress::new("import-progress", 0.0, Some(3600.0), 0.5);
+        assert_eq!(starting.percent, 0.0);
+        assert_eq!(starting.eta_secs, None);
+    }
+
+    fn failed_import(session_id: &str) -> SessionJournal {
+        let journal = SessionJournal::create(session_id).unwrap();
+        let source = MeetingSource::Import { path: "/imports/call.m4a".to_string() };
+        library::create(MeetingRecord::new(session_id, "call", source)).unwrap();
+        journal
+    }
+
+    #[test]
+    fn failed_import_without_lines_is_removed() {
+        let journal = failed_import("import-failed-empty");
+        abandon(&journal, false);
+        assert!(library::get("import-failed-empty").is_err());
+        assert!(!journal::session_dir("import-failed-empty").exists());
+    }
+
+    #[test]
+    fn failed_import_with_lines_is_kept_as_interrupted() {
+        let journal = failed_import("import-failed-partial");
+        journal.append_line("transcribed before the failure", 0.0, 2.0, "Mixed Audio").unwrap();
+        abandon(&journal, true);
+
+        let transcript = journal::load_transcript("import-failed-partial").unwrap();
+        assert!(transcript.interrupted);
+        assert!(transcript.stopped_at.is_some());
+        assert_eq!(transcript.lines.len(), 1);
+        assert!(library::get("import-failed-partial").unwrap().ended_at.is_some());
+    }
+}
@@ -0,0 +1,744 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::vault;
+
+// Matches the bundle identifier in tauri.conf.json so session data lands in $APPDATA
+const APP_IDENTIFIER: &str = "com.meetily.ai";
+const JOURNAL_FILE_NAME: &str = "transcript.jsonl";
+
+// The journal of the session being recorded. Anything else appending to that session
+// goes through this handle, since a second writer would seal lines at the same positions.
+pub static CURRENT_SESSION: Lazy<Mutex<Option<Arc<SessionJournal>>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn app_data_dir() -> PathBuf {
+    // Tests never touch the user's real data
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("{}-test-{}", APP_IDENTIFIER, std::process::id()));
+    }
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_IDENTIFIER)
+}
+
+pub fn sessions_dir() -> PathBuf {
+    app_data_dir().join("sessions")
+}
+
+pub fn session_dir(session_id: &str) -> PathBuf {
+    sessions_dir().join(session_id)
+}
+
+pub fn journal_path(session_id: &str) -> PathBuf {
+    session_dir(session_id).join(JOURNAL_FILE_NAME)
+}
+
+pub fn generate_session_id() -> String {
+    let suffix: u16 = rand::thread_rng().gen();
+    format!("{}-{:04x}", Utc::now().format("%Y%m%d-%H%M%S"), suffix)
+}
+
+// Session ids end up in filesystem paths, so only allow the characters we generate
+pub fn validate_session_id(session_id: &str) -> Result<()> {
+    let valid = !session_id.is_empty()
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid session id: {:?}", session_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptLine {
+    pub sequence: u64,
+    pub text: String,
+    // Seconds from the start of the session
+    pub start: f32,
+    pub end: f32,
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+// A moment the user marked as important while recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub label: String,
+    // Seconds of recorded audio from the start of the session
+    pub offset: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Started {
+        session_id: String,
+        started_at: DateTime<Utc>,
+    },
+    Line(TranscriptLine),
+    Stopped {
+        stopped_at: DateTime<Utc>,
+        #[serde(default)]
+        recovered: bool,
+    },
+    Exported {
+        path: String,
+        exported_at: DateTime<Utc>,
+    },
+    Bookmark(Bookmark),
+}
+
+pub struct SessionJournal {
+    session_id: String,
+    file: Mutex<JournalFile>,
+    next_sequence: AtomicU64,
+}
+
+struct JournalFile {
+    file: File,
+    // Position of the next line in the file; sealed lines are bound to it
+    next_line: u64,
+}
+
+impl SessionJournal {
+    pub fn create(session_id: &str) -> Result<Self> {
+        validate_session_id(session_id)?;
+        let dir = session_dir(session_id);
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(JOURNAL_FILE_NAME);
+        if path.exists() {
+            return Err(anyhow!("Journal already exists for session {}", session_id));
+        }
+
+        let journal = Self {
+            session_id: session_id.to_string(),
+            file: Mutex::new(JournalFile {
+                file: OpenOptions::new().create(true).append(true).open(&path)?,
+                next_line: 0,
+            }),
+            next_sequence: AtomicU64::new(0),
+        };
+        journal.append(&JournalEntry::Started {
+            session_id: session_id.to_string(),
+            started_at: Utc::now(),
+        })?;
+        info!("Created transcript journal at {:?}", path);
+        Ok(journal)
+    }
+
+    pub fn open(session_id: &str) -> Result<Self> {
+        validate_session_id(session_id)?;
+        let path = journal_path(session_id);
+        let line_count = read_journal(session_id)?
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, JournalEntry::Line(_)))
+            .count();
+
+        let mut file = OpenOptions::new().read(true).append(true).open(&path)?;
+        // A line torn by a crash is ended first so the next entry starts on its own line
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        let next_line = BufReader::new(File::open(&path)?)
+            .lines()
+            .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .count() as u64;
+
+        Ok(Self {
+            session_id: session_id.to_string(),
+            file: Mutex::new(JournalFile { file, next_line }),
+            next_sequence: AtomicU64::new(line_count as u64),
+        })
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        session_dir(&self.session_id)
+    }
+
+    pub fn append_line(&self, text: &str, start: f32, end: f32, source: &str) -> Result<TranscriptLine> {
+        let line = TranscriptLine {
+            sequence: self.next_sequence.fetch_add(1, Ordering::SeqCst),
+            text: text.to_string(),
+            start,
+            end,
+            source: source.to_string(),
+            speaker: None,
+            recorded_at: Utc::now(),
+        };
+        self.append(&JournalEntry::Line(line.clone()))?;
+        Ok(line)
+    }
+
+    pub fn append_bookmark(&self, label: &str, offset: f32) -> Result<Bookmark> {
+        let bookmark = Bookmark {
+            label: label.trim().to_string(),
+            offset,
+            created_at: Utc::now(),
+        };
+        self.append(&JournalEntry::Bookmark(bookmark.clone()))?;
+        Ok(bookmark)
+    }
+
+    pub fn finish(&self) -> Result<()> {
+        self.append(&JournalEntry::Stopped {
+            stopped_at: Utc::now(),
+            recovered: false,
+        })
+    }
+
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let json = serde_json::to_string(entry)?;
+        let mut journal = self
+            .file
+            .lock()
+            .map_err(|_| anyhow!("Journal lock poisoned"))?;
+        let mut encoded = vault::seal_line(&self.session_id, journal.next_line, &json)?;
+        encoded.push('\n');
+
+        // One write per entry keeps each line whole; sync so a crash loses at most the line in flight
+        journal.file.write_all(encoded.as_bytes())?;
+        journal.file.sync_data()?;
+        journal.next_line += 1;
+        Ok(())
+    }
+}
+
+struct JournalContents {
+    entries: Vec<JournalEntry>,
+    // Lines that were removed, reordered, injected or could not be read, not counting a
+    // torn last line
+    missing_lines: u64,
+}
+
+fn read_journal(session_id: &str) -> Result<JournalContents> {
+    let path = journal_path(session_id);
+    let reader = BufReader::new(File::open(&path)?);
+    let mut entries = Vec::new();
+    let mut missing_lines = 0;
+    // Position the next line should have been sealed at
+    let mut expected = 0u64;
+    let mut unreadable = None;
+    // Encryption can be turned on part way through a session but never off, so once a
+    // sealed line has been seen every later line must be sealed too
+    let mut sealed = false;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Only the final line may be torn; an unreadable line with more after it is damage
+        if let Some(previous) = unreadable.take() {
+            error!("Journal line {} in {:?} is unreadable", previous, path);
+            missing_lines += 1;
+        }
+        let position = expected;
+        let line = match vault::open_line(session_id, &line) {
+            Ok((Some(index), line)) => {
+                if index > position {
+                    error!(
+                        "{} journal line(s) missing before line {} in {:?}",
+                        index - position,
+                        number + 1,
+                        path
+                    );
+                    missing_lines += index - position;
+                } else if index < position && sealed {
+                    error!("Journal line {} in {:?} is out of order", number + 1, path);
+                    missing_lines += 1;
+                    continue;
+                } else if index < position {
+                    // More plain lines precede the first sealed one than there were when
+                    // it was written, so some of them were added later
+                    error!(
+                        "{} plain journal line(s) before line {} in {:?} were added after encryption was turned on",
+                        position - index,
+                        number + 1,
+                        path
+                    );
+                    missing_lines += position - index;
+                }
+                sealed = true;
+                expected = index + 1;
+                line
+            }
+            Ok((None, _)) if sealed => {
+                error!("Journal line {} in {:?} is not sealed", number + 1, path);
+                missing_lines += 1;
+                continue;
+            }
+            Ok((None, line)) => {
+                expected += 1;
+                line
+            }
+            // Without the key nothing is readable; don't mistake that for damaged lines
+            Err(e) if !vault::is_unlocked() => return Err(e),
+            Err(e) => {
+                warn!("Undecryptable journal line {} in {:?}: {}", number + 1, path, e);
+                expected += 1;
+                unreadable = Some(number + 1);
+                continue;
+            }
+        };
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) => entries.push(entry),
+            // A torn final line is expected after a crash
+            Err(e) => {
+                warn!("Unparseable journal line {} in {:?}: {}", number + 1, path, e);
+                unreadable = Some(number + 1);
+            }
+        }
+    }
+
+    Ok(JournalContents { entries, missing_lines })
+}
+
+pub fn read_entries(session_id: &str) -> Result<Vec<JournalEntry>> {
+    validate_session_id(session_id)?;
+    Ok(read_journal(session_id)?.entries)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTranscript {
+    pub session_id: String,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub interrupted: bool,
+    pub exported: bool,
+    pub lines: Vec<TranscriptLine>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    // Journal lines lost to tampering or corruption; the transcript is incomplete when set
+    #[serde(default)]
+    pub missing_lines: u64,
+}
+
+impl SessionTranscript {
+    pub fn from_entries(session_id: &str, entries: Vec<JournalEntry>) -> Self {
+        let mut transcript = SessionTranscript {
+            session_id: session_id.to_string(),
+            started_at: None,
+            stopped_at: None,
+            interrupted: false,
+            exported: false,
+            lines: Vec::new(),
+            bookmarks: Vec::new(),
+            missing_lines: 0,
+        };
+
+        for entry in entries {
+            match entry {
+                JournalEntry::Started { started_at, .. } => transcript.started_at = Some(started_at),
+                JournalEntry::Line(line) => transcript.lines.push(line),
+                JournalEntry::Stopped { stopped_at, recovered } => {
+                    transcript.stopped_at = Some(stopped_at);
+                    transcript.interrupted = recovered;
+                }
+                JournalEntry::Exported { .. } => transcript.exported = true,
+                JournalEntry::Bookmark(bookmark) => transcript.bookmarks.push(bookmark),
+            }
+        }
+
+        transcript.lines.sort_by_key(|line| line.sequence);
+        transcript.bookmarks.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+        transcript
+    }
+}
+
+pub fn load_transcript(session_id: &str) -> Result<SessionTranscript> {
+    validate_session_id(session_id)?;
+    let contents = read_journal(session_id)?;
+    let mut transcript = SessionTranscript::from_entries(session_id, contents.entries);
+    transcript.missing_lines = contents.missing_lines;
+    Ok(transcript)
+}
+
+pub fn mark_exported(session_id: &str, path: &str) -> Result<()> {
+    let entry = JournalEntry::Exported {
+        path: path.to_string(),
+        exported_at: Utc::now(),
+    };
+    // Held while appending so the session can't stop and close its journal in between
+    let current = CURRENT_SESSION
+        .lock()
+        .map_err(|_| anyhow!("Session lock poisoned"))?;
+    match current.as_ref().filter(|journal| journal.session_id() == session_id) {
+        Some(journal) => journal.append(&entry),
+        None => SessionJournal::open(session_id)?.append(&entry),
+    }
+}
+
+pub fn list_session_ids() -> Result<Vec<String>> {
+    let root = sessions_dir();
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(&root)?.flatten() {
+        let path = entry.path();
+        if !path.join(JOURNAL_FILE_NAME).is_file() {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if validate_session_id(name).is_ok() {
+                ids.push(name.to_string());
+            }
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveredSession {
+    pub session_id: String,
+    pub started_at: Option<DateTime<Utc>>,
+    pub line_count: usize,
+    pub interrupted: bool,
+}
+
+// Closes journals left open by a crash and reports every session whose transcript was never exported.
+// `active_session` is skipped so a session that is still recording is not closed underneath itself.
+pub fn recover_sessions(active_session: Option<&str>) -> Result<Vec<RecoveredSession>> {
+    let mut recovered = Vec::new();
+
+    for session_id in list_session_ids()? {
+        if active_session == Some(session_id.as_str()) {
+            continue;
+        }
+
+        let mut transcript = match load_transcript(&session_id) {
+            Ok(transcript) => transcript,
+            Err(e) => {
+                warn!("Failed to read journal for session {}: {}", session_id, e);
+                continue;
+            }
+        };
+
+        if transcript.stopped_at.is_none() {
+            info!("Closing interrupted session {}", session_id);
+            SessionJournal::open(&session_id)?.append(&JournalEntry::Stopped {
+                stopped_at: Utc::now(),
+                recovered: true,
+            })?;
+            transcript.interrupted = true;
+        }
+
+        if !transcript.exported && !transcript.lines.is_empty() {
+            recovered.push(RecoveredSession {
+                session_id,
+                started_at: transcript.started_at,
+                line_count: transcript.lines.len(),
+                interrupted: transcript.interrupted,
+            });
+        }
+    }
+
+    Ok(recovered)
+}
+
+// Recordings are written into the session directory as `recording.<ext>` and
+// `recording.partN.<ext>` until they are moved at stop
+fn recording_files(session_id: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(session_dir(session_id)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("recording.") && !name.ends_with(".tmp"))
+        })
+        .collect()
+}
+
+// Seals recordings a crash left behind: an encrypted one gets the last record its writer
+// never wrote, and a plain one, from before encryption was on, is encrypted now. Needs
+// the vault unlocked, so it runs at startup and again after unlocking.
+pub fn recover_recordings(active_session: Option<&str>) -> Result<()> {
+    for session_id in list_session_ids()? {
+        if active_session == Some(session_id.as_str()) {
+            continue;
+        }
+        for path in recording_files(&session_id) {
+            let result = match vault::is_encrypted_file(&path) {
+                Ok(true) => vault::finish_sealed_file(&path).map(|repaired| {
+                    if repaired {
+                        info!("Finished encrypted recording {:?} left open by a crash", path);
+                    }
+                }),
+                Ok(false) => vault::encrypt_file_in_place(&path),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Recording {:?} in session {} is not sealed: {}", path, session_id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Recovery closes every unfinished journal it finds, so it must not run while
+    // another test is part way through writing one
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn journal_lines(session_id: &str) -> Vec<String> {
+        fs::read_to_string(journal_path(session_id))
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn write_journal_lines(session_id: &str, lines: &[String]) {
+        fs::write(journal_path(session_id), lines.join("\n") + "\n").unwrap();
+    }
+
+    fn texts(transcript: &SessionTranscript) -> Vec<&str> {
+        transcript.lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    fn plain_line(sequence: u64, text: &str) -> String {
+        serde_json::to_string(&JournalEntry::Line(TranscriptLine {
+            sequence,
+            text: text.to_string(),
+            start: 0.0,
+            end: 1.0,
+            source: "mic".to_string(),
+            speaker: None,
+            recorded_at: Utc::now(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validates_session_ids() {
+        assert!(validate_session_id("20240501-090000-1a2b").is_ok());
+        assert!(validate_session_id("import_1").is_ok());
+        assert!(validate_session_id("").is_err());
+        assert!(validate_session_id("..").is_err());
+        assert!(validate_session_id("a/b").is_err());
+        assert!(validate_session_id("a\\b").is_err());
+        assert!(validate_session_id(&generate_session_id()).is_ok());
+    }
+
+    #[test]
+    fn appended_entries_reload_in_order() {
+        let _lock = lock();
+        let journal = SessionJournal::create("journal-reload").unwrap();
+        assert!(SessionJournal::create("journal-reload").is_err());
+        journal.append_line("hello", 0.0, 1.5, "mic").unwrap();
+        journal.append_bookmark("  decision  ", 9.0).unwrap();
+        journal.append_bookmark("intro", 1.0).unwrap();
+        journal.append_line("world", 1.5, 3.0, "system").unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+
+        let transcript = load_transcript("journal-reload").unwrap();
+        assert!(transcript.started_at.is_some());
+        assert!(transcript.stopped_at.is_some());
+        assert!(!transcript.interrupted);
+        assert!(!transcript.exported);
+        assert_eq!(texts(&transcript), ["hello", "world"]);
+        assert_eq!(transcript.lines[1].source, "system");
+        let labels: Vec<_> = transcript.bookmarks.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, ["intro", "decision"]);
+
+        // Sequences carry on after reopening
+        let journal = SessionJournal::open("journal-reload").unwrap();
+        assert_eq!(journal.append_line("again", 3.0, 4.0, "mic").unwrap().sequence, 2);
+        mark_exported("journal-reload", "out.txt").unwrap();
+        assert!(load_transcript("journal-reload").unwrap().exported);
+    }
+
+    #[test]
+    fn torn_last_line_is_dropped_and_ended_on_reopen() {
+        let _lock = lock();
+        let journal = SessionJournal::create("journal-torn").unwrap();
+        journal.append_line("whole", 0.0, 1.0, "mic").unwrap();
+        drop(journal);
+        let torn = plain_line(1, "torn");
+        let mut file = OpenOptions::new().append(true).open(journal_path("journal-torn")).unwrap();
+        file.write_all(&torn.as_bytes()[..torn.len() / 2]).unwrap();
+        drop(file);
+
+        let transcript = load_transcript("journal-torn").unwrap();
+        assert_eq!(texts(&transcript), ["whole"]);
+        assert_eq!(transcript.missing_lines, 0);
+
+        let journal = SessionJournal::open("journal-torn").unwrap();
+        journal.append_line("after", 1.0, 2.0, "mic").unwrap();
+        let transcript = load_transcript("journal-torn").unwrap();
+        assert_eq!(texts(&transcript), ["whole", "after"]);
+        // The torn line is now followed by others, so it counts as damage
+        assert_eq!(transcript.missing_lines, 1);
+    }
+
+    #[test]
+    fn recovery_closes_interrupted_sessions() {
+        let _lock = lock();
+        let journal = SessionJournal::create("journal-crashed").unwrap();
+        journal.append_line("unsaved", 0.0, 1.0, "mic").unwrap();
+        drop(journal);
+        let active = SessionJournal::create("journal-active").unwrap();
+        active.append_line("still recording", 0.0, 1.0, "mic").unwrap();
+
+        let recovered = recover_sessions(Some("journal-active")).unwrap();
+        let crashed = recovered.iter().find(|s| s.session_id == "journal-crashed").unwrap();
+        assert!(crashed.interrupted);
+        assert_eq!(crashed.line_count, 1);
+        assert!(!recovered.iter().any(|s| s.session_id == "journal-active"));
+
+        let transcript = load_transcript("journal-crashed").unwrap();
+        assert!(transcript.interrupted);
+        assert!(transcript.stopped_at.is_some());
+        assert!(load_transcript("journal-active").unwrap().stopped_at.is_none());
+
+        // Exported sessions are closed but not offered again
+        mark_exported("journal-crashed", "out.txt").unwrap();
+        let recovered = recover_sessions(Some("journal-active")).unwrap();
+        assert!(!recovered.iter().any(|s| s.session_id == "journal-crashed"));
+    }
+
+    fn sealed_journal(session_id: &str) -> Vec<String> {
+        vault::use_test_key();
+        let journal = SessionJournal::create(session_id).unwrap();
+        for text in ["a", "b", "c"] {
+            journal.append_line(text, 0.0, 1.0, "mic").unwrap();
+        }
+        drop(journal);
+        journal_lines(session_id)
+    }
+
+    #[test]
+    fn removed_sealed_line_is_reported_missing() {
+        let mut lines = sealed_journal("journal-removed");
+        lines.remove(2);
+        write_journal_lines("journal-removed", &lines);
+
+        let transcript = load_transcript("journal-removed").unwrap();
+        assert_eq!(texts(&transcript), ["a", "c"]);
+        assert_eq!(transcript.missing_lines, 1);
+    }
+
+    #[test]
+    fn reordered_sealed_lines_are_reported() {
+        let mut lines = sealed_journal("journal-reordered");
+        lines.swap(2, 3);
+        write_journal_lines("journal-reordered", &lines);
+
+        // "c" arrives where "b" should be, and "b" then comes too late to be trusted
+        let transcript = load_transcript("journal-reordered").unwrap();
+        assert_eq!(texts(&transcript), ["a", "c"]);
+        assert_eq!(transcript.missing_lines, 2);
+    }
+
+    #[test]
+    fn damaged_sealed_line_is_reported_missing() {
+        let mut lines = sealed_journal("journal-damaged");
+        lines[2] = lines[2].replacen("enc2:2:", "enc2:2:00", 1);
+        write_journal_lines("journal-damaged", &lines);
+
+        let transcript = load_transcript("journal-damaged").unwrap();
+        assert_eq!(texts(&transcript), ["a", "c"]);
+        assert_eq!(transcript.missing_lines, 1);
+    }
+
+    #[test]
+    fn plain_line_injected_into_a_sealed_journal_is_rejected() {
+        vault::use_test_key();
+        let journal = SessionJournal::create("journal-injected").unwrap();
+        journal.append_line("first", 0.0, 1.0, "mic").unwrap();
+        journal.append_line("second", 1.0, 2.0, "mic").unwrap();
+        drop(journal);
+
+        // Takes the place of "second", which is then still read at its own position
+        let mut lines = journal_lines("journal-injected");
+        lines.insert(2, plain_line(1, "forged"));
+        write_journal_lines("journal-injected", &lines);
+
+        let transcript = load_transcript("journal-injected").unwrap();
+        assert_eq!(texts(&transcript), ["first", "second"]);
+        assert_eq!(transcript.missing_lines, 1);
+    }
+
+    #[test]
+    fn plain_lines_before_encryption_was_turned_on_are_kept() {
+        let _lock = lock();
+        let journal = SessionJournal::create("journal-plain-prefix").unwrap();
+        journal.append_line("plain", 0.0, 1.0, "mic").unwrap();
+        drop(journal);
+
+        vault::use_test_key();
+        let journal = SessionJournal::open("journal-plain-prefix").unwrap();
+        journal.append_line("sealed", 1.0, 2.0, "mic").unwrap();
+        drop(journal);
+
+        let transcript = load_transcript("journal-plain-prefix").unwrap();
+        assert_eq!(texts(&transcript), ["plain", "sealed"]);
+        assert_eq!(transcript.missing_lines, 0);
+
+        // A plain line added in front of the sealed ones pushes them out of position
+        let mut lines = journal_lines("journal-plain-prefix");
+        lines.insert(1, plain_line(5, "forged"));
+        write_journal_lines("journal-plain-prefix", &lines);
+        let transcript = load_transcript("journal-plain-prefix").unwrap();
+        assert!(texts(&transcript).contains(&"sealed"));
+        assert_eq!(transcript.missing_lines, 1);
+    }
+
+    #[test]
+    fn export_of_the_recording_session_appends_through_its_journal() {
+        vault::use_test_key();
+        let journal = Arc::new(SessionJournal::create("journal-live-export").unwrap());
+        *CURRENT_SESSION.lock().unwrap() = Some(journal.clone());
+        journal.append_line("before export", 0.0, 1.0, "mic").unwrap();
+        mark_exported("journal-live-export", "transcript.txt").unwrap();
+        journal.append_line("after export", 1.0, 2.0, "mic").unwrap();
+        CURRENT_SESSION.lock().unwrap().take();
+
+        let transcript = load_transcript("journal-live-export").unwrap();
+        assert!(transcript.exported);
+        assert_eq!(texts(&transcript), ["before export", "after export"]);
+        assert_eq!(transcript.missing_lines, 0);
+    }
+}
@@ -1,29 +1,67 @@
 use std::fs;
-use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}};
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use once_cell::sync::Lazy;
+use chrono::Utc;
 
 // Declare audio module
+pub mod ask;
 pub mod audio;
+pub mod backend_client;
+pub mod clip;
+pub mod export;
+pub mod extract;
+pub mod import;
+pub mod journal;
+pub mod library;
 pub mod ollama;
+pub mod playback;
+pub mod redact;
+pub mod search;
+pub mod semantic;
+pub mod storage;
+pub mod summary;
+pub mod vault;
+pub mod waveform;
 
+use ask::{AskScope, MeetingAnswer};
 use audio::{
-    default_input_device, default_output_device, AudioStream,
+    default_input_device, default_output_device, AudioFormat, AudioStream, AudioTrack, EncodeError, EncodingProfile, PushOutcome,
+    StreamingEncoder, TrackLayout,
 };
-use tauri::{Runtime, AppHandle, Emitter};
+use audio::decode::{MediaDecoder, TrackSelection};
+use audio::ffmpeg::{FfmpegSettings, FfmpegStatus};
+use export::ExportFormat;
+use extract::Extraction;
+use journal::{Bookmark, SessionJournal, SessionTranscript, RecoveredSession, CURRENT_SESSION};
+use library::{MeetingRecord, MeetingSource};
+use clip::ClipOptions;
+use waveform::Waveform;
+use redact::{AudioRedaction, RedactedAudio, RedactedTranscript, RedactionOptions};
+use search::{SearchHit, SearchQuery};
+use semantic::SemanticHit;
+use storage::{StoragePolicy, StorageReport};
+use summary::{MeetingSummary, SummaryOptions};
+use vault::{EncryptionStatus, KeySource};
+use tauri::{Runtime, AppHandle, Emitter, Manager};
 use log::{info as log_info, error as log_error, debug as log_debug};
 use reqwest::multipart::{Form, Part};
 
 static RECORDING_FLAG: AtomicBool = AtomicBool::new(false);
 static STOPPING_FLAG: AtomicBool = AtomicBool::new(false);
 
-static MIC_BUFFER: Lazy<Arc<Mutex<Vec<f32>>>> = Lazy::new(|| Arc::new(Mutex::new(Vec::new())));
-static SYSTEM_BUFFER: Lazy<Arc<Mutex<Vec<f32>>>> = Lazy::new(|| Arc::new(Mutex::new(Vec::new())));
+// Mixed audio is streamed to ffmpeg as it is captured instead of being buffered until stop
+static RECORDING_ENCODER: Lazy<tokio::sync::Mutex<Option<StreamingEncoder>>> =
+    Lazy::new(|| tokio::sync::Mutex::new(None));
 static MIC_STREAM: Lazy<Mutex<Option<Arc<AudioStream>>>> = Lazy::new(|| Mutex::new(None));
 static SYSTEM_STREAM: Lazy<Mutex<Option<Arc<AudioStream>>>> = Lazy::new(|| Mutex::new(None));
 static IS_RUNNING: Lazy<Arc<AtomicBool>> = Lazy::new(|| Arc::new(AtomicBool::new(false)));
 static RECORDING_START_TIME: Lazy<Mutex<Option<std::time::Instant>>> = Lazy::new(|| Mutex::new(None));
+// Audio captured so far in the current session. Bookmarks use this rather than the wall
+// clock so that stalls and gaps in capture don't shift them against the recording.
+static RECORDED_SAMPLES: AtomicU64 = AtomicU64::new(0);
+static RECORDED_SAMPLE_RATE: AtomicU32 = AtomicU32::new(0);
 const CHUNK_DURATION_MS: u32 = 30000; // 30 seconds per chunk for better sentence processing
 const WHISPER_SAMPLE_RATE: u32 = 16000; // Whisper's required sample rate
 const WAV_SAMPLE_RATE: u32 = 44100; // WAV file sample rate
@@ -38,11 +76,24 @@ struct RecordingArgs {
     save_path: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+struct RecordingError {
+    session_id: String,
+    message: String,
+    error: EncodeError,
+    // Segment the recording continues in after ffmpeg was restarted
+    segment: Option<String>,
+}
+
 #[derive(Debug, Serialize, Clone)]
 struct TranscriptUpdate {
     text: String,
     timestamp: String,
     source: String,
+    // Session-relative seconds, plus the journal sequence once persisted
+    start: f32,
+    end: f32,
+    sequence: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -64,6 +115,8 @@ struct TranscriptAccumulator {
     sentence_start_time: f32,
     last_update_time: std::time::Instant,
     last_segment_hash: u64,
+    chunk_offset: f32,
+    sentence_start_offset: f32,
 }
 
 impl TranscriptAccumulator {
@@ -73,9 +126,16 @@ impl TranscriptAccumulator {
             sentence_start_time: 0.0,
             last_update_time: std::time::Instant::now(),
             last_segment_hash: 0,
+            chunk_offset: 0.0,
+            sentence_start_offset: 0.0,
         }
     }
 
+    // Segment times from the whisper server are relative to the chunk they came from
+    fn set_chunk_offset(&mut self, offset_secs: f32) {
+        self.chunk_offset = offset_secs;
+    }
+
     fn add_segment(&mut self, segment: &TranscriptSegment) -> Option<TranscriptUpdate> {
         log_info!("Processing new transcript segment: {:?}", segment);
         
@@ -115,6 +175,7 @@ impl TranscriptAccumulator {
         // If this is the start of a new sentence, store the start time
         if self.current_sentence.is_empty() {
             self.sentence_start_time = segment.t0;
+            self.sentence_start_offset = self.chunk_offset;
         }
 
         // Add the new text with proper spacing
@@ -130,6 +191,9 @@ impl TranscriptAccumulator {
                 text: sentence.trim().to_string(),
                 timestamp: format!("{:.1} - {:.1}", self.sentence_start_time, segment.t1),
                 source: "Mixed Audio".to_string(),
+                start: self.sentence_start_offset + self.sentence_start_time,
+                end: self.chunk_offset + segment.t1,
+                sequence: 0,
             };
             log_info!("Generated transcript update: {:?}", update);
             Some(update)
@@ -139,14 +203,25 @@ impl TranscriptAccumulator {
     }
 
     fn check_timeout(&mut self) -> Option<TranscriptUpdate> {
-        if !self.current_sentence.is_empty() && 
-           self.last_update_time.elapsed() > Duration::from_millis(SENTENCE_TIMEOUT_MS) {
+        if self.last_update_time.elapsed() > Duration::from_millis(SENTENCE_TIMEOUT_MS) {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    // Emits any pending sentence regardless of the silence timeout, e.g. at the end of an import
+    fn flush(&mut self) -> Option<TranscriptUpdate> {
+        if !self.current_sentence.is_empty() {
             let sentence = std::mem::take(&mut self.current_sentence);
             let current_time = self.sentence_start_time + (SENTENCE_TIMEOUT_MS as f32 / 1000.0);
             let update = TranscriptUpdate {
                 text: sentence.trim().to_string(),
                 timestamp: format!("{:.1} - {:.1}", self.sentence_start_time, current_time),
                 source: "Mixed Audio".to_string(),
+                start: self.sentence_start_offset + self.sentence_start_time,
+                end: self.sentence_start_offset + current_time,
+                sequence: 0,
             };
             Some(update)
         } else {
@@ -155,6 +230,25 @@ impl TranscriptAccumulator {
     }
 }
 
+fn publish_transcript_update<R: Runtime>(
+    app: &AppHandle<R>,
+    journal: &SessionJournal,
+    mut update: TranscriptUpdate,
+) {
+    // Journal first so the line survives even if the window never receives the event
+    match journal.append_line(&update.text, update.start, update.end, &update.source) {
+        Ok(line) => {
+            update.sequence = line.sequence;
+            search::index_line(journal.session_id(), &line);
+        }
+        Err(e) => log_error!("Failed to append transcript line to journal: {}", e),
+    }
+
+    if let Err(e) = app.emit("transcript-update", update) {
+        log_error!("Failed to emit transcript update: {}", e);
+    }
+}
+
 async fn send_audio_chunk(chunk: Vec<f32>, client: &reqwest::Client) -> Result<TranscriptResponse, String> {
     log_debug!("Preparing to send audio chunk of size: {}", chunk.len());
     
@@ -213,7 +307,7 @@ async fn send_audio_chunk(chunk: Vec<f32>, client: &reqwest::Client) -> Result<T
 }
 
 #[tauri::command]
-async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+async fn start_recording<R: Runtime>(app: AppHandle<R>, profile: Option<EncodingProfile>) -> Result<String, String> {
     log_info!("Attempting to start recording...");
     
     if is_recording() {
@@ -228,11 +322,6 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
     // Store recording start time
     *RECORDING_START_TIME.lock().unwrap() = Some(std::time::Instant::now());
 
-    // Initialize audio buffers
-    MIC_BUFFER.lock().unwrap().clear();
-    SYSTEM_BUFFER.lock().unwrap().clear();
-    log_info!("Initialized audio buffers");
-    
     // Get default devices
     let mic_device = Arc::new(default_input_device().map_err(|e| {
         log_error!("Failed to get default input device: {}", e);
@@ -265,6 +354,45 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
         })?;
     let system_stream = Arc::new(system_stream);
 
+    // The profile given for this session, or the one from settings, limited to what
+    // can be written without ffmpeg when it is missing. Looking ffmpeg up may probe
+    // binaries or download one, so it runs off the async runtime; the encoder then
+    // reuses the resolved binary.
+    let profile = match profile {
+        Some(profile) => profile,
+        None => audio::profile::load_default_profile().unwrap_or_else(|e| {
+            log_error!("Failed to load encoding profile, using defaults: {}", e);
+            EncodingProfile::default()
+        }),
+    };
+    let profile = tokio::task::spawn_blocking(move || audio::available_profile(&profile, true))
+        .await
+        .map_err(|e| {
+            log_error!("ffmpeg lookup failed: {}", e);
+            RECORDING_FLAG.store(false, Ordering::SeqCst);
+            format!("ffmpeg lookup failed: {}", e)
+        })?;
+
+    // Open the transcript journal and library entry for this session
+    let session_id = journal::generate_session_id();
+    let session_journal = Arc::new(SessionJournal::create(&session_id).map_err(|e| {
+        log_error!("Failed to create transcript journal: {}", e);
+        RECORDING_FLAG.store(false, Ordering::SeqCst);
+        e.to_string()
+    })?);
+    let mut meeting = MeetingRecord::new(
+        &session_id,
+        library::default_title(Utc::now()),
+        MeetingSource::Recording,
+    );
+    meeting.devices = vec![mic_device.to_string(), system_device.to_string()];
+    meeting.track_layout = profile.track_layout();
+    if let Err(e) = library::create(meeting) {
+        log_error!("Failed to add session {} to the library: {}", session_id, e);
+    }
+    *CURRENT_SESSION.lock().unwrap() = Some(session_journal.clone());
+    log_info!("Started session {}", session_id);
+
     *MIC_STREAM.lock().unwrap() = Some(mic_stream.clone());
     *SYSTEM_STREAM.lock().unwrap() = Some(system_stream.clone());
     IS_RUNNING.store(true, Ordering::SeqCst);
@@ -282,7 +410,7 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
     // Create debug directory for chunks in temp
     let temp_dir = std::env::temp_dir();
     log_info!("System temp directory: {:?}", temp_dir);
-    let debug_dir = temp_dir.join("meeting_minutes_debug");
+    let debug_dir = storage::debug_dir();
     log_info!("Full debug directory path: {:?}", debug_dir);
     
     // Create directory and check if it exists
@@ -307,21 +435,36 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
     let _device_name = mic_stream.device.to_string();
     let sample_rate = device_config.sample_rate().0;
     let channels = device_config.channels();
+    RECORDED_SAMPLES.store(0, Ordering::SeqCst);
+    RECORDED_SAMPLE_RATE.store(sample_rate, Ordering::SeqCst);
+    waveform::start_live(&session_id, sample_rate);
+
+    // Audio is encoded to the session directory while recording
+    let recording_path = profile.output_path(&journal::session_dir(&session_id).join("recording"));
+    let track_layout = profile.track_layout();
+    let encoder_channels = profile.input_channels();
+    match StreamingEncoder::start(&recording_path, sample_rate, encoder_channels, profile) {
+        Ok(encoder) => *RECORDING_ENCODER.lock().await = Some(encoder),
+        Err(e) => {
+            log_error!("Failed to start recording encoder: {}", e);
+            emit_recording_error(&app, &session_id, e, None);
+        }
+    }
+    let encoder_session_id = session_id.clone();
     
     tokio::spawn(async move {
         let chunk_samples = (WHISPER_SAMPLE_RATE as f32 * (CHUNK_DURATION_MS as f32 / 1000.0)) as usize;
         let min_samples = (WHISPER_SAMPLE_RATE as f32 * (MIN_CHUNK_DURATION_MS as f32 / 1000.0)) as usize;
         let mut current_chunk: Vec<f32> = Vec::with_capacity(chunk_samples);
         let mut last_chunk_time = std::time::Instant::now();
+        let mut samples_sent: usize = 0;
         
         log_info!("Mic config: {} Hz, {} channels", sample_rate, channels);
         
         while is_running.load(Ordering::SeqCst) {
             // Check for timeout on current sentence
             if let Some(update) = accumulator.check_timeout() {
-                if let Err(e) = app_handle.emit("transcript-update", update) {
-                    log_error!("Failed to send timeout transcript update: {}", e);
-                }
+                publish_transcript_update(&app_handle, &session_journal, update);
             }
 
             // Collect audio samples
@@ -334,13 +477,7 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
             while let Ok(chunk) = mic_receiver_clone.try_recv() {
                 got_mic_samples = true;
                 log_debug!("Received {} mic samples", chunk.len());
-                let chunk_clone = chunk.clone();
                 mic_samples.extend(chunk);
-                
-                // Store in global buffer
-                if let Ok(mut guard) = MIC_BUFFER.try_lock() {
-                    guard.extend(chunk_clone);
-                }
             }
             // If we didn't get any samples, try to resubscribe to clear any backlog
             if !got_mic_samples {
@@ -353,13 +490,7 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
             while let Ok(chunk) = system_receiver.try_recv() {
                 got_system_samples = true;
                 log_debug!("Received {} system samples", chunk.len());
-                let chunk_clone = chunk.clone();
                 system_samples.extend(chunk);
-                
-                // Store in global buffer
-                if let Ok(mut guard) = SYSTEM_BUFFER.try_lock() {
-                    guard.extend(chunk_clone);
-                }
             }
             // If we didn't get any samples, try to resubscribe to clear any backlog
             if !got_system_samples {
@@ -377,6 +508,24 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
             }
             
             log_debug!("Mixed {} samples", new_samples.len());
+            RECORDED_SAMPLES.fetch_add(new_samples.len() as u64, Ordering::SeqCst);
+            waveform::push_live(&new_samples, &mic_samples, &system_samples);
+
+            if !new_samples.is_empty() {
+                // Separate tracks get mic and system interleaved as a stereo pair
+                let encoder_samples = match track_layout {
+                    TrackLayout::Mixed => new_samples.clone(),
+                    TrackLayout::StereoSplit | TrackLayout::MultiTrack => (0..max_len)
+                        .flat_map(|i| {
+                            [
+                                mic_samples.get(i).copied().unwrap_or(0.0),
+                                system_samples.get(i).copied().unwrap_or(0.0),
+                            ]
+                        })
+                        .collect(),
+                };
+                push_recording_samples(&app_handle, &encoder_session_id, encoder_samples).await;
+            }
             
             // Add samples to current chunk
             for sample in new_samples {
@@ -393,6 +542,8 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
                 let chunk_to_send = current_chunk.clone();
                 current_chunk.clear();
                 last_chunk_time = std::time::Instant::now();
+                accumulator.set_chunk_offset(samples_sent as f32 / sample_rate as f32);
+                samples_sent += chunk_to_send.len();
                 
                 // Save debug chunks
                 let chunk_num = chunk_counter_clone.fetch_add(1, Ordering::SeqCst);
@@ -514,10 +665,7 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
                                      segment.text.trim(), segment.t0, segment.t1);
                             // Add segment to accumulator and check for complete sentence
                             if let Some(update) = accumulator.add_segment(&segment) {
-                                // Emit the update
-                                if let Err(e) = app_handle.emit("transcript-update", update) {
-                                    log_error!("Failed to emit transcript update: {}", e);
-                                }
+                                publish_transcript_update(&app_handle, &session_journal, update);
                             }
                         }
                     }
@@ -532,19 +680,17 @@ async fn start_recording<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
         
         // Emit any remaining transcript when recording stops
         if let Some(update) = accumulator.check_timeout() {
-            if let Err(e) = app_handle.emit("transcript-update", update) {
-                log_error!("Failed to send final transcript update: {}", e);
-            }
+            publish_transcript_update(&app_handle, &session_journal, update);
         }
         
         log_info!("Transcription task ended");
     });
     
-    Ok(())
+    Ok(session_id)
 }
 
 #[tauri::command]
-async fn stop_recording(args: RecordingArgs) -> Result<(), String> {
+async fn stop_recording<R: Runtime>(app: AppHandle<R>, args: RecordingArgs) -> Result<(), String> {
     log_info!("Attempting to stop recording...");
     
     if STOPPING_FLAG.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
@@ -631,21 +777,6 @@ async fn stop_recording(args: RecordingArgs) -> Result<(), String> {
     if !stop_errors.is_empty() {
         log_error!("Some streams failed to stop cleanly: {:?}", stop_errors);
     }
-    let _mic_data = match MIC_BUFFER.try_lock() {
-        Ok(guard) => guard.clone(),
-        Err(e) => {
-            log_error!("Failed to lock mic buffer during cleanup: {}", e);
-            Vec::new()
-        }
-    };
-    
-    let _system_data = match SYSTEM_BUFFER.try_lock() {
-        Ok(guard) => guard.clone(),
-        Err(e) => {
-            log_error!("Failed to lock system buffer during cleanup: {}", e);
-            Vec::new()
-        }
-    };
     
     if let Some(parent) = std::path::Path::new(&args.save_path).parent() {
         if !parent.exists() {
@@ -656,12 +787,35 @@ async fn stop_recording(args: RecordingArgs) -> Result<(), String> {
             }
         }
     }
-    
-    if let Ok(mut buffer) = MIC_BUFFER.try_lock() {
-        buffer.clear();
-    }
-    if let Ok(mut buffer) = SYSTEM_BUFFER.try_lock() {
-        buffer.clear();
+
+    let session_id = current_session_id();
+    if let Some(encoder) = RECORDING_ENCODER.lock().await.take() {
+        let finished = encoder.finish().await;
+        if let Some(error) = finished.error {
+            log_error!("Recording encoder finished with an error: {}", error);
+            emit_recording_error(&app, session_id.as_deref().unwrap_or_default(), error, None);
+        }
+        let segments = move_recording_segments(finished.segments, &args.save_path);
+        // With encryption on the recording was sealed as it was written, and ffmpeg
+        // can't read it to add tags
+        let sealed = segments.iter().any(|segment| vault::is_encrypted_file(segment).unwrap_or(false));
+        if let (Some(session_id), false) = (&session_id, sealed) {
+            if let Err(e) = embed_recording_metadata_in(app.package_info(), session_id, &segments) {
+                log_error!("Failed to embed metadata in the recording: {}", e);
+            }
+        }
+        for segment in &segments {
+            // Covers encryption being turned on mid-recording
+            if let Err(e) = vault::encrypt_file_in_place(segment) {
+                log_error!("Failed to encrypt recording {:?}: {}", segment, e);
+            }
+            if let Some(session_id) = &session_id {
+                if let Err(e) = library::add_audio_file(session_id, segment) {
+                    log_error!("Failed to add {:?} to the library: {}", segment, e);
+                }
+            }
+        }
+        log_info!("Saved recording to {:?}", segments);
     }
     if let Ok(mut stream) = MIC_STREAM.try_lock() {
         *stream = None;
@@ -673,6 +827,15 @@ async fn stop_recording(args: RecordingArgs) -> Result<(), String> {
     if let Ok(mut time) = RECORDING_START_TIME.try_lock() {
         *time = None;
     }
+    waveform::stop_live();
+    if let Some(session) = CURRENT_SESSION.lock().unwrap().take() {
+        if let Err(e) = session.finish() {
+            log_error!("Failed to close transcript journal for {}: {}", session.session_id(), e);
+        }
+        if let Err(e) = library::finish(session.session_id()) {
+            log_error!("Failed to update library entry for {}: {}", session.session_id(), e);
+        }
+    }
     
     log_info!("Recording stopped and cleaned up successfully");
     
@@ -681,21 +844,224 @@ async fn stop_recording(args: RecordingArgs) -> Result<(), String> {
     Ok(())
 }
 
+fn emit_recording_error<R: Runtime>(app: &AppHandle<R>, session_id: &str, error: EncodeError, segment: Option<String>) {
+    let error = RecordingError {
+        session_id: session_id.to_string(),
+        message: error.to_string(),
+        error,
+        segment,
+    };
+    if let Err(e) = app.emit("recording-error", error) {
+        log_error!("Failed to emit recording error: {}", e);
+    }
+}
+
+async fn push_recording_samples<R: Runtime>(app: &AppHandle<R>, session_id: &str, samples: Vec<f32>) {
+    let mut guard = RECORDING_ENCODER.lock().await;
+    // Once the encoder has given up the remaining audio is dropped until stop
+    let Some(encoder) = guard.as_mut().filter(|encoder| encoder.is_running()) else {
+        return;
+    };
+    match encoder.push(samples).await {
+        Ok(PushOutcome::Written) => {}
+        Ok(PushOutcome::Restarted { error, segment }) => {
+            log_error!("Recording encoder restarted into {:?}: {}", segment, error);
+            emit_recording_error(app, session_id, error, Some(segment.to_string_lossy().into_owned()));
+        }
+        Err(e) => {
+            // What was written so far is kept and saved on stop; the transcript carries on
+            log_error!("Recording encoder stopped: {}", e);
+            emit_recording_error(app, session_id, e, None);
+        }
+    }
+}
+
+// Chapter times are relative to the session start, which only lines up with the file
+// when the encoder was never restarted
+fn embed_recording_metadata_in(
+    package: &tauri::PackageInfo,
+    session_id: &str,
+    files: &[std::path::PathBuf],
+) -> anyhow::Result<()> {
+    let mut metadata = library::recording_metadata(session_id, &package.name, &package.version.to_string())?;
+    if files.len() > 1 {
+        metadata.chapters.clear();
+    }
+    for file in files {
+        if vault::is_encrypted_file(file)? {
+            return Err(anyhow::anyhow!("{:?} is encrypted; metadata can only be embedded before encryption", file));
+        }
+        audio::metadata::embed(file, &metadata)?;
+    }
+    Ok(())
+}
+
+// Moves the session recording to the path the user chose; later segments from an
+// encoder restart go alongside it as `name.partN.ext`. The extension follows the
+// encoding profile rather than the one in `save_path`.
+fn move_recording_segments(segments: Vec<std::path::PathBuf>, save_path: &str) -> Vec<std::path::PathBuf> {
+    if save_path.is_empty() {
+        return segments;
+    }
+    let save_path = std::path::Path::new(save_path);
+    let stem = save_path.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+
+    segments
+        .into_iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            let extension = segment.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            let destination = if index == 0 {
+                save_path.with_extension(extension)
+            } else {
+                save_path.with_file_name(format!("{}.part{}.{}", stem, index + 1, extension))
+            };
+            // Rename fails across filesystems, so fall back to copying
+            let moved = fs::rename(&segment, &destination)
+                .or_else(|_| fs::copy(&segment, &destination).and_then(|_| fs::remove_file(&segment)));
+            match moved {
+                Ok(()) => destination,
+                Err(e) => {
+                    log_error!("Failed to move recording to {:?}, keeping {:?}: {}", destination, segment, e);
+                    segment
+                }
+            }
+        })
+        .collect()
+}
+
 #[tauri::command]
 fn is_recording() -> bool {
     RECORDING_FLAG.load(Ordering::SeqCst)
 }
 
+// Loads the whole file; the player streams recordings through `playback::SCHEME` instead
 #[tauri::command]
 fn read_audio_file(file_path: String) -> Result<Vec<u8>, String> {
-    match std::fs::read(&file_path) {
+    // Recordings made with encryption on are decrypted here; plain files pass through
+    match vault::read(&file_path) {
         Ok(data) => Ok(data),
         Err(e) => Err(format!("Failed to read audio file: {}", e))
     }
 }
 
+// Decodes the mic or system side of a session recording, joining the segments of a
+// recording that was split by an encoder restart
+fn decode_audio_track(session_id: &str, track: AudioTrack) -> anyhow::Result<(Vec<f32>, u32)> {
+    let meeting = library::get(session_id)?;
+    let selection = TrackSelection::for_track(meeting.track_layout, track)?;
+    let mut samples = Vec::new();
+    let mut sample_rate = None;
+    for path in meeting.audio_files.iter().map(std::path::Path::new).filter(|p| p.is_file()) {
+        let mut decoder = MediaDecoder::open_track(path, selection)?;
+        sample_rate.get_or_insert(decoder.sample_rate());
+        while let Some(block) = decoder.next_block()? {
+            samples.extend(block);
+        }
+    }
+    let sample_rate = sample_rate.ok_or_else(|| anyhow::anyhow!("Session {} has no audio file", session_id))?;
+    Ok((samples, sample_rate))
+}
+
+// Like `read_audio_file`, but returns one side of the recording as WAV
+#[tauri::command]
+async fn read_audio_track(session_id: String, track: AudioTrack) -> Result<Vec<u8>, String> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<u8>> {
+        let (samples, sample_rate) = decode_audio_track(&session_id, track)?;
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        // Built in memory so a decrypted copy never lands on disk
+        let mut wav = std::io::Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut wav, spec)?;
+        for sample in samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+        Ok(wav.into_inner())
+    })
+    .await
+    .map_err(|e| format!("Audio track task failed: {}", e))?
+    .map_err(|e| format!("Failed to read audio track: {}", e))
+}
+
+#[tauri::command]
+async fn export_audio_track(session_id: String, track: AudioTrack, path: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let (samples, sample_rate) = decode_audio_track(&session_id, track)?;
+        let path = std::path::Path::new(&path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let profile = EncodingProfile::for_path(path);
+        let written = audio::encode_audio(bytemuck::cast_slice(&samples), sample_rate, 1, path, &profile)?;
+        log_info!("Exported {:?} track of session {} to {:?}", track, session_id, written);
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Audio track task failed: {}", e))?
+    .map_err(|e| format!("Failed to export audio track: {}", e))
+}
+
+#[tauri::command]
+async fn export_clip(
+    session_id: String,
+    start: Option<f32>,
+    end: Option<f32>,
+    format: Option<AudioFormat>,
+    path: String,
+    options: Option<ClipOptions>,
+) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        clip::export_clip(&session_id, start, end, format, std::path::Path::new(&path), &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Clip task failed: {}", e))?
+    .map(|written| written.to_string_lossy().into_owned())
+    .map_err(|e| format!("Failed to export clip: {}", e))
+}
+
+#[tauri::command]
+async fn get_waveform(session_id: String, buckets: usize, track: Option<AudioTrack>) -> Result<Waveform, String> {
+    tokio::task::spawn_blocking(move || waveform::get_waveform(&session_id, buckets, track))
+        .await
+        .map_err(|e| format!("Waveform task failed: {}", e))?
+        .map_err(|e| format!("Failed to load waveform: {}", e))
+}
+
+// Re-tags a finished recording, e.g. once extraction has produced chapters
 #[tauri::command]
-async fn save_transcript(file_path: String, content: String) -> Result<(), String> {
+async fn embed_recording_metadata<R: Runtime>(app: AppHandle<R>, session_id: String) -> Result<(), String> {
+    let package = app.package_info().clone();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let meeting = library::get(&session_id)?;
+        // An imported original belongs to the user and is left as it is
+        let source = match &meeting.source {
+            MeetingSource::Import { path } => Some(path.clone()),
+            MeetingSource::Recording => None,
+        };
+        let files: Vec<std::path::PathBuf> = meeting
+            .audio_files
+            .iter()
+            .filter(|path| Some(*path) != source.as_ref())
+            .map(std::path::PathBuf::from)
+            .filter(|path| path.is_file())
+            .collect();
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("Session {} has no recording", session_id));
+        }
+        embed_recording_metadata_in(&package, &session_id, &files)
+    })
+    .await
+    .map_err(|e| format!("Metadata task failed: {}", e))?
+    .map_err(|e| format!("Failed to embed recording metadata: {}", e))
+}
+
+#[tauri::command]
+async fn save_transcript(file_path: String, content: String, session_id: Option<String>) -> Result<(), String> {
     log::info!("Saving transcript to: {}", file_path);
 
     // Ensure parent directory exists
@@ -710,10 +1076,390 @@ async fn save_transcript(file_path: String, content: String) -> Result<(), Strin
     std::fs::write(&file_path, content)
         .map_err(|e| format!("Failed to write transcript: {}", e))?;
 
+    if let Some(session_id) = session_id {
+        journal::mark_exported(&session_id, &file_path)
+            .map_err(|e| format!("Failed to record export in journal: {}", e))?;
+    }
+
     log::info!("Transcript saved successfully");
     Ok(())
 }
 
+#[tauri::command]
+fn load_session_transcript(session_id: String) -> Result<SessionTranscript, String> {
+    journal::load_transcript(&session_id)
+        .map_err(|e| format!("Failed to load transcript for session {}: {}", session_id, e))
+}
+
+#[tauri::command]
+async fn export_transcript(session_id: String, format: ExportFormat, path: String) -> Result<(), String> {
+    log_info!("Exporting session {} as {:?} to {}", session_id, format, path);
+    export::export_session(&session_id, format, std::path::Path::new(&path))
+        .map_err(|e| format!("Failed to export transcript: {}", e))
+}
+
+#[tauri::command]
+async fn import_media<R: Runtime>(app: AppHandle<R>, path: String) -> Result<String, String> {
+    if is_recording() {
+        return Err("Cannot import while recording is in progress".to_string());
+    }
+
+    import::import_media(&app, std::path::Path::new(&path))
+        .await
+        .map_err(|e| {
+            log_error!("Failed to import {}: {}", path, e);
+            format!("Failed to import media: {}", e)
+        })
+}
+
+#[tauri::command]
+async fn generate_summary<R: Runtime>(
+    app: AppHandle<R>,
+    session_id: String,
+    model: String,
+    context_tokens: Option<usize>,
+    ollama_url: Option<String>,
+) -> Result<MeetingSummary, String> {
+    let client = ollama::client_for(ollama_url);
+    let options = SummaryOptions::new(model, context_tokens);
+
+    summary::summarize_session(&client, &session_id, &options, |progress| {
+        if let Err(e) = app.emit("summary-progress", progress) {
+            log_error!("Failed to emit summary progress: {}", e);
+        }
+    })
+    .await
+    .map_err(|e| {
+        log_error!("Failed to summarize session {}: {}", session_id, e);
+        format!("Failed to generate summary: {}", e)
+    })
+}
+
+#[tauri::command]
+async fn extract_action_items(
+    session_id: String,
+    model: String,
+    context_tokens: Option<usize>,
+    ollama_url: Option<String>,
+) -> Result<Extraction, String> {
+    let client = ollama::client_for(ollama_url);
+    extract::extract_session(&client, &session_id, &model, context_tokens)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to extract action items for session {}: {}", session_id, e);
+            format!("Failed to extract action items: {}", e)
+        })
+}
+
+#[tauri::command]
+fn get_session_extraction(session_id: String) -> Result<Option<Extraction>, String> {
+    extract::load_extraction(&session_id)
+        .map_err(|e| format!("Failed to load extraction for session {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn get_session_summary(session_id: String) -> Result<Option<MeetingSummary>, String> {
+    summary::load_summary(&session_id)
+        .map_err(|e| format!("Failed to load summary for session {}: {}", session_id, e))
+}
+
+#[tauri::command]
+async fn search_transcripts(query: SearchQuery) -> Result<Vec<SearchHit>, String> {
+    // The first search builds the index from every journal, so keep it off the async workers
+    tokio::task::spawn_blocking(move || search::search(&query))
+        .await
+        .map_err(|e| format!("Search task failed: {}", e))?
+        .map_err(|e| format!("Search failed: {}", e))
+}
+
+#[tauri::command]
+async fn semantic_search(
+    query: String,
+    model: Option<String>,
+    session_ids: Option<Vec<String>>,
+    limit: Option<usize>,
+    ollama_url: Option<String>,
+) -> Result<Vec<SemanticHit>, String> {
+    let client = ollama::client_for(ollama_url);
+    let model = model.unwrap_or_else(|| semantic::DEFAULT_EMBEDDING_MODEL.to_string());
+    semantic::semantic_search(&client, &query, &model, session_ids, limit)
+        .await
+        .map_err(|e| format!("Semantic search failed: {}", e))
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct AskToken {
+    request_id: Option<String>,
+    token: String,
+}
+
+#[tauri::command]
+async fn ask_meetings<R: Runtime>(
+    app: AppHandle<R>,
+    question: String,
+    scope: Option<AskScope>,
+    model: String,
+    embedding_model: Option<String>,
+    request_id: Option<String>,
+    ollama_url: Option<String>,
+) -> Result<MeetingAnswer, String> {
+    let client = ollama::client_for(ollama_url);
+    let embedding_model =
+        embedding_model.unwrap_or_else(|| semantic::DEFAULT_EMBEDDING_MODEL.to_string());
+
+    ask::ask_meetings(
+        &client,
+        &question,
+        scope.unwrap_or_default(),
+        &model,
+        &embedding_model,
+        |token| {
+            let event = AskToken {
+                request_id: request_id.clone(),
+                token: token.to_string(),
+            };
+            if let Err(e) = app.emit("ask-meetings-token", event) {
+                log_error!("Failed to emit answer token: {}", e);
+            }
+        },
+    )
+    .await
+    .map_err(|e| format!("Failed to answer question: {}", e))
+}
+
+fn current_session_id() -> Option<String> {
+    CURRENT_SESSION
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|session| session.session_id().to_string())
+}
+
+// Seconds of audio recorded so far in the current session
+fn recorded_offset() -> f32 {
+    let rate = RECORDED_SAMPLE_RATE.load(Ordering::SeqCst);
+    if rate == 0 {
+        return 0.0;
+    }
+    RECORDED_SAMPLES.load(Ordering::SeqCst) as f32 / rate as f32
+}
+
+#[tauri::command]
+fn add_bookmark<R: Runtime>(app: AppHandle<R>, session_id: String, label: String) -> Result<Bookmark, String> {
+    let session = CURRENT_SESSION.lock().unwrap().clone();
+    let session = session
+        .filter(|session| session.session_id() == session_id)
+        .ok_or_else(|| format!("Session {} is not recording", session_id))?;
+    let bookmark = session
+        .append_bookmark(&label, recorded_offset())
+        .map_err(|e| format!("Failed to add bookmark: {}", e))?;
+    log_info!("Bookmarked session {} at {:.1}s", session_id, bookmark.offset);
+    if let Err(e) = app.emit("bookmark-added", &bookmark) {
+        log_error!("Failed to emit bookmark: {}", e);
+    }
+    Ok(bookmark)
+}
+
+#[tauri::command]
+fn list_meetings() -> Result<Vec<MeetingRecord>, String> {
+    library::list().map_err(|e| format!("Failed to list meetings: {}", e))
+}
+
+#[tauri::command]
+fn get_meeting(session_id: String) -> Result<MeetingRecord, String> {
+    library::get(&session_id).map_err(|e| format!("Failed to load meeting {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn refresh_meeting(session_id: String) -> Result<MeetingRecord, String> {
+    library::refresh(&session_id).map_err(|e| format!("Failed to refresh meeting {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn rename_meeting(session_id: String, title: String) -> Result<MeetingRecord, String> {
+    library::rename(&session_id, &title)
+        .map_err(|e| format!("Failed to rename meeting {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn tag_meeting(session_id: String, tag: String) -> Result<MeetingRecord, String> {
+    library::add_tag(&session_id, &tag)
+        .map_err(|e| format!("Failed to tag meeting {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn untag_meeting(session_id: String, tag: String) -> Result<MeetingRecord, String> {
+    library::remove_tag(&session_id, &tag)
+        .map_err(|e| format!("Failed to untag meeting {}: {}", session_id, e))
+}
+
+#[tauri::command]
+fn delete_meeting(session_id: String, delete_audio: Option<bool>) -> Result<(), String> {
+    if current_session_id().as_deref() == Some(session_id.as_str()) {
+        return Err("Cannot delete a meeting that is still recording".to_string());
+    }
+    library::delete(&session_id, delete_audio.unwrap_or(false))
+        .map_err(|e| format!("Failed to delete meeting {}: {}", session_id, e))
+}
+
+// Blocking filesystem walk; callers run it off the async runtime
+fn enforce_storage(dry_run: bool) -> anyhow::Result<StorageReport> {
+    storage::enforce(dry_run, current_session_id().as_deref())
+}
+
+#[tauri::command]
+async fn storage_report() -> Result<StorageReport, String> {
+    tokio::task::spawn_blocking(|| enforce_storage(true))
+        .await
+        .map_err(|e| format!("Storage scan task failed: {}", e))?
+        .map_err(|e| format!("Failed to build storage report: {}", e))
+}
+
+#[tauri::command]
+async fn enforce_storage_policy(dry_run: Option<bool>) -> Result<StorageReport, String> {
+    let dry_run = dry_run.unwrap_or(false);
+    tokio::task::spawn_blocking(move || enforce_storage(dry_run))
+        .await
+        .map_err(|e| format!("Storage cleanup task failed: {}", e))?
+        .map_err(|e| format!("Failed to apply storage policy: {}", e))
+}
+
+#[tauri::command]
+fn get_storage_policy() -> Result<StoragePolicy, String> {
+    storage::load_policy().map_err(|e| format!("Failed to load storage policy: {}", e))
+}
+
+#[tauri::command]
+fn set_storage_policy(policy: StoragePolicy) -> Result<(), String> {
+    storage::save_policy(&policy).map_err(|e| format!("Failed to save storage policy: {}", e))
+}
+
+#[tauri::command]
+fn get_encoding_profile() -> Result<EncodingProfile, String> {
+    audio::profile::load_default_profile().map_err(|e| format!("Failed to load encoding profile: {}", e))
+}
+
+#[tauri::command]
+fn set_encoding_profile(profile: EncodingProfile) -> Result<(), String> {
+    audio::profile::save_default_profile(&profile).map_err(|e| format!("Failed to save encoding profile: {}", e))
+}
+
+#[tauri::command]
+async fn ffmpeg_status(refresh: Option<bool>) -> Result<FfmpegStatus, String> {
+    tokio::task::spawn_blocking(move || {
+        if refresh.unwrap_or(false) {
+            audio::ffmpeg::refresh();
+        }
+        audio::ffmpeg::status()
+    })
+    .await
+    .map_err(|e| format!("ffmpeg lookup failed: {}", e))
+}
+
+#[tauri::command]
+fn get_ffmpeg_settings() -> Result<FfmpegSettings, String> {
+    audio::ffmpeg::load_settings().map_err(|e| format!("Failed to load ffmpeg settings: {}", e))
+}
+
+#[tauri::command]
+fn set_ffmpeg_settings(settings: FfmpegSettings) -> Result<(), String> {
+    audio::ffmpeg::save_settings(&settings).map_err(|e| format!("Failed to save ffmpeg settings: {}", e))
+}
+
+#[tauri::command]
+async fn redact_transcript(
+    session_id: String,
+    options: Option<RedactionOptions>,
+    format: Option<ExportFormat>,
+    path: Option<String>,
+) -> Result<RedactedTranscript, String> {
+    let redacted = redact::redact_session(&session_id, &options.unwrap_or_default())
+        .await
+        .map_err(|e| format!("Failed to redact session {}: {}", session_id, e))?;
+    if let Some(path) = path {
+        redact::export_redacted_transcript(&redacted, format.unwrap_or(ExportFormat::Text), std::path::Path::new(&path))
+            .map_err(|e| format!("Failed to write redacted transcript: {}", e))?;
+    }
+    Ok(redacted)
+}
+
+#[tauri::command]
+async fn redact_audio(
+    session_id: String,
+    options: Option<RedactionOptions>,
+    mode: Option<AudioRedaction>,
+    path: String,
+) -> Result<RedactedAudio, String> {
+    let redacted = redact::redact_session(&session_id, &options.unwrap_or_default())
+        .await
+        .map_err(|e| format!("Failed to redact session {}: {}", session_id, e))?;
+    let spans = redacted.spans;
+    tokio::task::spawn_blocking(move || {
+        redact::export_redacted_audio(&session_id, spans, mode.unwrap_or_default(), std::path::Path::new(&path))
+    })
+    .await
+    .map_err(|e| format!("Audio redaction task failed: {}", e))?
+    .map_err(|e| format!("Failed to write redacted audio: {}", e))
+}
+
+#[tauri::command]
+fn encryption_status() -> Result<EncryptionStatus, String> {
+    vault::status().map_err(|e| format!("Failed to read encryption status: {}", e))
+}
+
+#[tauri::command]
+async fn enable_encryption(key_source: KeySource, passphrase: Option<String>) -> Result<EncryptionStatus, String> {
+    // Key derivation is deliberately slow; keep it off the async runtime
+    tokio::task::spawn_blocking(move || vault::enable(key_source, passphrase.as_deref()))
+        .await
+        .map_err(|e| format!("Encryption task failed: {}", e))?
+        .map_err(|e| format!("Failed to enable encryption: {}", e))
+}
+
+#[tauri::command]
+async fn unlock_encryption(passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || vault::unlock(Some(&passphrase)))
+        .await
+        .map_err(|e| format!("Encryption task failed: {}", e))?
+        .map_err(|e| format!("Failed to unlock encrypted storage: {}", e))?;
+    // Sessions that were unreadable while locked are now searchable
+    search::invalidate();
+    // Recordings a crash left unsealed could not be handled while locked
+    let active = current_session_id();
+    match tokio::task::spawn_blocking(move || journal::recover_recordings(active.as_deref())).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log_error!("Failed to recover recordings: {}", e),
+        Err(e) => log_error!("Recording recovery task failed: {}", e),
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn rekey_encryption(
+    current_passphrase: Option<String>,
+    key_source: KeySource,
+    passphrase: Option<String>,
+) -> Result<EncryptionStatus, String> {
+    tokio::task::spawn_blocking(move || {
+        vault::rekey(current_passphrase.as_deref(), key_source, passphrase.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Encryption task failed: {}", e))?
+    .map_err(|e| format!("Failed to re-key encrypted storage: {}", e))
+}
+
+#[tauri::command]
+fn export_decrypted_file(path: String, destination: String) -> Result<(), String> {
+    vault::export_decrypted(std::path::Path::new(&path), std::path::Path::new(&destination))
+        .map_err(|e| format!("Failed to export decrypted copy of {}: {}", path, e))
+}
+
+#[tauri::command]
+fn list_unexported_sessions() -> Result<Vec<RecoveredSession>, String> {
+    journal::recover_sessions(current_session_id().as_deref())
+        .map_err(|e| format!("Failed to scan session journals: {}", e))
+}
+
 fn stereo_to_mono(stereo: &[i16]) -> Vec<i16> {
     let mut mono = Vec::with_capacity(stereo.len() / 2);
     for chunk in stereo.chunks_exact(2) {
@@ -729,9 +1475,58 @@ pub fn run() {
     log::set_max_level(log::LevelFilter::Info);
     
     tauri::Builder::default()
-        .setup(|_app| {
+        .setup(|app| {
             log::info!("Application setup complete");
 
+            // Keyring and file keys unlock without user input; journals can't be read before this
+            vault::auto_unlock();
+
+            // Close journals left open by a crash and tell the UI which transcripts can be restored
+            match journal::recover_sessions(None) {
+                Ok(recovered) if !recovered.is_empty() => {
+                    log::info!("Found {} unexported session(s)", recovered.len());
+                    for session in recovered.iter().filter(|session| session.interrupted) {
+                        if let Err(e) = library::finish(&session.session_id) {
+                            log::error!("Failed to update library entry for {}: {}", session.session_id, e);
+                        }
+                    }
+                    if let Err(e) = app.emit("sessions-recovered", recovered) {
+                        log::error!("Failed to emit recovered sessions: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Failed to recover sessions: {}", e),
+            }
+            if let Err(e) = journal::recover_recordings(None) {
+                log::error!("Failed to recover recordings: {}", e);
+            }
+
+            // Find ffmpeg before the first recording needs it
+            tauri::async_runtime::spawn_blocking(|| {
+                audio::ffmpeg::status();
+            });
+
+            // Replay transcript saves and summary requests queued while the backend was down
+            tauri::async_runtime::spawn(backend_client::run_sync_loop(app.handle().clone()));
+
+            // Apply the retention policy now and periodically; this also clears debug
+            // chunks left behind if the app was killed mid-recording
+            tauri::async_runtime::spawn(async {
+                loop {
+                    match tokio::task::spawn_blocking(|| enforce_storage(false)).await {
+                        Ok(Ok(report)) if !report.deletions.is_empty() => log::info!(
+                            "Storage policy freed {} bytes in {} deletion(s)",
+                            report.freed_bytes,
+                            report.deletions.len()
+                        ),
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => log::error!("Failed to apply storage policy: {}", e),
+                        Err(e) => log::error!("Storage policy task failed: {}", e),
+                    }
+                    tokio::time::sleep(storage::ENFORCE_INTERVAL).await;
+                }
+            });
+
             // Trigger microphone permission request on startup
             if let Err(e) = audio::core::trigger_audio_permission() {
                 log::error!("Failed to trigger audio permission: {}", e);
@@ -739,15 +1534,76 @@ pub fn run() {
 
             Ok(())
         })
+        // Serves recordings with Range support so the player can seek without loading
+        // the whole file over IPC
+        .register_asynchronous_uri_scheme_protocol(playback::SCHEME, |_ctx, request, responder| {
+            tauri::async_runtime::spawn_blocking(move || responder.respond(playback::respond(&request)));
+        })
         .invoke_handler(tauri::generate_handler![
             start_recording,
             stop_recording,
             is_recording,
             read_audio_file,
+            read_audio_track,
+            export_audio_track,
+            export_clip,
+            get_waveform,
+            embed_recording_metadata,
+            add_bookmark,
             save_transcript,
+            load_session_transcript,
+            list_unexported_sessions,
+            export_transcript,
+            import_media,
+            generate_summary,
+            get_session_summary,
+            extract_action_items,
+            get_session_extraction,
+            search_transcripts,
+            semantic_search,
+            ask_meetings,
+            list_meetings,
+            get_meeting,
+            refresh_meeting,
+            rename_meeting,
+            tag_meeting,
+            untag_meeting,
+            delete_meeting,
+            ollama::get_ollama_models,
+            ollama::ollama_status,
+            ollama::pull_ollama_model,
+            ollama::delete_ollama_model,
+            ollama::show_ollama_model,
+            backend_client::sync_save_transcript,
+            backend_client::sync_process_transcript,
+            backend_client::backend_sync_status,
+            backend_client::flush_backend_queue,
+            backend_client::get_backend_summary,
+            backend_client::get_meeting_session,
+            storage_report,
+            enforce_storage_policy,
+            get_storage_policy,
+            set_storage_policy,
+            get_encoding_profile,
+            set_encoding_profile,
+            ffmpeg_status,
+            get_ffmpeg_settings,
+            set_ffmpeg_settings,
+            encryption_status,
+            enable_encryption,
+            unlock_encryption,
+            rekey_encryption,
+            export_decrypted_file,
+            redact_transcript,
+            redact_audio,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                storage::clean_debug_artifacts();
+            }
+        });
 }
 
 fn resample_audio(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
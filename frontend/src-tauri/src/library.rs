@@ -0,0 +1,520 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::metadata::{Chapter, RecordingMetadata};
+use crate::audio::TrackLayout;
+use crate::journal::{self, SessionTranscript};
+use crate::{export, extract, search, summary, waveform};
+
+const MEETING_FILE_NAME: &str = "meeting.json";
+
+// Serializes read-modify-write cycles on meeting.json files
+static LIBRARY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MeetingSource {
+    Recording,
+    Import { path: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeetingRecord {
+    pub session_id: String,
+    pub title: String,
+    pub source: MeetingSource,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub devices: Vec<String>,
+    #[serde(default)]
+    pub audio_files: Vec<String>,
+    // Whether mic and system audio can be separated again in `audio_files`
+    #[serde(default)]
+    pub track_layout: TrackLayout,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub updated_at: DateTime<Utc>,
+    // Taken from the transcript when the session finishes or on `refresh`; reading every
+    // journal to list the library would not scale
+    #[serde(default)]
+    pub line_count: usize,
+    #[serde(default)]
+    pub bookmark_count: usize,
+    // Seconds of audio covered by the transcript
+    #[serde(default)]
+    pub duration: Option<f32>,
+    // Filled in from the session directory on every read, never trusted from disk
+    #[serde(default)]
+    pub transcript_journal: Option<String>,
+    #[serde(default)]
+    pub summary_file: Option<String>,
+    #[serde(default)]
+    pub extraction_file: Option<String>,
+}
+
+impl MeetingRecord {
+    pub fn new(session_id: &str, title: impl Into<String>, source: MeetingSource) -> Self {
+        let now = Utc::now();
+        Self {
+            session_id: session_id.to_string(),
+            title: title.into(),
+            source,
+            started_at: Some(now),
+            ended_at: None,
+            devices: Vec::new(),
+            audio_files: Vec::new(),
+            track_layout: TrackLayout::Mixed,
+            tags: Vec::new(),
+            updated_at: now,
+            line_count: 0,
+            bookmark_count: 0,
+            duration: None,
+            transcript_journal: None,
+            summary_file: None,
+            extraction_file: None,
+        }
+    }
+
+    // Audio files the app wrote for this meeting; the original file of an import
+    // belongs to the user and is never included
+    pub fn owned_audio(&self) -> Vec<PathBuf> {
+        let source = match &self.source {
+            MeetingSource::Import { path } => Some(path.as_str()),
+            MeetingSource::Recording => None,
+        };
+        self.audio_files
+            .iter()
+            .filter(|path| Some(path.as_str()) != source)
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    fn refresh_derived(&mut self) {
+        let existing = |path: PathBuf| path.is_file().then(|| path.to_string_lossy().into_owned());
+
+        self.transcript_journal = existing(journal::journal_path(&self.session_id));
+        self.summary_file = existing(summary::summary_path(&self.session_id));
+        self.extraction_file = existing(extract::extraction_path(&self.session_id));
+    }
+
+    fn apply_transcript(&mut self, transcript: &SessionTranscript) {
+        self.line_count = transcript.lines.len();
+        self.bookmark_count = transcript.bookmarks.len();
+        self.duration = transcript.lines.iter().map(|line| line.end).reduce(f32::max);
+        if self.started_at.is_none() {
+            self.started_at = transcript.started_at;
+        }
+        if self.ended_at.is_none() {
+            self.ended_at = transcript.stopped_at;
+        }
+    }
+}
+
+pub fn default_title(started_at: DateTime<Utc>) -> String {
+    format!("Meeting {}", started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"))
+}
+
+fn record_path(session_id: &str) -> PathBuf {
+    journal::session_dir(session_id).join(MEETING_FILE_NAME)
+}
+
+fn write_record(record: &MeetingRecord) -> Result<()> {
+    let path = record_path(&record.session_id);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Write then rename so a crash never leaves a half-written record
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(record)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn read_record(session_id: &str) -> Result<Option<MeetingRecord>> {
+    let path = record_path(session_id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+}
+
+// Sessions recorded before the library existed only have a journal; synthesize a record for them
+fn record_from_journal(session_id: &str) -> Result<MeetingRecord> {
+    let transcript = journal::load_transcript(session_id)?;
+    let started_at = transcript.started_at.unwrap_or_else(Utc::now);
+    let mut record = MeetingRecord::new(session_id, default_title(started_at), MeetingSource::Recording);
+    record.started_at = transcript.started_at;
+    record.ended_at = transcript.stopped_at;
+    record.apply_transcript(&transcript);
+    Ok(record)
+}
+
+pub fn create(record: MeetingRecord) -> Result<MeetingRecord> {
+    journal::validate_session_id(&record.session_id)?;
+    let _guard = LIBRARY_LOCK.lock().map_err(|_| anyhow!("Library lock poisoned"))?;
+    write_record(&record)?;
+    info!("Added meeting {} to library", record.session_id);
+    Ok(record)
+}
+
+pub fn get(session_id: &str) -> Result<MeetingRecord> {
+    journal::validate_session_id(session_id)?;
+    let mut record = match read_record(session_id)? {
+        Some(record) => record,
+        None if journal::journal_path(session_id).exists() => record_from_journal(session_id)?,
+        None => return Err(anyhow!("Meeting {} not found", session_id)),
+    };
+    record.refresh_derived();
+    Ok(record)
+}
+
+pub fn list() -> Result<Vec<MeetingRecord>> {
+    let root = journal::sessions_dir();
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    for entry in std::fs::read_dir(root)?.flatten() {
+        let Some(session_id) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if journal::validate_session_id(&session_id).is_err() || !entry.path().is_dir() {
+            continue;
+        }
+        // Store a record for sessions that predate the library so their journal is read only once
+        if !record_path(&session_id).exists() && journal::journal_path(&session_id).exists() {
+            if let Err(e) = save_from_journal(&session_id) {
+                warn!("Failed to add meeting {} to library: {}", session_id, e);
+            }
+        }
+        match get(&session_id) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping unreadable meeting {}: {}", session_id, e),
+        }
+    }
+
+    records.sort_by(|a, b| b.started_at.cmp(&a.started_at).then(b.session_id.cmp(&a.session_id)));
+    Ok(records)
+}
+
+fn save_from_journal(session_id: &str) -> Result<()> {
+    let record = record_from_journal(session_id)?;
+    let _guard = LIBRARY_LOCK.lock().map_err(|_| anyhow!("Library lock poisoned"))?;
+    if !record_path(session_id).exists() {
+        write_record(&record)?;
+    }
+    Ok(())
+}
+
+pub fn update(session_id: &str, change: impl FnOnce(&mut MeetingRecord)) -> Result<MeetingRecord> {
+    let _guard = LIBRARY_LOCK.lock().map_err(|_| anyhow!("Library lock poisoned"))?;
+    let mut record = get(session_id)?;
+    change(&mut record);
+    record.updated_at = Utc::now();
+    write_record(&record)?;
+    Ok(record)
+}
+
+// Marks the meeting ended and stores its transcript statistics
+pub fn finish(session_id: &str) -> Result<MeetingRecord> {
+    let transcript = journal::load_transcript(session_id);
+    if let Err(e) = &transcript {
+        warn!("Failed to read transcript of meeting {}: {}", session_id, e);
+    }
+    update(session_id, |record| {
+        record.ended_at = Some(Utc::now());
+        if let Ok(transcript) = &transcript {
+            record.apply_transcript(transcript);
+        }
+    })
+}
+
+// Recomputes the stored transcript statistics, e.g. after a recovered session was closed
+pub fn refresh(session_id: &str) -> Result<MeetingRecord> {
+    let transcript = journal::load_transcript(session_id)?;
+    update(session_id, |record| record.apply_transcript(&transcript))
+}
+
+pub fn rename(session_id: &str, title: &str) -> Result<MeetingRecord> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(anyhow!("Meeting title cannot be empty"));
+    }
+    update(session_id, |record| record.title = title.to_string())
+}
+
+pub fn add_tag(session_id: &str, tag: &str) -> Result<MeetingRecord> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(anyhow!("Tag cannot be empty"));
+    }
+    update(session_id, |record| {
+        if !record.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            record.tags.push(tag.to_string());
+            record.tags.sort();
+        }
+    })
+}
+
+pub fn remove_tag(session_id: &str, tag: &str) -> Result<MeetingRecord> {
+    let tag = tag.trim();
+    update(session_id, |record| record.tags.retain(|t| !t.eq_ignore_ascii_case(tag)))
+}
+
+pub fn add_audio_file(session_id: &str, path: &Path) -> Result<MeetingRecord> {
+    let path = path.to_string_lossy().into_owned();
+    update(session_id, |record| {
+        if !record.audio_files.contains(&path) {
+            record.audio_files.push(path);
+        }
+    })
+}
+
+// Tags and chapters for the session's recordings. Chapters come from extracted
+// decisions and open questions that point back at the transcript.
+pub fn recording_metadata(session_id: &str, app_name: &str, app_version: &str) -> Result<RecordingMetadata> {
+    let record = get(session_id)?;
+    let mut participants: Vec<String> = Vec::new();
+    let mut chapters = Vec::new();
+
+    if let Some(extraction) = extract::load_extraction(session_id)? {
+        participants.extend(extraction.action_items.iter().filter_map(|item| item.assignee.clone()));
+        let sourced = extraction
+            .decisions
+            .iter()
+            .map(|item| ("Decision", item))
+            .chain(extraction.open_questions.iter().map(|item| ("Question", item)));
+        for (kind, item) in sourced {
+            if let Some(source) = &item.source {
+                chapters.push(Chapter {
+                    start: source.start,
+                    title: format!("{}: {}", kind, item.text),
+                });
+            }
+        }
+    }
+    for bookmark in journal::load_transcript(session_id)?.bookmarks {
+        chapters.push(Chapter {
+            start: bookmark.offset,
+            title: export::bookmark_label(&bookmark).to_string(),
+        });
+    }
+    if let Some(summary) = summary::load_summary(session_id)? {
+        participants.extend(summary.action_items.iter().filter_map(|item| item.owner.clone()));
+    }
+    participants.retain(|name| !name.trim().is_empty());
+    participants.sort_by_key(|name| name.to_lowercase());
+    participants.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+
+    Ok(RecordingMetadata {
+        title: record.title,
+        date: record.started_at,
+        participants,
+        app_name: app_name.to_string(),
+        app_version: app_version.to_string(),
+        chapters,
+    })
+}
+
+// Removes the session directory and every artifact in it; audio saved outside the
+// session directory is left alone unless `delete_audio` is set, and an imported
+// meeting's source file is always kept
+// Deletes an audio file along with its cached waveform peaks
+pub fn remove_audio(path: &Path) -> Result<()> {
+    std::fs::remove_file(path)?;
+    waveform::remove_sidecars(path);
+    Ok(())
+}
+
+pub fn delete(session_id: &str, delete_audio: bool) -> Result<()> {
+    let record = get(session_id)?;
+    let _guard = LIBRARY_LOCK.lock().map_err(|_| anyhow!("Library lock poisoned"))?;
+
+    if delete_audio {
+        for audio in record.owned_audio() {
+            if let Err(e) = remove_audio(&audio) {
+                warn!("Failed to delete audio file {:?}: {}", audio, e);
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(journal::session_dir(session_id))?;
+    search::remove_session(session_id);
+    info!("Deleted meeting {}", session_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::SessionJournal;
+    use crate::vault;
+    use serde_json::json;
+
+    fn recording(session_id: &str) -> MeetingRecord {
+        create(MeetingRecord::new(session_id, "Weekly sync", MeetingSource::Recording)).unwrap()
+    }
+
+    #[test]
+    fn create_rename_and_tag() {
+        let session_id = "library-edit";
+        recording(session_id);
+        assert!(create(MeetingRecord::new("../escape", "bad", MeetingSource::Recording)).is_err());
+
+        let renamed = rename(session_id, "  Planning  ").unwrap();
+        assert_eq!(renamed.title, "Planning");
+        assert!(rename(session_id, " ").is_err());
+
+        add_tag(session_id, "budget").unwrap();
+        add_tag(session_id, "Alpha").unwrap();
+        // Tags are unique regardless of case
+        let tagged = add_tag(session_id, "BUDGET").unwrap();
+        assert_eq!(tagged.tags, vec!["Alpha", "budget"]);
+        assert!(add_tag(session_id, "").is_err());
+        assert_eq!(remove_tag(session_id, "alpha").unwrap().tags, vec!["budget"]);
+
+        let stored = get(session_id).unwrap();
+        assert_eq!(stored.title, "Planning");
+        assert_eq!(stored.tags, vec!["budget"]);
+        assert!(stored.updated_at >= renamed.updated_at);
+        assert!(get("library-missing").is_err());
+    }
+
+    #[test]
+    fn finishing_stores_transcript_statistics() {
+        let session_id = "library-finish";
+        recording(session_id);
+        let journal = SessionJournal::create(session_id).unwrap();
+        journal.append_line("hello", 0.0, 2.5, "mic").unwrap();
+        journal.append_line("world", 2.5, 7.0, "system").unwrap();
+        journal.append_bookmark("start", 1.0).unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+
+        // Nothing reads the journal until the meeting is finished
+        assert_eq!(get(session_id).unwrap().line_count, 0);
+        let finished = finish(session_id).unwrap();
+        assert!(finished.ended_at.is_some());
+        assert_eq!((finished.line_count, finished.bookmark_count), (2, 1));
+        assert_eq!(finished.duration, Some(7.0));
+        assert!(finished.transcript_journal.is_some());
+
+        let journal = SessionJournal::open(session_id).unwrap();
+        journal.append_line("again", 7.0, 9.0, "mic").unwrap();
+        drop(journal);
+        assert_eq!(get(session_id).unwrap().line_count, 2);
+        let refreshed = refresh(session_id).unwrap();
+        assert_eq!(refreshed.line_count, 3);
+        assert_eq!(refreshed.duration, Some(9.0));
+    }
+
+    #[test]
+    fn sessions_without_a_record_are_stored_when_listed() {
+        let session_id = "library-legacy";
+        let journal = SessionJournal::create(session_id).unwrap();
+        journal.append_line("from before the library", 0.0, 3.0, "mic").unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+        assert!(!record_path(session_id).exists());
+
+        let records = list().unwrap();
+        let legacy = records.iter().find(|r| r.session_id == session_id).unwrap();
+        assert!(legacy.title.starts_with("Meeting "));
+        assert_eq!(legacy.line_count, 1);
+        assert!(legacy.ended_at.is_some());
+        assert!(record_path(session_id).exists());
+    }
+
+    #[test]
+    fn delete_keeps_outside_audio_unless_asked() {
+        let outside = std::env::temp_dir().join(format!("library-delete-{}.mp3", std::process::id()));
+        let source = std::env::temp_dir().join(format!("library-source-{}.mp3", std::process::id()));
+        std::fs::write(&outside, b"audio").unwrap();
+        std::fs::write(&source, b"original").unwrap();
+        let import = MeetingSource::Import {
+            path: source.to_string_lossy().into_owned(),
+        };
+
+        for (session_id, delete_audio) in [("library-delete-keep", false), ("library-delete-all", true)] {
+            std::fs::write(&outside, b"audio").unwrap();
+            let peaks = std::env::temp_dir().join(format!(".library-delete-{}.mp3.mixed.peaks", std::process::id()));
+            std::fs::write(&peaks, b"peaks").unwrap();
+            create(MeetingRecord::new(session_id, "call", import.clone())).unwrap();
+            add_audio_file(session_id, &outside).unwrap();
+            add_audio_file(session_id, &source).unwrap();
+            assert_eq!(get(session_id).unwrap().owned_audio(), vec![outside.clone()]);
+
+            delete(session_id, delete_audio).unwrap();
+            assert!(!journal::session_dir(session_id).exists());
+            assert!(get(session_id).is_err());
+            assert_eq!(outside.exists(), !delete_audio);
+            // Cached waveform peaks go with the audio
+            assert_eq!(peaks.exists(), !delete_audio);
+            // The file an import came from belongs to the user
+            assert!(source.exists());
+        }
+        std::fs::remove_file(&source).unwrap();
+    }
+
+    #[test]
+    fn metadata_chapters_come_from_sourced_items_and_bookmarks() {
+        let session_id = "library-chapters";
+        recording(session_id);
+        let journal = SessionJournal::create(session_id).unwrap();
+        journal.append_line("we agreed", 0.0, 5.0, "mic").unwrap();
+        journal.append_bookmark("", 30.0).unwrap();
+        journal.append_bookmark("Demo", 12.0).unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+
+        let source = |start: f32| json!({ "line_sequence": 0, "start": start, "end": start + 1.0 });
+        let extraction = json!({
+            "session_id": session_id,
+            "model": "stand-in",
+            "generated_at": Utc::now(),
+            "action_items": [
+                { "task": "Send notes", "assignee": "bob", "due": null, "source": null },
+                { "task": "Book room", "assignee": " ", "due": null, "source": null }
+            ],
+            "decisions": [
+                { "text": "Ship in May", "source": source(4.0) },
+                { "text": "Unsourced", "source": null }
+            ],
+            "open_questions": [{ "text": "Who pays?", "source": source(20.0) }]
+        });
+        vault::write(extract::extraction_path(session_id), extraction.to_string().as_bytes()).unwrap();
+        let summary = json!({
+            "session_id": session_id,
+            "model": "stand-in",
+            "generated_at": Utc::now(),
+            "key_points": [],
+            "decisions": [],
+            "action_items": [{ "description": "Send notes", "owner": "Bob" }, { "description": "Plan", "owner": "Ana" }]
+        });
+        vault::write(summary::summary_path(session_id), summary.to_string().as_bytes()).unwrap();
+
+        let metadata = recording_metadata(session_id, "Meetily", "1.2.3").unwrap();
+        assert_eq!(metadata.title, "Weekly sync");
+        assert_eq!((metadata.app_name.as_str(), metadata.app_version.as_str()), ("Meetily", "1.2.3"));
+        assert_eq!(metadata.participants, vec!["Ana", "bob"]);
+        let chapters: Vec<(f32, &str)> = metadata.chapters.iter().map(|c| (c.start, c.title.as_str())).collect();
+        assert_eq!(
+            chapters,
+            vec![
+                (4.0, "Decision: Ship in May"),
+                (20.0, "Question: Who pays?"),
+                (12.0, "Demo"),
+                (30.0, "Bookmark"),
+            ]
+        );
+    }
+}
@@ -1,8 +1,37 @@
 use std::process::Command;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tauri::command;
+use tauri::{command, AppHandle, Emitter, Runtime};
 use reqwest::blocking::Client;
 
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+static TRAILING_COMMA: Lazy<Regex> = Lazy::new(|| Regex::new(r",\s*([}\]])").unwrap());
+
+// Best-effort cleanup of near-JSON: code fences, surrounding prose and trailing commas
+fn repair_json(text: &str) -> Option<String> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    if end <= start {
+        return None;
+    }
+    Some(TRAILING_COMMA.replace_all(&text[start..=end], "$1").into_owned())
+}
+
+// Parses a model's JSON reply, repairing it first when it doesn't parse as it is
+pub fn parse_json_reply<T: DeserializeOwned>(text: &str) -> Result<T> {
+    match serde_json::from_str(text.trim()) {
+        Ok(value) => Ok(value),
+        Err(first) => {
+            let repaired = repair_json(text).ok_or_else(|| anyhow!("No JSON object in reply: {}", first))?;
+            Ok(serde_json::from_str(&repaired)?)
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OllamaModel {
     pub name: String,
@@ -41,7 +70,7 @@ pub fn get_ollama_models() -> Result<Vec<OllamaModel>, String> {
 fn get_models_via_http() -> Result<Vec<OllamaModel>, String> {
     let client = Client::new();
     let response = client
-        .get("http://localhost:11434/api/tags")
+        .get(format!("{}/api/tags", DEFAULT_OLLAMA_URL))
         .send()
         .map_err(|e| format!("Failed to make HTTP request: {}", e))?;
 
@@ -101,3 +130,450 @@ fn format_size(size: i64) -> String {
         format!("{:.1} GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct GenerateRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    stream: bool,
+    options: &'a ModelOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+    options: &'a ModelOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateChunk {
+    #[serde(default)]
+    response: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChunk {
+    message: Option<ChatMessage>,
+}
+
+// Streamed endpoints report failures in-band as `{"error": "..."}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StreamLine<T> {
+    Error { error: String },
+    Item(T),
+}
+
+// Async client for the Ollama HTTP API. The base URL is configurable so the
+// app can point at a remote server (or a local stand-in).
+#[derive(Debug, Clone)]
+pub struct OllamaClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl Default for OllamaClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_OLLAMA_URL)
+    }
+}
+
+impl OllamaClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn post_stream<B: Serialize>(&self, path: &str, body: &B) -> Result<reqwest::Response> {
+        let response = self.http.post(self.url(path)).json(body).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Ollama {} failed with status {}: {}", path, status, text.trim()));
+        }
+        Ok(response)
+    }
+
+    // Streams `/api/generate`, calling `on_token` for each fragment; returns the full response
+    pub async fn generate(
+        &self,
+        model: &str,
+        system: Option<&str>,
+        prompt: &str,
+        options: &ModelOptions,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<String> {
+        let request = GenerateRequest { model, prompt, system, stream: true, options };
+        let response = self.post_stream("/api/generate", &request).await?;
+
+        let mut output = String::new();
+        read_ndjson::<GenerateChunk>(response, |chunk| {
+            on_token(&chunk.response);
+            output.push_str(&chunk.response);
+            Ok(())
+        })
+        .await?;
+        Ok(output)
+    }
+
+    // Streams `/api/chat`; `format` is either `"json"` or a JSON schema the reply must follow
+    pub async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: Option<&serde_json::Value>,
+        options: &ModelOptions,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<String> {
+        let request = ChatRequest { model, messages, stream: true, format, options };
+        let response = self.post_stream("/api/chat", &request).await?;
+
+        let mut output = String::new();
+        read_ndjson::<ChatChunk>(response, |chunk| {
+            if let Some(message) = chunk.message {
+                on_token(&message.content);
+                output.push_str(&message.content);
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(output)
+    }
+
+    pub async fn embed(&self, model: &str, prompt: &str) -> Result<Vec<f32>> {
+        let response = self
+            .http
+            .post(self.url("/api/embeddings"))
+            .json(&serde_json::json!({ "model": model, "prompt": prompt }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Ollama /api/embeddings failed with status {}: {}", status, text.trim()));
+        }
+        let embedding = response.json::<EmbeddingResponse>().await?.embedding;
+        if embedding.is_empty() {
+            return Err(anyhow!("Model {} returned an empty embedding", model));
+        }
+        Ok(embedding)
+    }
+
+    pub async fn version(&self) -> Result<String> {
+        let response = self.http.get(self.url("/api/version")).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Ollama /api/version failed with status {}", response.status()));
+        }
+        Ok(response.json::<VersionResponse>().await?.version)
+    }
+
+    // Streams `/api/pull`, reporting each status line until the model is fully downloaded
+    pub async fn pull(&self, model: &str, mut on_progress: impl FnMut(PullStatus)) -> Result<()> {
+        let body = serde_json::json!({ "model": model, "stream": true });
+        let response = self.post_stream("/api/pull", &body).await?;
+        read_ndjson::<PullStatus>(response, |status| {
+            on_progress(status);
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn delete(&self, model: &str) -> Result<()> {
+        let response = self
+            .http
+            .delete(self.url("/api/delete"))
+            .json(&serde_json::json!({ "model": model }))
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::NOT_FOUND => Err(anyhow!("Model {} not found", model)),
+            status => Err(anyhow!("Ollama /api/delete failed with status {}", status)),
+        }
+    }
+
+    pub async fn show(&self, model: &str) -> Result<OllamaModelDetails> {
+        let response = self
+            .http
+            .post(self.url("/api/show"))
+            .json(&serde_json::json!({ "model": model }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Ollama /api/show failed with status {}", response.status()));
+        }
+        let show: ShowResponse = response.json().await?;
+        Ok(OllamaModelDetails::from_show(model, show))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullStatus {
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ShowDetails {
+    #[serde(default)]
+    family: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    parameter_size: Option<String>,
+    #[serde(default)]
+    quantization_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    details: ShowDetails,
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    parameters: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaModelDetails {
+    pub name: String,
+    pub family: Option<String>,
+    pub format: Option<String>,
+    pub parameter_size: Option<String>,
+    pub parameter_count: Option<u64>,
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    // `num_ctx` from the Modelfile, if the model overrides the default
+    pub configured_context: Option<u64>,
+}
+
+impl OllamaModelDetails {
+    fn from_show(name: &str, show: ShowResponse) -> Self {
+        // Architecture-specific keys are prefixed, e.g. "llama.context_length"
+        let context_length = show
+            .model_info
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64());
+        let parameter_count = show
+            .model_info
+            .get("general.parameter_count")
+            .and_then(|value| value.as_u64());
+        let configured_context = show.parameters.as_deref().and_then(|parameters| {
+            parameters.lines().find_map(|line| {
+                let mut parts = line.split_whitespace();
+                match (parts.next(), parts.next()) {
+                    (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                    _ => None,
+                }
+            })
+        });
+
+        Self {
+            name: name.to_string(),
+            family: show.details.family,
+            format: show.details.format,
+            parameter_size: show.details.parameter_size,
+            parameter_count,
+            quantization: show.details.quantization_level,
+            context_length,
+            configured_context,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaStatus {
+    pub url: String,
+    pub reachable: bool,
+    pub version: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PullProgress {
+    model: String,
+    status: String,
+    digest: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+    percent: Option<f32>,
+}
+
+pub(crate) fn client_for(ollama_url: Option<String>) -> OllamaClient {
+    ollama_url.map(OllamaClient::new).unwrap_or_default()
+}
+
+#[command]
+pub async fn ollama_status(ollama_url: Option<String>) -> Result<OllamaStatus, String> {
+    let client = client_for(ollama_url);
+    Ok(match client.version().await {
+        Ok(version) => OllamaStatus {
+            url: client.base_url().to_string(),
+            reachable: true,
+            version: Some(version),
+            error: None,
+        },
+        Err(e) => OllamaStatus {
+            url: client.base_url().to_string(),
+            reachable: false,
+            version: None,
+            error: Some(e.to_string()),
+        },
+    })
+}
+
+#[command]
+pub async fn pull_ollama_model<R: Runtime>(
+    app: AppHandle<R>,
+    model: String,
+    ollama_url: Option<String>,
+) -> Result<(), String> {
+    log::info!("Pulling Ollama model {}", model);
+    client_for(ollama_url)
+        .pull(&model, |status| {
+            let percent = match (status.completed, status.total) {
+                (Some(completed), Some(total)) if total > 0 => {
+                    Some((completed as f64 / total as f64 * 100.0) as f32)
+                }
+                _ => None,
+            };
+            let progress = PullProgress {
+                model: model.clone(),
+                status: status.status,
+                digest: status.digest,
+                total: status.total,
+                completed: status.completed,
+                percent,
+            };
+            if let Err(e) = app.emit("ollama-pull-progress", progress) {
+                log::error!("Failed to emit pull progress: {}", e);
+            }
+        })
+        .await
+        .map_err(|e| format!("Failed to pull model {}: {}", model, e))
+}
+
+#[command]
+pub async fn delete_ollama_model(model: String, ollama_url: Option<String>) -> Result<(), String> {
+    log::info!("Deleting Ollama model {}", model);
+    client_for(ollama_url)
+        .delete(&model)
+        .await
+        .map_err(|e| format!("Failed to delete model {}: {}", model, e))
+}
+
+#[command]
+pub async fn show_ollama_model(
+    model: String,
+    ollama_url: Option<String>,
+) -> Result<OllamaModelDetails, String> {
+    client_for(ollama_url)
+        .show(&model)
+        .await
+        .map_err(|e| format!("Failed to inspect model {}: {}", model, e))
+}
+
+// Reads a newline-delimited JSON body incrementally, handing each object to `on_item`
+pub(crate) async fn read_ndjson<T: DeserializeOwned>(
+    mut response: reqwest::Response,
+    mut on_item: impl FnMut(T) -> Result<()>,
+) -> Result<()> {
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let mut handle_line = |line: &[u8]| -> Result<()> {
+        let line = std::str::from_utf8(line)?.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        match serde_json::from_str::<StreamLine<T>>(line)? {
+            StreamLine::Error { error } => Err(anyhow!("Ollama error: {}", error)),
+            StreamLine::Item(item) => on_item(item),
+        }
+    };
+
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            handle_line(&line)?;
+        }
+    }
+    handle_line(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_strips_fences_prose_and_trailing_commas() {
+        let reply = "Sure!\n```json\n{\"a\": [1, 2,], \"b\": {\"c\": true,},}\n```\nDone.";
+        assert_eq!(repair_json(reply).unwrap(), "{\"a\": [1, 2], \"b\": {\"c\": true}}");
+        assert_eq!(repair_json("} backwards {"), None);
+        assert_eq!(repair_json("nothing"), None);
+    }
+
+    #[test]
+    fn valid_json_is_parsed_without_repair() {
+        let value: serde_json::Value = parse_json_reply("  {\"text\": \"a, ]\"}  ").unwrap();
+        // The repair would have rewritten the comma inside the string
+        assert_eq!(value["text"], "a, ]");
+    }
+}
@@ -0,0 +1,231 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use tauri::http::{header, Method, Request, Response, StatusCode};
+
+use crate::audio::AudioFormat;
+use crate::library;
+use crate::vault::RangeReader;
+
+// Recordings are served as `recording://localhost/<session_id>?segment=<n>`, or
+// `http://recording.localhost/...` on Windows
+pub const SCHEME: &str = "recording";
+// Upper bound on one response, so an open-ended range over a long recording returns a
+// slice the player can start on instead of the whole file
+const MAX_RANGE_BYTES: u64 = 2 * 1024 * 1024;
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).and_then(AudioFormat::from_extension) {
+        Some(AudioFormat::AacMp4) => "audio/mp4",
+        Some(AudioFormat::OpusOgg) => "audio/ogg",
+        Some(AudioFormat::OpusWebm) => "audio/webm",
+        Some(AudioFormat::Flac) => "audio/flac",
+        Some(AudioFormat::Mp3) => "audio/mpeg",
+        Some(AudioFormat::Wav) => "audio/wav",
+        None => "application/octet-stream",
+    }
+}
+
+// Only files the library lists for the session can be served
+fn resolve(request: &Request<Vec<u8>>) -> Result<String> {
+    let session_id = percent_decode(request.uri().path().trim_start_matches('/'));
+    let segment = request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("segment="))
+        .map(|index| index.parse::<usize>())
+        .transpose()?
+        .unwrap_or(0);
+    let record = library::get(&session_id)?;
+    record
+        .audio_files
+        .get(segment)
+        .cloned()
+        .ok_or_else(|| anyhow!("Session {} has no audio segment {}", session_id, segment))
+}
+
+// Parses a single `bytes=` range into an inclusive start and end. Only the first range
+// of a multi-range request is honoured.
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len.checked_sub(1)?)),
+    };
+    (start <= end && start < len).then_some((start, end))
+}
+
+fn serve(request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+    let path = resolve(request)?;
+    let mut reader = RangeReader::open(Path::new(&path))?;
+    let len = reader.len();
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type(Path::new(&path)))
+        .header(header::ACCEPT_RANGES, "bytes");
+    let head = request.method() == Method::HEAD;
+
+    let range = request.headers().get(header::RANGE).and_then(|value| value.to_str().ok());
+    // A request without a range gets a plain 200, which may not carry partial content.
+    // Custom protocol bodies can't be streamed, so past the cap it is the first slice;
+    // media elements always ask for ranges and `Accept-Ranges` tells others they can too.
+    let Some(range) = range else {
+        let served = len.min(MAX_RANGE_BYTES);
+        let body = if head { Vec::new() } else { reader.read_at(0, served as usize)? };
+        return Ok(builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, served)
+            .body(body)?);
+    };
+    let Some((start, end)) = parse_range(range, len) else {
+        return Ok(builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Vec::new())?);
+    };
+
+    let end = end.min(start + MAX_RANGE_BYTES - 1);
+    let body = if head { Vec::new() } else { reader.read_at(start, (end - start + 1) as usize)? };
+    Ok(builder
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+        .header(header::CONTENT_LENGTH, end - start + 1)
+        .body(body)?)
+}
+
+// Answers a request on the recording scheme; runs file I/O and decryption, so call it
+// off the main thread
+pub fn respond(request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    serve(request).unwrap_or_else(|e| {
+        warn!("Failed to serve {}: {}", request.uri(), e);
+        let mut response = Response::new(e.to_string().into_bytes());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        response
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal;
+    use crate::library::{MeetingRecord, MeetingSource};
+
+    #[test]
+    fn parses_explicit_and_open_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        // An end past the file is clamped to the last byte
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        // A suffix longer than the file covers all of it
+        assert_eq!(parse_range("bytes=-5000", 1000), Some((0, 999)));
+        // An empty suffix selects nothing
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+    }
+
+    #[test]
+    fn rejects_ranges_outside_the_file() {
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=1500-2000", 1000), None);
+        assert_eq!(parse_range("bytes=200-100", 1000), None);
+        assert_eq!(parse_range("items=0-10", 1000), None);
+    }
+
+    #[test]
+    fn uses_the_first_of_multiple_ranges() {
+        assert_eq!(parse_range("bytes=0-9, 20-29, -5", 1000), Some((0, 9)));
+        assert_eq!(parse_range("bytes=2000-2100, 0-9", 1000), None);
+    }
+
+    #[test]
+    fn empty_file_has_no_satisfiable_range() {
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("bytes=0-0", 0), None);
+        assert_eq!(parse_range("bytes=-10", 0), None);
+    }
+
+    fn session_with_audio(session_id: &str, len: usize) -> Vec<u8> {
+        let contents: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let dir = journal::app_data_dir().join("playback");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.wav", session_id));
+        std::fs::write(&path, &contents).unwrap();
+        library::create(MeetingRecord::new(session_id, "Playback", MeetingSource::Recording)).unwrap();
+        library::add_audio_file(session_id, &path).unwrap();
+        contents
+    }
+
+    fn get(session_id: &str, range: Option<&str>) -> Response<Vec<u8>> {
+        let mut request = Request::builder().uri(format!("{}://localhost/{}", SCHEME, session_id));
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        serve(&request.body(Vec::new()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn request_without_range_is_ok_and_capped() {
+        let len = MAX_RANGE_BYTES as usize + 1000;
+        let contents = session_with_audio("playback-large", len);
+        let response = get("playback-large", None);
+        // Never 206 without a Range header
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::CONTENT_RANGE).is_none());
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], MAX_RANGE_BYTES.to_string().as_str());
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(response.body().as_slice(), &contents[..MAX_RANGE_BYTES as usize]);
+
+        // The rest is reached with ranges
+        let response = get("playback-large", Some(&format!("bytes={}-", MAX_RANGE_BYTES)));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", MAX_RANGE_BYTES, len - 1, len).as_str()
+        );
+        assert_eq!(response.body().as_slice(), &contents[MAX_RANGE_BYTES as usize..]);
+    }
+
+    #[test]
+    fn small_file_without_range_is_sent_whole() {
+        let contents = session_with_audio("playback-small", 1000);
+        let response = get("playback-small", None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &contents);
+
+        let response = get("playback-small", Some("bytes=-10"));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body().as_slice(), &contents[990..]);
+    }
+}
+
@@ -0,0 +1,531 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{EncodingProfile, PushOutcome, StreamingEncoder};
+use crate::clip;
+use crate::export::{self, ExportFormat};
+use crate::journal::{self, SessionTranscript, TranscriptLine};
+use crate::ollama::{ChatMessage, ModelOptions, OllamaClient};
+use crate::summary::chunk_texts;
+
+// Lines carry only start/end times, so a span's position in the audio is estimated from
+// its character offset within the line; the padding absorbs the error at either edge
+// and may reach into the gap before or after the line, but not into its neighbours
+const AUDIO_PADDING_SECS: f32 = 0.3;
+// Returned with every redacted recording so the estimate above is not mistaken for word timing
+const TIMING_NOTICE: &str = "Transcript lines have no word timings, so each redaction's position in \
+the audio is estimated from where it falls in its line and padded by 0.3 seconds. Fast or uneven \
+speech can leave part of a word audible; listen to the result before sharing it.";
+const BLEEP_FREQUENCY_HZ: f32 = 1000.0;
+const BLEEP_AMPLITUDE: f32 = 0.3;
+const NER_CHUNK_CHARS: usize = 6000;
+
+const NER_SYSTEM_PROMPT: &str = "You find personal names in meeting transcripts. Return every \
+name of a person exactly as written in the text, including first names on their own. Do not \
+return company, product or place names.";
+
+static EMAIL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b").unwrap());
+// Loose on purpose: digits with the usual separators; the digit count is checked afterwards
+static PHONE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{1,4}\)[\s.-]?)?\d[\d\s.-]{5,}\d").unwrap());
+static CARD: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiCategory {
+    Email,
+    Phone,
+    CreditCard,
+    Term,
+    Name,
+}
+
+impl PiiCategory {
+    fn placeholder(self) -> &'static str {
+        match self {
+            PiiCategory::Email => "[EMAIL]",
+            PiiCategory::Phone => "[PHONE]",
+            PiiCategory::CreditCard => "[CARD]",
+            PiiCategory::Term => "[REDACTED]",
+            PiiCategory::Name => "[NAME]",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioRedaction {
+    #[default]
+    Bleep,
+    Silence,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RedactionOptions {
+    // Extra words or phrases to remove, matched case-insensitively on word boundaries
+    pub terms: Vec<String>,
+    // Ollama model for the named-entity pass; skipped when absent
+    pub ner_model: Option<String>,
+    pub ollama_url: Option<String>,
+    // Detectors to leave out, e.g. phone numbers in a support call
+    pub skip: Vec<PiiCategory>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactionSpan {
+    pub line_sequence: u64,
+    pub category: PiiCategory,
+    // Byte range in the original line text
+    pub start_byte: usize,
+    pub end_byte: usize,
+    // Estimated position in the session audio
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactedTranscript {
+    pub transcript: SessionTranscript,
+    pub spans: Vec<RedactionSpan>,
+}
+
+fn digit_count(text: &str) -> usize {
+    text.chars().filter(char::is_ascii_digit).count()
+}
+
+fn luhn_valid(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum % 10 == 0
+}
+
+// Word boundaries only apply next to word characters; `\b` after "c++" could never match
+fn term_regex(terms: &[String]) -> Result<Option<Regex>> {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let alternatives: Vec<String> = terms
+        .iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| {
+            format!(
+                "{}{}{}",
+                if is_word(t.chars().next()) { r"\b" } else { "" },
+                regex::escape(t),
+                if is_word(t.chars().last()) { r"\b" } else { "" }
+            )
+        })
+        .collect();
+    if alternatives.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        RegexBuilder::new(&format!("(?:{})", alternatives.join("|")))
+            .case_insensitive(true)
+            .build()?,
+    ))
+}
+
+struct Detector {
+    category: PiiCategory,
+    pattern: Regex,
+}
+
+fn detectors(options: &RedactionOptions, names: &[String]) -> Result<Vec<Detector>> {
+    let mut detectors = vec![
+        // Cards before phones: a card number also looks like a long phone number
+        Detector { category: PiiCategory::CreditCard, pattern: CARD.clone() },
+        Detector { category: PiiCategory::Email, pattern: EMAIL.clone() },
+        Detector { category: PiiCategory::Phone, pattern: PHONE.clone() },
+    ];
+    if let Some(pattern) = term_regex(&options.terms)? {
+        detectors.push(Detector { category: PiiCategory::Term, pattern });
+    }
+    if let Some(pattern) = term_regex(names)? {
+        detectors.push(Detector { category: PiiCategory::Name, pattern });
+    }
+    detectors.retain(|d| !options.skip.contains(&d.category));
+    Ok(detectors)
+}
+
+fn accept(category: PiiCategory, text: &str) -> bool {
+    match category {
+        PiiCategory::CreditCard => luhn_valid(text),
+        // Fewer digits is usually a time, an amount or a year range
+        PiiCategory::Phone => (9..=15).contains(&digit_count(text)),
+        _ => true,
+    }
+}
+
+// Finds non-overlapping spans in one line; earlier detectors win on overlap. Padded
+// audio positions stay within `bounds`.
+fn detect_line(line: &TranscriptLine, detectors: &[Detector], bounds: (f32, f32)) -> Vec<RedactionSpan> {
+    let mut spans: Vec<RedactionSpan> = Vec::new();
+    let length = line.text.len().max(1) as f32;
+    let duration = (line.end - line.start).max(0.0);
+
+    for detector in detectors {
+        for found in detector.pattern.find_iter(&line.text) {
+            if !accept(detector.category, found.as_str()) {
+                continue;
+            }
+            if spans.iter().any(|s| found.start() < s.end_byte && s.start_byte < found.end()) {
+                continue;
+            }
+            let start = line.start + duration * found.start() as f32 / length;
+            let end = line.start + duration * found.end() as f32 / length;
+            spans.push(RedactionSpan {
+                line_sequence: line.sequence,
+                category: detector.category,
+                start_byte: found.start(),
+                end_byte: found.end(),
+                start: (start - AUDIO_PADDING_SECS).max(bounds.0),
+                end: (end + AUDIO_PADDING_SECS).min(bounds.1),
+            });
+        }
+    }
+
+    spans.sort_by_key(|s| s.start_byte);
+    spans
+}
+
+fn apply_spans(text: &str, spans: &[RedactionSpan]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for span in spans {
+        redacted.push_str(&text[cursor..span.start_byte]);
+        redacted.push_str(span.category.placeholder());
+        cursor = span.end_byte;
+    }
+    redacted.push_str(&text[cursor..]);
+    redacted
+}
+
+#[derive(Debug, Deserialize)]
+struct NerReply {
+    #[serde(default)]
+    names: Vec<String>,
+}
+
+async fn find_names(client: &OllamaClient, model: &str, lines: &[TranscriptLine]) -> Result<Vec<String>> {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": { "names": { "type": "array", "items": { "type": "string" } } },
+        "required": ["names"]
+    });
+    let options = ModelOptions {
+        num_ctx: None,
+        temperature: Some(0.0),
+    };
+
+    let mut names = BTreeSet::new();
+    for chunk in chunk_texts(lines.iter().map(|l| l.text.clone()), NER_CHUNK_CHARS) {
+        let messages = [ChatMessage::system(NER_SYSTEM_PROMPT), ChatMessage::user(chunk)];
+        let reply = client.chat(model, &messages, Some(&schema), &options, |_| {}).await?;
+        match serde_json::from_str::<NerReply>(&reply) {
+            Ok(reply) => names.extend(reply.names.into_iter().map(|n| n.trim().to_string())),
+            Err(e) => warn!("Ignoring unparseable name list from {}: {}", model, e),
+        }
+    }
+    // Single letters and fragments would blank out half the transcript
+    names.retain(|name| name.chars().filter(|c| c.is_alphabetic()).count() >= 2);
+    Ok(names.into_iter().collect())
+}
+
+pub async fn redact_session(session_id: &str, options: &RedactionOptions) -> Result<RedactedTranscript> {
+    let mut transcript = journal::load_transcript(session_id)?;
+
+    let names = match &options.ner_model {
+        Some(model) if !options.skip.contains(&PiiCategory::Name) => {
+            let client = crate::ollama::client_for(options.ollama_url.clone());
+            find_names(&client, model, &transcript.lines).await?
+        }
+        _ => Vec::new(),
+    };
+    let detectors = detectors(options, &names)?;
+
+    // From the end of the previous line to the start of the next, never narrower than the line
+    let bounds: Vec<(f32, f32)> = (0..transcript.lines.len())
+        .map(|i| {
+            let line = &transcript.lines[i];
+            let previous = i.checked_sub(1).map_or(0.0, |p| transcript.lines[p].end);
+            let next = transcript.lines.get(i + 1).map_or(f32::MAX, |n| n.start);
+            (previous.min(line.start), next.max(line.end))
+        })
+        .collect();
+
+    let mut all_spans = Vec::new();
+    for (line, bounds) in transcript.lines.iter_mut().zip(bounds) {
+        let spans = detect_line(line, &detectors, bounds);
+        if !spans.is_empty() {
+            line.text = apply_spans(&line.text, &spans);
+            all_spans.extend(spans);
+        }
+    }
+    info!("Redacted {} span(s) in session {}", all_spans.len(), session_id);
+
+    Ok(RedactedTranscript {
+        transcript,
+        spans: all_spans,
+    })
+}
+
+pub fn export_redacted_transcript(redacted: &RedactedTranscript, format: ExportFormat, path: &Path) -> Result<()> {
+    // Extractions and summaries were generated from the unredacted text, so they are left out
+    let rendered = export::render(&redacted.transcript, None, format)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, rendered)?;
+    Ok(())
+}
+
+// `samples` is the block starting `offset` samples into the session audio
+fn redact_samples(samples: &mut [f32], offset: usize, sample_rate: u32, spans: &[RedactionSpan], mode: AudioRedaction) {
+    let block_end = offset + samples.len();
+    let to_index = |seconds: f32| ((seconds.max(0.0) as f64 * sample_rate as f64) as usize).clamp(offset, block_end);
+    for span in spans {
+        let start = to_index(span.start);
+        let end = to_index(span.end).max(start);
+        for (i, sample) in samples[start - offset..end - offset].iter_mut().enumerate() {
+            *sample = match mode {
+                AudioRedaction::Silence => 0.0,
+                AudioRedaction::Bleep => {
+                    let t = (start + i) as f64 / sample_rate as f64;
+                    BLEEP_AMPLITUDE * (2.0 * std::f64::consts::PI * BLEEP_FREQUENCY_HZ as f64 * t).sin() as f32
+                }
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactedAudio {
+    pub path: PathBuf,
+    pub spans: Vec<RedactionSpan>,
+    // Always set: how far the span positions can be trusted
+    pub timing_notice: &'static str,
+}
+
+// Decodes the session audio across all segments a block at a time, bleeps or silences
+// every span and streams the result to an encoder matching the output file's extension.
+// Blocking; run it off the async runtime.
+pub fn export_redacted_audio(
+    session_id: &str,
+    spans: Vec<RedactionSpan>,
+    mode: AudioRedaction,
+    output_path: &Path,
+) -> Result<RedactedAudio> {
+    let (files, layout) = clip::session_files(session_id)?;
+    let mut audio = clip::SessionAudio::open(&files, layout)?;
+    let sample_rate = audio.sample_rate();
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let runtime = tokio::runtime::Handle::current();
+    // The extension is corrected if ffmpeg is missing and the format can't be written
+    let mut encoder = StreamingEncoder::start(output_path, sample_rate, 1, EncodingProfile::for_path(output_path))?;
+    let mut offset = 0;
+    let streamed = loop {
+        let mut block = match audio.next_block() {
+            Ok(Some(block)) => block,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        redact_samples(&mut block, offset, sample_rate, &spans, mode);
+        offset += block.len();
+        match runtime.block_on(encoder.push(block)) {
+            Ok(PushOutcome::Written) => {}
+            // A restart would split the export across files
+            Ok(PushOutcome::Restarted { error, .. }) | Err(error) => break Err(error.into()),
+        }
+    };
+    let finished = runtime.block_on(encoder.finish());
+    streamed?;
+    if let Some(error) = finished.error {
+        return Err(error.into());
+    }
+    let path = finished
+        .segments
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Encoder wrote no redacted audio"))?;
+    info!("Wrote redacted audio for session {} to {:?}", session_id, path);
+    Ok(RedactedAudio {
+        path,
+        spans,
+        timing_notice: TIMING_NOTICE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn line(text: &str, start: f32, end: f32) -> TranscriptLine {
+        TranscriptLine {
+            sequence: 7,
+            text: text.to_string(),
+            start,
+            end,
+            source: "mic".to_string(),
+            speaker: None,
+            recorded_at: Utc::now(),
+        }
+    }
+
+    fn span(start: f32, end: f32) -> RedactionSpan {
+        RedactionSpan {
+            line_sequence: 0,
+            category: PiiCategory::Term,
+            start_byte: 0,
+            end_byte: 0,
+            start,
+            end,
+        }
+    }
+
+    fn found(text: &str, options: &RedactionOptions, names: &[String]) -> Vec<(PiiCategory, String)> {
+        let detectors = detectors(options, names).unwrap();
+        detect_line(&line(text, 0.0, 10.0), &detectors, (0.0, 10.0))
+            .into_iter()
+            .map(|s| (s.category, text[s.start_byte..s.end_byte].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn luhn_checks_digits_and_length() {
+        assert!(luhn_valid("4111 1111 1111 1111"));
+        assert!(luhn_valid("4111-1111-1111-1111"));
+        assert!(luhn_valid("378282246310005"));
+        assert!(!luhn_valid("4111 1111 1111 1112"));
+        // Too short or too long for a card, even with a valid checksum
+        assert!(!luhn_valid("0000 0000 0000"));
+        assert!(!luhn_valid("00000000000000000000"));
+    }
+
+    #[test]
+    fn phones_need_nine_to_fifteen_digits() {
+        assert!(accept(PiiCategory::Phone, "555 0100 123"));
+        assert!(accept(PiiCategory::Phone, "+44 (20) 7946 0958"));
+        assert!(!accept(PiiCategory::Phone, "2019-2023"));
+        assert!(!accept(PiiCategory::Phone, "1234 5678 9012 3456"));
+        assert!(!accept(PiiCategory::CreditCard, "1234 5678 9012 3456"));
+        assert!(accept(PiiCategory::Email, "anything"));
+    }
+
+    #[test]
+    fn terms_match_whole_words_in_any_case() {
+        assert!(term_regex(&[]).unwrap().is_none());
+        assert!(term_regex(&[" ".to_string()]).unwrap().is_none());
+
+        let pattern = term_regex(&["Project X".to_string(), "c++".to_string(), "ana".to_string()])
+            .unwrap()
+            .unwrap();
+        assert!(pattern.is_match("about PROJECT x today"));
+        assert!(pattern.is_match("Ana said"));
+        assert!(!pattern.is_match("banana bread"));
+        // Terms are literal text, not patterns
+        assert!(pattern.is_match("we use c++ here"));
+        assert!(!pattern.is_match("we use cc here"));
+    }
+
+    #[test]
+    fn overlapping_matches_go_to_the_earlier_detector() {
+        let options = RedactionOptions {
+            terms: vec!["4111".to_string(), "budget".to_string()],
+            ..RedactionOptions::default()
+        };
+        let text = "Card 4111 1111 1111 1111, mail ana@example.com or call 555 0100 123 about the budget";
+        assert_eq!(
+            found(text, &options, &["Ana".to_string()]),
+            vec![
+                (PiiCategory::CreditCard, "4111 1111 1111 1111".to_string()),
+                (PiiCategory::Email, "ana@example.com".to_string()),
+                (PiiCategory::Phone, "555 0100 123".to_string()),
+                (PiiCategory::Term, "budget".to_string()),
+            ]
+        );
+
+        let skip_cards = RedactionOptions {
+            skip: vec![PiiCategory::CreditCard, PiiCategory::Phone],
+            ..options
+        };
+        assert_eq!(
+            found("Card 4111 1111 1111 1111 for Ana", &skip_cards, &["Ana".to_string()]),
+            vec![
+                (PiiCategory::Term, "4111".to_string()),
+                (PiiCategory::Name, "Ana".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn span_times_follow_the_character_offset_within_bounds() {
+        let detectors = detectors(&RedactionOptions::default(), &["Bob".to_string()]).unwrap();
+        // "Bob" covers the middle fifth of a ten-second line
+        let text = "xxxxxx Bob xxxxx";
+        let spans = detect_line(&line(text, 10.0, 26.0), &detectors, (9.8, 30.0));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].line_sequence, 7);
+        assert!((spans[0].start - (17.0 - AUDIO_PADDING_SECS)).abs() < 1e-4);
+        assert!((spans[0].end - (20.0 + AUDIO_PADDING_SECS)).abs() < 1e-4);
+
+        // Padding stops at the neighbouring lines
+        let spans = detect_line(&line("Bob", 10.0, 11.0), &detectors, (9.9, 11.1));
+        assert_eq!((spans[0].start, spans[0].end), (9.9, 11.1));
+    }
+
+    #[test]
+    fn spans_are_replaced_by_placeholders() {
+        let text = "mail ana@example.com or 555 0100 123";
+        let detectors = detectors(&RedactionOptions::default(), &[]).unwrap();
+        let spans = detect_line(&line(text, 0.0, 1.0), &detectors, (0.0, 1.0));
+        assert_eq!(apply_spans(text, &spans), "mail [EMAIL] or [PHONE]");
+        assert_eq!(apply_spans("nothing here", &[]), "nothing here");
+    }
+
+    #[test]
+    fn samples_are_redacted_the_same_block_by_block() {
+        let rate = 100;
+        let spans = [span(0.25, 0.5), span(0.75, 0.875), span(2.0, 3.0)];
+        let mut whole = vec![1.0f32; 100];
+        redact_samples(&mut whole, 0, rate, &spans, AudioRedaction::Silence);
+        let silent: Vec<usize> = (0..whole.len()).filter(|&i| whole[i] == 0.0).collect();
+        assert_eq!(silent, (25..50).chain(75..87).collect::<Vec<_>>());
+
+        let mut blocks = vec![1.0f32; 100];
+        for (i, block) in blocks.chunks_mut(17).enumerate() {
+            redact_samples(block, i * 17, rate, &spans, AudioRedaction::Silence);
+        }
+        assert_eq!(blocks, whole);
+
+        // The bleep keeps its phase across block boundaries
+        let mut whole = vec![0.0f32; 100];
+        redact_samples(&mut whole, 0, 8000, &[span(0.0, 1.0)], AudioRedaction::Bleep);
+        let mut blocks = vec![0.0f32; 100];
+        for (i, block) in blocks.chunks_mut(33).enumerate() {
+            redact_samples(block, i * 33, 8000, &[span(0.0, 1.0)], AudioRedaction::Bleep);
+        }
+        assert_eq!(blocks, whole);
+        assert!(whole.iter().all(|s| s.abs() <= BLEEP_AMPLITUDE));
+        assert!(whole.iter().any(|s| s.abs() > BLEEP_AMPLITUDE * 0.9));
+    }
+}
@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::journal::{self, SessionTranscript, TranscriptLine};
+
+const DEFAULT_LIMIT: usize = 50;
+// Characters of context kept on either side of a hit in the snippet
+const SNIPPET_CONTEXT_CHARS: usize = 80;
+
+// Built on the first search, then kept current as lines are finalized
+static SEARCH_INDEX: Lazy<RwLock<Option<TranscriptIndex>>> = Lazy::new(|| RwLock::new(None));
+// Changes that arrive while a build runs outside the lock; `Some` only during a build,
+// replayed into the new index before it is swapped in
+static PENDING_CHANGES: Lazy<Mutex<Option<Vec<PendingChange>>>> = Lazy::new(|| Mutex::new(None));
+
+enum PendingChange {
+    Line(String, TranscriptLine),
+    Remove(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    // Every word must appear in the line, in any order
+    #[default]
+    Words,
+    Phrase,
+    // Words match any term they are a prefix of
+    Prefix,
+    Regex,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchQuery {
+    pub text: String,
+    #[serde(default)]
+    pub mode: SearchMode,
+    #[serde(default)]
+    pub session_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub sequence: u64,
+    pub start: f32,
+    pub end: f32,
+    pub score: f32,
+    pub snippet: String,
+    // Byte ranges within `snippet` to highlight
+    pub highlights: Vec<(usize, usize)>,
+}
+
+struct IndexedLine {
+    session_id: String,
+    sequence: u64,
+    start: f32,
+    end: f32,
+    text: String,
+    token_count: usize,
+}
+
+#[derive(Default)]
+pub struct TranscriptIndex {
+    lines: Vec<IndexedLine>,
+    // term -> (line index, occurrences); sorted so prefix queries are a range scan
+    postings: BTreeMap<String, Vec<(u32, u16)>>,
+    // Next unindexed sequence per session, so the same line is never indexed twice
+    next_sequence: HashMap<String, u64>,
+    removed: HashSet<u32>,
+    // Sessions that could not be loaded, e.g. while the vault is locked; only these are
+    // read again on later searches
+    failed: HashSet<String>,
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+impl TranscriptIndex {
+    pub fn build() -> Result<Self> {
+        let mut index = TranscriptIndex::default();
+        for session_id in journal::list_session_ids()? {
+            match journal::load_transcript(&session_id) {
+                Ok(transcript) => {
+                    for line in &transcript.lines {
+                        index.add_line(&session_id, line);
+                    }
+                }
+                Err(e) => {
+                    warn!("Skipping session {} while indexing: {}", session_id, e);
+                    index.failed.insert(session_id);
+                }
+            }
+        }
+        info!(
+            "Built transcript index: {} lines, {} terms",
+            index.lines.len(),
+            index.postings.len()
+        );
+        Ok(index)
+    }
+
+    pub fn add_line(&mut self, session_id: &str, line: &TranscriptLine) {
+        let next = self.next_sequence.entry(session_id.to_string()).or_insert(0);
+        if line.sequence < *next {
+            return;
+        }
+        *next = line.sequence + 1;
+
+        let id = self.lines.len() as u32;
+        let tokens = tokenize(&line.text);
+        let mut counts: HashMap<&str, u16> = HashMap::new();
+        for token in &tokens {
+            *counts.entry(token.as_str()).or_insert(0) += 1;
+        }
+        for (token, count) in counts {
+            self.postings.entry(token.to_string()).or_default().push((id, count));
+        }
+
+        self.lines.push(IndexedLine {
+            session_id: session_id.to_string(),
+            sequence: line.sequence,
+            start: line.start,
+            end: line.end,
+            text: line.text.clone(),
+            token_count: tokens.len(),
+        });
+    }
+
+    pub fn remove_session(&mut self, session_id: &str) {
+        for (id, line) in self.lines.iter().enumerate() {
+            if line.session_id == session_id {
+                self.removed.insert(id as u32);
+            }
+        }
+        self.next_sequence.remove(session_id);
+        self.failed.remove(session_id);
+    }
+
+    // Indexes sessions that failed earlier and have now been read. Anything indexed for
+    // them in the meantime is replaced by the full transcript.
+    fn add_loaded(&mut self, loaded: Vec<(String, SessionTranscript)>) {
+        for (session_id, transcript) in loaded {
+            // Removed while it was being read
+            if !self.failed.contains(&session_id) {
+                continue;
+            }
+            self.remove_session(&session_id);
+            for line in &transcript.lines {
+                self.add_line(&session_id, line);
+            }
+        }
+    }
+
+    fn live_lines(&self) -> usize {
+        self.lines.len() - self.removed.len()
+    }
+
+    fn idf(&self, postings_len: usize) -> f32 {
+        ((self.live_lines() as f32 + 1.0) / (postings_len as f32 + 1.0)).ln() + 1.0
+    }
+
+    // Scores candidate lines that contain every term group; each group is a set of
+    // alternative terms (a single word, or all expansions of a prefix)
+    fn match_groups(&self, groups: &[Vec<&str>]) -> HashMap<u32, f32> {
+        let mut scores: Option<HashMap<u32, f32>> = None;
+
+        for group in groups {
+            let mut group_scores: HashMap<u32, f32> = HashMap::new();
+            for term in group {
+                if let Some(postings) = self.postings.get(*term) {
+                    let idf = self.idf(postings.len());
+                    for (id, count) in postings {
+                        *group_scores.entry(*id).or_insert(0.0) += *count as f32 * idf;
+                    }
+                }
+            }
+
+            scores = Some(match scores {
+                None => group_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(id, score)| group_scores.get(&id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut scores = scores.unwrap_or_default();
+        scores.retain(|id, _| !self.removed.contains(id));
+        // Shorter lines with the same matches are more focused hits
+        for (id, score) in scores.iter_mut() {
+            let length = self.lines[*id as usize].token_count.max(1) as f32;
+            *score /= length.sqrt();
+        }
+        scores
+    }
+
+    fn prefix_terms(&self, prefix: &str) -> Vec<&str> {
+        self.postings
+            .range(prefix.to_string()..)
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .map(|(term, _)| term.as_str())
+            .collect()
+    }
+
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let tokens = tokenize(&query.text);
+
+        // Each mode yields scored line ids plus a regex used to locate highlights
+        let (scores, highlighter) = match query.mode {
+            SearchMode::Words => {
+                let groups: Vec<Vec<&str>> = tokens.iter().map(|t| vec![t.as_str()]).collect();
+                (self.match_groups(&groups), word_regex(&tokens, false)?)
+            }
+            SearchMode::Prefix => {
+                let groups: Vec<Vec<&str>> = tokens.iter().map(|t| self.prefix_terms(t)).collect();
+                (self.match_groups(&groups), word_regex(&tokens, true)?)
+            }
+            SearchMode::Phrase => {
+                let groups: Vec<Vec<&str>> = tokens.iter().map(|t| vec![t.as_str()]).collect();
+                let phrase = phrase_regex(&tokens)?;
+                let mut scores = self.match_groups(&groups);
+                scores.retain(|id, _| phrase.is_match(&self.lines[*id as usize].text));
+                (scores, phrase)
+            }
+            SearchMode::Regex => {
+                let pattern = RegexBuilder::new(&query.text)
+                    .case_insensitive(true)
+                    .size_limit(1 << 20)
+                    .build()
+                    .map_err(|e| anyhow!("Invalid regex: {}", e))?;
+                let scores = self
+                    .lines
+                    .iter()
+                    .enumerate()
+                    .filter(|(id, _)| !self.removed.contains(&(*id as u32)))
+                    .filter_map(|(id, line)| {
+                        let matches = pattern.find_iter(&line.text).count();
+                        (matches > 0).then_some((id as u32, matches as f32))
+                    })
+                    .collect();
+                (scores, pattern)
+            }
+        };
+
+        let allowed: Option<HashSet<&str>> = query
+            .session_ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+
+        let mut ranked: Vec<(u32, f32)> = scores
+            .into_iter()
+            .filter(|(id, _)| match &allowed {
+                Some(ids) => ids.contains(self.lines[*id as usize].session_id.as_str()),
+                None => true,
+            })
+            .collect();
+        // Best score first; ties go to the most recent session (ids start with a timestamp)
+        ranked.sort_by(|(a_id, a), (b_id, b)| {
+            b.partial_cmp(a)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| {
+                    let (a, b) = (&self.lines[*a_id as usize], &self.lines[*b_id as usize]);
+                    b.session_id.cmp(&a.session_id).then(a.sequence.cmp(&b.sequence))
+                })
+        });
+        ranked.truncate(limit);
+
+        Ok(ranked
+            .into_iter()
+            .map(|(id, score)| {
+                let line = &self.lines[id as usize];
+                let (snippet, highlights) = make_snippet(&line.text, &highlighter);
+                SearchHit {
+                    session_id: line.session_id.clone(),
+                    sequence: line.sequence,
+                    start: line.start,
+                    end: line.end,
+                    score,
+                    snippet,
+                    highlights,
+                }
+            })
+            .collect())
+    }
+}
+
+fn word_regex(tokens: &[String], prefix: bool) -> Result<Regex> {
+    if tokens.is_empty() {
+        return Err(anyhow!("Search query is empty"));
+    }
+    let alternatives: Vec<String> = tokens.iter().map(|t| regex::escape(t)).collect();
+    let suffix = if prefix { r"\w*" } else { r"\b" };
+    Ok(RegexBuilder::new(&format!(r"\b(?:{}){}", alternatives.join("|"), suffix))
+        .case_insensitive(true)
+        .build()?)
+}
+
+// Words in order, separated by anything that isn't a word character
+fn phrase_regex(tokens: &[String]) -> Result<Regex> {
+    if tokens.is_empty() {
+        return Err(anyhow!("Search query is empty"));
+    }
+    let words: Vec<String> = tokens.iter().map(|t| regex::escape(t)).collect();
+    Ok(RegexBuilder::new(&format!(r"\b{}\b", words.join(r"\W+")))
+        .case_insensitive(true)
+        .build()?)
+}
+
+fn make_snippet(text: &str, highlighter: &Regex) -> (String, Vec<(usize, usize)>) {
+    let Some(first) = highlighter.find(text) else {
+        return (text.to_string(), Vec::new());
+    };
+
+    let mut start = first.start();
+    for _ in 0..SNIPPET_CONTEXT_CHARS {
+        match text[..start].char_indices().next_back() {
+            Some((index, _)) => start = index,
+            None => break,
+        }
+    }
+    let mut end = first.end();
+    for _ in 0..SNIPPET_CONTEXT_CHARS {
+        match text[end..].chars().next() {
+            Some(c) => end += c.len_utf8(),
+            None => break,
+        }
+    }
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let snippet = format!("{}{}{}", prefix, &text[start..end], suffix);
+    let highlights = highlighter
+        .find_iter(&text[start..end])
+        .map(|m| (m.start() + prefix.len(), m.end() + prefix.len()))
+        .collect();
+    (snippet, highlights)
+}
+
+// Queues the change while a build is running; hands it back when the caller should
+// apply it to the current index instead
+fn queue_change(change: PendingChange) -> Option<PendingChange> {
+    let mut pending = PENDING_CHANGES.lock().unwrap_or_else(PoisonError::into_inner);
+    match pending.as_mut() {
+        Some(changes) => {
+            changes.push(change);
+            None
+        }
+        None => Some(change),
+    }
+}
+
+// Called for every finalized line; a no-op until the index has been built by a search
+pub fn index_line(session_id: &str, line: &TranscriptLine) {
+    if queue_change(PendingChange::Line(session_id.to_string(), line.clone())).is_none() {
+        return;
+    }
+    if let Ok(mut guard) = SEARCH_INDEX.write() {
+        if let Some(index) = guard.as_mut() {
+            index.add_line(session_id, line);
+        }
+    }
+}
+
+pub fn remove_session(session_id: &str) {
+    if queue_change(PendingChange::Remove(session_id.to_string())).is_none() {
+        return;
+    }
+    if let Ok(mut guard) = SEARCH_INDEX.write() {
+        if let Some(index) = guard.as_mut() {
+            index.remove_session(session_id);
+        }
+    }
+}
+
+// Drops the cached index so the next search rebuilds it, e.g. after the vault is
+// unlocked and sessions that failed to load become readable
+pub fn invalidate() {
+    if let Ok(mut guard) = SEARCH_INDEX.write() {
+        *guard = None;
+    }
+}
+
+fn load_sessions(session_ids: Vec<String>) -> Vec<(String, SessionTranscript)> {
+    session_ids
+        .into_iter()
+        .filter_map(|session_id| match journal::load_transcript(&session_id) {
+            Ok(transcript) => Some((session_id, transcript)),
+            Err(e) => {
+                debug!("Session {} is still unreadable: {}", session_id, e);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn search(query: &SearchQuery) -> Result<Vec<SearchHit>> {
+    let failed: Option<Vec<String>> = {
+        let guard = SEARCH_INDEX.read().map_err(|_| anyhow!("Search index lock poisoned"))?;
+        match guard.as_ref() {
+            Some(index) if index.failed.is_empty() => return index.search(query),
+            Some(index) => Some(index.failed.iter().cloned().collect()),
+            None => None,
+        }
+    };
+
+    if let Some(failed) = failed {
+        // Read outside the lock, like a full build
+        let loaded = load_sessions(failed);
+        let mut guard = SEARCH_INDEX.write().map_err(|_| anyhow!("Search index lock poisoned"))?;
+        if let Some(index) = guard.as_mut() {
+            if !loaded.is_empty() {
+                info!("Indexing {} session(s) that were unreadable before", loaded.len());
+                index.add_loaded(loaded);
+            }
+            return index.search(query);
+        }
+        // Invalidated in the meantime; fall through to a full build
+    }
+
+    // Build without holding the lock so the recording loop never waits on a full scan
+    PENDING_CHANGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(Vec::new);
+    let built = TranscriptIndex::build();
+
+    let mut guard = SEARCH_INDEX.write().map_err(|_| anyhow!("Search index lock poisoned"))?;
+    // Taken under the write lock, so a change arriving now waits for the swap below
+    let pending = PENDING_CHANGES.lock().unwrap_or_else(PoisonError::into_inner).take();
+    let mut index = built?;
+    for change in pending.unwrap_or_default() {
+        match change {
+            PendingChange::Line(session_id, line) => index.add_line(&session_id, &line),
+            PendingChange::Remove(session_id) => index.remove_session(&session_id),
+        }
+    }
+    let hits = index.search(query)?;
+    if !index.failed.is_empty() {
+        warn!("{} session(s) could not be indexed; retrying them on the next search", index.failed.len());
+    }
+    if guard.is_none() {
+        *guard = Some(index);
+    }
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn line(sequence: u64, text: &str) -> TranscriptLine {
+        TranscriptLine {
+            sequence,
+            text: text.to_string(),
+            start: sequence as f32 * 10.0,
+            end: sequence as f32 * 10.0 + 5.0,
+            source: "mic".to_string(),
+            speaker: None,
+            recorded_at: Utc::now(),
+        }
+    }
+
+    fn indexed(sessions: &[(&str, &[&str])]) -> TranscriptIndex {
+        let mut index = TranscriptIndex::default();
+        for (session_id, texts) in sessions {
+            for (sequence, text) in texts.iter().enumerate() {
+                index.add_line(session_id, &line(sequence as u64, text));
+            }
+        }
+        index
+    }
+
+    fn query(text: &str, mode: SearchMode) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            mode,
+            session_ids: None,
+            limit: None,
+        }
+    }
+
+    fn found(index: &TranscriptIndex, query: &SearchQuery) -> Vec<(String, u64)> {
+        index
+            .search(query)
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.session_id, hit.sequence))
+            .collect()
+    }
+
+    fn hit(session_id: &str, sequence: u64) -> (String, u64) {
+        (session_id.to_string(), sequence)
+    }
+
+    const LINES: &[&str] = &[
+        "The budget review is on Friday",
+        "Review the budget before then",
+        "Budgets were reviewed last quarter",
+        "Call 555-0100 about the venue",
+    ];
+
+    #[test]
+    fn words_match_in_any_order() {
+        let index = indexed(&[("s1", LINES)]);
+        let mut hits = found(&index, &query("BUDGET review", SearchMode::Words));
+        hits.sort();
+        assert_eq!(hits, vec![hit("s1", 0), hit("s1", 1)]);
+        assert!(found(&index, &query("budget venue", SearchMode::Words)).is_empty());
+        assert!(index.search(&query("  ", SearchMode::Words)).is_err());
+    }
+
+    #[test]
+    fn phrase_needs_the_words_in_order() {
+        let index = indexed(&[("s1", LINES)]);
+        assert_eq!(found(&index, &query("budget review", SearchMode::Phrase)), vec![hit("s1", 0)]);
+        assert_eq!(found(&index, &query("review, the budget", SearchMode::Phrase)), vec![hit("s1", 1)]);
+    }
+
+    #[test]
+    fn prefix_expands_each_word() {
+        let index = indexed(&[("s1", LINES)]);
+        let mut hits = found(&index, &query("budg revi", SearchMode::Prefix));
+        hits.sort();
+        assert_eq!(hits, vec![hit("s1", 0), hit("s1", 1), hit("s1", 2)]);
+        assert!(found(&index, &query("udget", SearchMode::Prefix)).is_empty());
+    }
+
+    #[test]
+    fn regex_matches_raw_text() {
+        let index = indexed(&[("s1", LINES)]);
+        assert_eq!(found(&index, &query(r"\d{3}-\d{4}", SearchMode::Regex)), vec![hit("s1", 3)]);
+        assert_eq!(found(&index, &query("^review", SearchMode::Regex)), vec![hit("s1", 1)]);
+        assert!(index.search(&query("(unclosed", SearchMode::Regex)).is_err());
+    }
+
+    #[test]
+    fn ranking_prefers_focused_lines_then_recent_sessions() {
+        let index = indexed(&[
+            ("20240101-090000-0001", &["budget", "budget and several other words in a long line"]),
+            ("20240301-090000-0001", &["budget"]),
+        ]);
+        let hits = index.search(&query("budget", SearchMode::Words)).unwrap();
+        let order: Vec<_> = hits.iter().map(|h| (h.session_id.as_str(), h.sequence)).collect();
+        assert_eq!(
+            order,
+            [("20240301-090000-0001", 0), ("20240101-090000-0001", 0), ("20240101-090000-0001", 1)]
+        );
+        assert!(hits[1].score > hits[2].score);
+        assert_eq!(hits[0].start, 0.0);
+        assert_eq!(hits[1].end, 5.0);
+    }
+
+    #[test]
+    fn snippets_are_trimmed_around_the_first_hit() {
+        let text = format!("{} the budget is final {}", "é".repeat(200), "x".repeat(200));
+        let index = indexed(&[("s1", &[text.as_str()])]);
+        let hit = &index.search(&query("budget", SearchMode::Words)).unwrap()[0];
+        assert!(hit.snippet.starts_with('…') && hit.snippet.ends_with('…'), "{}", hit.snippet);
+        assert!(hit.snippet.chars().count() <= 2 * SNIPPET_CONTEXT_CHARS + "budget".len() + 2);
+        assert_eq!(hit.highlights.len(), 1);
+        let (start, end) = hit.highlights[0];
+        assert_eq!(&hit.snippet[start..end], "budget");
+
+        // Short lines are kept whole with every match highlighted
+        let index = indexed(&[("s1", &["Budget, budget, budget"])]);
+        let hit = &index.search(&query("budget", SearchMode::Words)).unwrap()[0];
+        assert_eq!(hit.snippet, "Budget, budget, budget");
+        assert_eq!(hit.highlights, vec![(0, 6), (8, 14), (16, 22)]);
+    }
+
+    #[test]
+    fn lines_are_indexed_once_and_removed_with_their_session() {
+        let mut index = indexed(&[("s1", &["alpha"]), ("s2", &["alpha"])]);
+        // A line replayed after a build is not indexed twice
+        index.add_line("s1", &line(0, "alpha"));
+        index.add_line("s1", &line(1, "alpha beta"));
+        let mut hits = found(&index, &query("alpha", SearchMode::Words));
+        hits.sort();
+        assert_eq!(hits, vec![hit("s1", 0), hit("s1", 1), hit("s2", 0)]);
+
+        index.remove_session("s1");
+        assert_eq!(found(&index, &query("alpha", SearchMode::Words)), vec![hit("s2", 0)]);
+        // A session can be indexed again after it was removed
+        index.add_line("s1", &line(0, "alpha"));
+        assert_eq!(found(&index, &query("alpha", SearchMode::Words)).len(), 2);
+    }
+
+    #[test]
+    fn search_can_be_limited_to_sessions() {
+        let index = indexed(&[("s1", &["alpha"]), ("s2", &["alpha"]), ("s3", &["alpha"])]);
+        let mut scoped = query("alpha", SearchMode::Words);
+        scoped.session_ids = Some(vec!["s1".to_string(), "s3".to_string()]);
+        let mut hits = found(&index, &scoped);
+        hits.sort();
+        assert_eq!(hits, vec![hit("s1", 0), hit("s3", 0)]);
+
+        scoped.limit = Some(1);
+        assert_eq!(found(&index, &scoped).len(), 1);
+    }
+
+    #[test]
+    fn unreadable_sessions_are_retried_alone() {
+        let session_id = "search-unreadable";
+        let journal = journal::SessionJournal::create(session_id).unwrap();
+        journal.append_line("quarterly forecast", 0.0, 1.0, "mic").unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+        let path = journal::journal_path(session_id);
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::write(&path, [&contents[..], b"\xff\xfe\n"].concat()).unwrap();
+
+        let mut index = TranscriptIndex::build().unwrap();
+        assert!(index.failed.contains(session_id));
+        assert!(found(&index, &query("forecast", SearchMode::Words)).is_empty());
+
+        // Still unreadable: nothing changes
+        index.add_loaded(load_sessions(vec![session_id.to_string()]));
+        assert!(index.failed.contains(session_id));
+
+        std::fs::write(&path, contents).unwrap();
+        index.add_loaded(load_sessions(index.failed.iter().cloned().collect()));
+        assert!(!index.failed.contains(session_id));
+        assert_eq!(found(&index, &query("forecast", SearchMode::Words)), vec![hit(session_id, 0)]);
+    }
+}
@@ -0,0 +1,450 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::journal::{self, TranscriptLine};
+use crate::ollama::OllamaClient;
+use crate::vault;
+
+pub const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+const DEFAULT_LIMIT: usize = 10;
+// Consecutive lines are grouped until a passage reaches roughly this many characters
+const PASSAGE_TARGET_CHARS: usize = 600;
+const VECTORS_FILE_NAME: &str = "embeddings.bin";
+const PASSAGES_FILE_NAME: &str = "embeddings.json";
+const VECTORS_MAGIC: &[u8; 4] = b"VNEM";
+const VECTORS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passage {
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+// Sidecar describing the rows of `embeddings.bin`, in the same order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PassageManifest {
+    model: String,
+    dimensions: usize,
+    // First line sequence not yet covered by a passage
+    next_sequence: u64,
+    passages: Vec<Passage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticHit {
+    pub session_id: String,
+    pub score: f32,
+    #[serde(flatten)]
+    pub passage: Passage,
+}
+
+// Nearest-neighbour lookup over normalized vectors. Brute force is exact and fast
+// enough for a personal meeting archive; an ANN structure can implement this later.
+pub trait VectorIndex {
+    fn insert(&mut self, key: usize, vector: Vec<f32>);
+    fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)>;
+}
+
+#[derive(Default)]
+pub struct BruteForceIndex {
+    entries: Vec<(usize, Vec<f32>)>,
+}
+
+impl VectorIndex for BruteForceIndex {
+    fn insert(&mut self, key: usize, vector: Vec<f32>) {
+        self.entries.push((key, normalize(vector)));
+    }
+
+    fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let query = normalize(query.to_vec());
+        let mut scored: Vec<(usize, f32)> = self
+            .entries
+            .iter()
+            .filter(|(_, vector)| vector.len() == query.len())
+            .map(|(key, vector)| (*key, dot(vector, &query)))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(k);
+        scored
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = dot(&vector, &vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn vectors_path(session_id: &str) -> PathBuf {
+    journal::session_dir(session_id).join(VECTORS_FILE_NAME)
+}
+
+fn manifest_path(session_id: &str) -> PathBuf {
+    journal::session_dir(session_id).join(PASSAGES_FILE_NAME)
+}
+
+// Vectors can be inverted back to text closely enough to leak the meeting, so they are
+// encrypted like the transcript
+fn write_vectors(session_id: &str, dimensions: usize, vectors: &[Vec<f32>]) -> Result<()> {
+    let mut bytes = Vec::with_capacity(16 + vectors.len() * dimensions * 4);
+    bytes.extend_from_slice(VECTORS_MAGIC);
+    bytes.extend_from_slice(&VECTORS_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(dimensions as u32).to_le_bytes());
+    bytes.extend_from_slice(&(vectors.len() as u32).to_le_bytes());
+    for vector in vectors {
+        for value in vector {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    vault::write(vectors_path(session_id), &bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_vectors(session_id: &str) -> Result<Vec<Vec<f32>>> {
+    let bytes = vault::read(vectors_path(session_id))?;
+    let mut reader = bytes.as_slice();
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != VECTORS_MAGIC {
+        return Err(anyhow!("Not an embeddings file"));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != VECTORS_VERSION {
+        return Err(anyhow!("Unsupported embeddings file version {}", version));
+    }
+    let dimensions = read_u32(&mut reader)? as usize;
+    let count = read_u32(&mut reader)? as usize;
+
+    let mut vectors = Vec::with_capacity(count);
+    let mut row = vec![0u8; dimensions * 4];
+    for _ in 0..count {
+        reader.read_exact(&mut row)?;
+        vectors.push(
+            row.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        );
+    }
+    Ok(vectors)
+}
+
+fn load_manifest(session_id: &str) -> Option<PassageManifest> {
+    let bytes = vault::read(manifest_path(session_id)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+pub fn build_passages(lines: &[TranscriptLine]) -> Vec<Passage> {
+    let mut passages = Vec::new();
+    let mut current: Option<Passage> = None;
+
+    for line in lines {
+        let text = line.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match current.as_mut() {
+            Some(passage) => {
+                passage.text.push(' ');
+                passage.text.push_str(text);
+                passage.last_sequence = line.sequence;
+                passage.end = line.end;
+            }
+            None => {
+                current = Some(Passage {
+                    first_sequence: line.sequence,
+                    last_sequence: line.sequence,
+                    start: line.start,
+                    end: line.end,
+                    text: text.to_string(),
+                });
+            }
+        }
+        if current.as_ref().is_some_and(|p| p.text.len() >= PASSAGE_TARGET_CHARS) {
+            passages.extend(current.take());
+        }
+    }
+    passages.extend(current);
+    passages
+}
+
+// Embeds any passages not yet on disk for this session and returns all of them with their vectors
+pub async fn ensure_session_embeddings(
+    client: &OllamaClient,
+    session_id: &str,
+    model: &str,
+) -> Result<(Vec<Passage>, Vec<Vec<f32>>)> {
+    let transcript = journal::load_transcript(session_id)?;
+
+    let (mut manifest, mut vectors) = match load_manifest(session_id) {
+        Some(manifest) if manifest.model == model => match read_vectors(session_id) {
+            Ok(vectors) if vectors.len() == manifest.passages.len() => (manifest, vectors),
+            _ => {
+                warn!("Embeddings for session {} are inconsistent, rebuilding", session_id);
+                (PassageManifest::empty(model), Vec::new())
+            }
+        },
+        _ => (PassageManifest::empty(model), Vec::new()),
+    };
+
+    let pending: Vec<TranscriptLine> = transcript
+        .lines
+        .into_iter()
+        .filter(|line| line.sequence >= manifest.next_sequence)
+        .collect();
+    if pending.is_empty() {
+        return Ok((manifest.passages, vectors));
+    }
+
+    let passages = build_passages(&pending);
+    info!("Embedding {} passage(s) for session {}", passages.len(), session_id);
+    for passage in passages {
+        let vector = client.embed(model, &passage.text).await?;
+        if manifest.dimensions == 0 {
+            manifest.dimensions = vector.len();
+        } else if vector.len() != manifest.dimensions {
+            return Err(anyhow!(
+                "Embedding size changed from {} to {}",
+                manifest.dimensions,
+                vector.len()
+            ));
+        }
+        vectors.push(vector);
+        manifest.passages.push(passage);
+    }
+    if let Some(last) = pending.last() {
+        manifest.next_sequence = last.sequence + 1;
+    }
+
+    write_vectors(session_id, manifest.dimensions, &vectors)?;
+    // The manifest holds passage text, so it is encrypted like the transcript
+    vault::write(manifest_path(session_id), &serde_json::to_vec(&manifest)?)?;
+    Ok((manifest.passages, vectors))
+}
+
+impl PassageManifest {
+    fn empty(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            dimensions: 0,
+            next_sequence: 0,
+            passages: Vec::new(),
+        }
+    }
+}
+
+pub async fn semantic_search(
+    client: &OllamaClient,
+    query: &str,
+    model: &str,
+    session_ids: Option<Vec<String>>,
+    limit: Option<usize>,
+) -> Result<Vec<SemanticHit>> {
+    if query.trim().is_empty() {
+        return Err(anyhow!("Search query is empty"));
+    }
+
+    let session_ids = match session_ids {
+        Some(ids) => ids,
+        None => journal::list_session_ids()?,
+    };
+
+    let mut index = BruteForceIndex::default();
+    let mut passages: Vec<(String, Passage)> = Vec::new();
+    for session_id in session_ids {
+        let (session_passages, vectors) =
+            match ensure_session_embeddings(client, &session_id, model).await {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!("Skipping session {} in semantic search: {}", session_id, e);
+                    continue;
+                }
+            };
+        for (passage, vector) in session_passages.into_iter().zip(vectors) {
+            index.insert(passages.len(), vector);
+            passages.push((session_id.clone(), passage));
+        }
+    }
+
+    let query_vector = client.embed(model, query).await?;
+    Ok(index
+        .nearest(&query_vector, limit.unwrap_or(DEFAULT_LIMIT))
+        .into_iter()
+        .map(|(key, score)| {
+            let (session_id, passage) = passages[key].clone();
+            SemanticHit { session_id, score, passage }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::SessionJournal;
+    use chrono::Utc;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    fn line(sequence: u64, text: &str) -> TranscriptLine {
+        TranscriptLine {
+            sequence,
+            text: text.to_string(),
+            start: sequence as f32 * 10.0,
+            end: sequence as f32 * 10.0 + 5.0,
+            source: "mic".to_string(),
+            speaker: None,
+            recorded_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn passages_group_lines_up_to_the_target_size() {
+        let long = "x".repeat(PASSAGE_TARGET_CHARS / 2);
+        let lines = vec![
+            line(0, &long),
+            line(1, "  "),
+            line(2, &format!(" {} ", long)),
+            line(3, "short"),
+            line(4, "tail"),
+        ];
+        let passages = build_passages(&lines);
+        assert_eq!(passages.len(), 2);
+        // Blank lines are skipped and text is trimmed before joining
+        assert_eq!(passages[0].text, format!("{} {}", long, long));
+        assert_eq!((passages[0].first_sequence, passages[0].last_sequence), (0, 2));
+        assert_eq!((passages[0].start, passages[0].end), (0.0, 25.0));
+        assert_eq!(passages[1].text, "short tail");
+        assert_eq!((passages[1].first_sequence, passages[1].last_sequence), (3, 4));
+        assert_eq!((passages[1].start, passages[1].end), (30.0, 45.0));
+
+        assert!(build_passages(&[line(0, " ")]).is_empty());
+    }
+
+    #[test]
+    fn vectors_round_trip_encrypted() {
+        vault::use_test_key();
+        let session_id = "semantic-vectors";
+        std::fs::create_dir_all(journal::session_dir(session_id)).unwrap();
+        let vectors = vec![vec![0.5, -1.25, 3.0], vec![f32::MIN_POSITIVE, 0.0, 1e9]];
+        write_vectors(session_id, 3, &vectors).unwrap();
+
+        let stored = std::fs::read(vectors_path(session_id)).unwrap();
+        assert!(vault::is_encrypted(&stored));
+        assert_eq!(read_vectors(session_id).unwrap(), vectors);
+
+        // Truncated or foreign files are rejected rather than read short
+        vault::write(vectors_path(session_id), &b"VNEM\x01\0\0\0\x03\0\0\0\x02\0\0\0"[..]).unwrap();
+        assert!(read_vectors(session_id).is_err());
+        vault::write(vectors_path(session_id), b"not vectors at all").unwrap();
+        assert!(read_vectors(session_id).is_err());
+    }
+
+    #[test]
+    fn nearest_orders_by_cosine_and_skips_other_sizes() {
+        let mut index = BruteForceIndex::default();
+        index.insert(0, vec![0.0, 10.0]);
+        index.insert(1, vec![3.0, 3.0]);
+        index.insert(2, vec![2.0, 0.0]);
+        index.insert(3, vec![1.0, 0.0, 0.0]);
+
+        let nearest = index.nearest(&[1.0, 0.0], 10);
+        let keys: Vec<usize> = nearest.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![2, 1, 0]);
+        assert!((nearest[0].1 - 1.0).abs() < 1e-6);
+        assert!((nearest[1].1 - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!(nearest[2].1.abs() < 1e-6);
+
+        assert_eq!(index.nearest(&[1.0, 0.0], 1).len(), 1);
+        assert_eq!(index.nearest(&[0.0, 0.0, 1.0], 10).len(), 1);
+    }
+
+    // A local stand-in for Ollama's `/api/embeddings`, answering with the prompt length and
+    // reporting each prompt
+    fn serve_embeddings(listener: TcpListener) -> mpsc::Receiver<String> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let prompt = body["prompt"].as_str().unwrap_or_default().to_string();
+                let reply = serde_json::json!({ "embedding": [prompt.len() as f32, 1.0] }).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    reply.len(),
+                    reply
+                );
+                let _ = tx.send(prompt);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        rx
+    }
+
+    #[tokio::test]
+    async fn only_new_lines_are_embedded() {
+        let session_id = "semantic-incremental";
+        let journal = SessionJournal::create(session_id).unwrap();
+        journal.append_line("first point", 0.0, 1.0, "mic").unwrap();
+        journal.append_line("second point", 1.0, 2.0, "mic").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = OllamaClient::new(format!("http://{}", listener.local_addr().unwrap()));
+        let prompts = serve_embeddings(listener);
+
+        let (passages, vectors) = ensure_session_embeddings(&client, session_id, "stand-in").await.unwrap();
+        assert_eq!(prompts.try_iter().collect::<Vec<_>>(), vec!["first point second point"]);
+        assert_eq!(passages.len(), 1);
+        assert_eq!(vectors, vec![vec![24.0, 1.0]]);
+        assert_eq!(load_manifest(session_id).unwrap().next_sequence, 2);
+
+        // Nothing new: served from disk
+        ensure_session_embeddings(&client, session_id, "stand-in").await.unwrap();
+        assert_eq!(prompts.try_iter().count(), 0);
+
+        journal.append_line("third point", 2.0, 3.0, "mic").unwrap();
+        journal.finish().unwrap();
+        let (passages, vectors) = ensure_session_embeddings(&client, session_id, "stand-in").await.unwrap();
+        assert_eq!(prompts.try_iter().collect::<Vec<_>>(), vec!["third point"]);
+        assert_eq!(passages.len(), 2);
+        assert_eq!((passages[1].first_sequence, passages[1].last_sequence), (2, 2));
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(load_manifest(session_id).unwrap().next_sequence, 3);
+
+        // Another model starts over
+        let (passages, _) = ensure_session_embeddings(&client, session_id, "other").await.unwrap();
+        assert_eq!(passages.len(), 1);
+        assert_eq!(prompts.try_iter().count(), 1);
+    }
+}
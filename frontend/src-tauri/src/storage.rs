@@ -0,0 +1,448 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::journal;
+use crate::library::{self, MeetingRecord};
+
+const POLICY_FILE_NAME: &str = "storage_policy.json";
+const DEBUG_DIR_NAME: &str = "meeting_minutes_debug";
+// How often the policy is re-applied while the app is running
+pub const ENFORCE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoragePolicy {
+    // Delete recorded audio this many days after the meeting ended; keep forever when unset
+    pub audio_retention_days: Option<u32>,
+    // Delete whole sessions (transcript, summaries, audio) after this many days; keep forever when unset
+    pub transcript_retention_days: Option<u32>,
+    // Oldest recordings are deleted first once total usage goes over this
+    pub max_total_gb: Option<f64>,
+    pub delete_debug_on_exit: bool,
+}
+
+impl Default for StoragePolicy {
+    fn default() -> Self {
+        Self {
+            audio_retention_days: None,
+            transcript_retention_days: None,
+            max_total_gb: None,
+            delete_debug_on_exit: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionReason {
+    AudioExpired,
+    SessionExpired,
+    OverQuota,
+    DebugArtifacts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedDeletion {
+    pub session_id: Option<String>,
+    pub path: String,
+    pub bytes: u64,
+    pub reason: DeletionReason,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionUsage {
+    pub session_id: String,
+    pub title: String,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub audio_bytes: u64,
+    // Journal, summaries, embeddings and other files in the session directory
+    pub transcript_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageReport {
+    pub policy: StoragePolicy,
+    pub sessions: Vec<SessionUsage>,
+    pub debug_bytes: u64,
+    pub total_bytes: u64,
+    pub dry_run: bool,
+    // What the policy deletes (or would delete, in a dry run)
+    pub deletions: Vec<PlannedDeletion>,
+    pub freed_bytes: u64,
+}
+
+pub fn debug_dir() -> PathBuf {
+    std::env::temp_dir().join(DEBUG_DIR_NAME)
+}
+
+fn policy_path() -> PathBuf {
+    journal::app_data_dir().join(POLICY_FILE_NAME)
+}
+
+pub fn load_policy() -> Result<StoragePolicy> {
+    let path = policy_path();
+    if !path.exists() {
+        return Ok(StoragePolicy::default());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+pub fn save_policy(policy: &StoragePolicy) -> Result<()> {
+    let path = policy_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(policy)?)?;
+    Ok(())
+}
+
+fn path_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| path_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn session_usage(record: &MeetingRecord) -> SessionUsage {
+    let session_dir = journal::session_dir(&record.session_id);
+    let mut audio_bytes = 0;
+    let mut audio_in_session_dir = 0;
+    for path in record.owned_audio() {
+        let size = path_size(&path);
+        audio_bytes += size;
+        if path.starts_with(&session_dir) {
+            audio_in_session_dir += size;
+        }
+    }
+    let transcript_bytes = path_size(&session_dir).saturating_sub(audio_in_session_dir);
+    SessionUsage {
+        session_id: record.session_id.clone(),
+        title: record.title.clone(),
+        ended_at: record.ended_at.or(record.started_at),
+        audio_bytes,
+        transcript_bytes,
+        total_bytes: audio_bytes + transcript_bytes,
+    }
+}
+
+fn older_than(ended_at: Option<DateTime<Utc>>, days: Option<u32>, now: DateTime<Utc>) -> bool {
+    match (ended_at, days) {
+        (Some(ended_at), Some(days)) => now - ended_at > chrono::Duration::days(days as i64),
+        _ => false,
+    }
+}
+
+fn plan(
+    policy: &StoragePolicy,
+    records: &[MeetingRecord],
+    usage: &[SessionUsage],
+    debug_bytes: u64,
+    active_session: Option<&str>,
+) -> Vec<PlannedDeletion> {
+    let now = Utc::now();
+    let mut deletions = Vec::new();
+    let mut audio_deleted: HashSet<&str> = HashSet::new();
+    let mut remaining: u64 = usage.iter().map(|u| u.total_bytes).sum::<u64>() + debug_bytes;
+
+    // Debug chunks belong to the recording in progress until it stops
+    if active_session.is_none() && policy.delete_debug_on_exit && debug_bytes > 0 {
+        deletions.push(PlannedDeletion {
+            session_id: None,
+            path: debug_dir().to_string_lossy().into_owned(),
+            bytes: debug_bytes,
+            reason: DeletionReason::DebugArtifacts,
+        });
+        remaining -= debug_bytes;
+    }
+
+    // Sessions are never touched while they are being recorded
+    let candidates: Vec<(&MeetingRecord, &SessionUsage)> = records
+        .iter()
+        .zip(usage)
+        .filter(|(record, _)| active_session != Some(record.session_id.as_str()))
+        .collect();
+
+    for (record, usage) in &candidates {
+        if older_than(usage.ended_at, policy.transcript_retention_days, now) {
+            deletions.push(PlannedDeletion {
+                session_id: Some(record.session_id.clone()),
+                path: journal::session_dir(&record.session_id).to_string_lossy().into_owned(),
+                bytes: usage.total_bytes,
+                reason: DeletionReason::SessionExpired,
+            });
+            audio_deleted.insert(&record.session_id);
+            remaining -= usage.total_bytes;
+        } else if usage.audio_bytes > 0
+            && older_than(usage.ended_at, policy.audio_retention_days, now)
+        {
+            deletions.extend(audio_deletions(record, DeletionReason::AudioExpired));
+            audio_deleted.insert(&record.session_id);
+            remaining -= usage.audio_bytes;
+        }
+    }
+
+    // Over quota: drop audio from the oldest meetings first, transcripts are kept
+    if let Some(max_gb) = policy.max_total_gb {
+        let max_bytes = (max_gb.max(0.0) * 1024.0 * 1024.0 * 1024.0) as u64;
+        let mut oldest_first: Vec<&(&MeetingRecord, &SessionUsage)> = candidates
+            .iter()
+            .filter(|(record, usage)| {
+                usage.audio_bytes > 0 && !audio_deleted.contains(record.session_id.as_str())
+            })
+            .collect();
+        oldest_first.sort_by_key(|(_, usage)| usage.ended_at);
+
+        for (record, usage) in oldest_first {
+            if remaining <= max_bytes {
+                break;
+            }
+            deletions.extend(audio_deletions(record, DeletionReason::OverQuota));
+            remaining -= usage.audio_bytes;
+        }
+        if remaining > max_bytes {
+            warn!(
+                "Storage still over quota after deleting audio: {} of {} bytes",
+                remaining, max_bytes
+            );
+        }
+    }
+
+    deletions
+}
+
+fn audio_deletions(record: &MeetingRecord, reason: DeletionReason) -> Vec<PlannedDeletion> {
+    record
+        .owned_audio()
+        .into_iter()
+        .map(|path| PlannedDeletion {
+            session_id: Some(record.session_id.clone()),
+            bytes: path_size(&path),
+            path: path.to_string_lossy().into_owned(),
+            reason,
+        })
+        .collect()
+}
+
+fn apply(deletion: &PlannedDeletion, active_session: Option<&str>) -> Result<()> {
+    if deletion.session_id.is_some() && deletion.session_id.as_deref() == active_session {
+        return Err(anyhow!("Session is being recorded"));
+    }
+    match (deletion.reason, &deletion.session_id) {
+        (DeletionReason::DebugArtifacts, _) => std::fs::remove_dir_all(&deletion.path)?,
+        (DeletionReason::SessionExpired, Some(session_id)) => {
+            let record = library::get(session_id)?;
+            for path in record.owned_audio() {
+                library::remove_audio(&path)?;
+            }
+            library::delete(session_id, false)?;
+        }
+        (_, Some(session_id)) => {
+            library::remove_audio(Path::new(&deletion.path))?;
+            library::update(session_id, |record| {
+                record.audio_files.retain(|path| *path != deletion.path)
+            })?;
+        }
+        (_, None) => std::fs::remove_file(&deletion.path)?,
+    }
+    Ok(())
+}
+
+// Measures usage and applies the policy; with `dry_run` nothing is deleted and the
+// report lists what would have been. `active_session` is the session being recorded,
+// which is left alone along with the debug artifacts.
+pub fn enforce(dry_run: bool, active_session: Option<&str>) -> Result<StorageReport> {
+    let policy = load_policy()?;
+    let records = library::list()?;
+    let sessions: Vec<SessionUsage> = records.iter().map(session_usage).collect();
+    let debug_bytes = path_size(&debug_dir());
+    let total_bytes = sessions.iter().map(|s| s.total_bytes).sum::<u64>() + debug_bytes;
+
+    let mut deletions = plan(&policy, &records, &sessions, debug_bytes, active_session);
+    if !dry_run {
+        deletions.retain(|deletion| match apply(deletion, active_session) {
+            Ok(()) => {
+                info!("Deleted {} ({:?}, {} bytes)", deletion.path, deletion.reason, deletion.bytes);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to delete {}: {}", deletion.path, e);
+                false
+            }
+        });
+    }
+    let freed_bytes = deletions.iter().map(|d| d.bytes).sum();
+
+    Ok(StorageReport {
+        policy,
+        sessions,
+        debug_bytes,
+        total_bytes,
+        dry_run,
+        deletions,
+        freed_bytes,
+    })
+}
+
+pub fn clean_debug_artifacts() {
+    match load_policy() {
+        Ok(policy) if !policy.delete_debug_on_exit => return,
+        Ok(_) => {}
+        Err(e) => warn!("Failed to load storage policy, using defaults: {}", e),
+    }
+    let dir = debug_dir();
+    if dir.exists() {
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => info!("Removed debug artifacts in {:?}", dir),
+            Err(e) => warn!("Failed to remove debug artifacts in {:?}: {}", dir, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::MeetingSource;
+
+    // A recorded meeting that ended `days_ago` with one audio file of `bytes`
+    fn meeting(session_id: &str, days_ago: i64, bytes: usize) -> (MeetingRecord, SessionUsage) {
+        let dir = journal::app_data_dir().join("storage-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio = dir.join(format!("{}.wav", session_id));
+        std::fs::write(&audio, vec![0u8; bytes]).unwrap();
+
+        let mut record = MeetingRecord::new(session_id, session_id, MeetingSource::Recording);
+        record.ended_at = Some(Utc::now() - chrono::Duration::days(days_ago));
+        record.audio_files.push(audio.to_string_lossy().into_owned());
+        let usage = SessionUsage {
+            session_id: session_id.to_string(),
+            title: session_id.to_string(),
+            ended_at: record.ended_at,
+            audio_bytes: bytes as u64,
+            transcript_bytes: 0,
+            total_bytes: bytes as u64,
+        };
+        (record, usage)
+    }
+
+    fn planned(deletions: &[PlannedDeletion]) -> Vec<(&str, DeletionReason)> {
+        deletions
+            .iter()
+            .map(|d| (d.session_id.as_deref().unwrap_or(""), d.reason))
+            .collect()
+    }
+
+    #[test]
+    fn retention_expires_sessions_and_audio_but_not_the_active_session() {
+        let (records, usage): (Vec<_>, Vec<_>) = [
+            meeting("retention-old", 40, 10),
+            meeting("retention-stale-audio", 10, 10),
+            meeting("retention-recent", 1, 10),
+            meeting("retention-active", 40, 10),
+        ]
+        .into_iter()
+        .unzip();
+        let policy = StoragePolicy {
+            audio_retention_days: Some(7),
+            transcript_retention_days: Some(30),
+            ..StoragePolicy::default()
+        };
+
+        let deletions = plan(&policy, &records, &usage, 0, Some("retention-active"));
+        assert_eq!(
+            planned(&deletions),
+            vec![
+                ("retention-old", DeletionReason::SessionExpired),
+                ("retention-stale-audio", DeletionReason::AudioExpired),
+            ]
+        );
+    }
+
+    #[test]
+    fn over_quota_drops_the_oldest_audio_first() {
+        let (records, usage): (Vec<_>, Vec<_>) = [
+            meeting("quota-newest", 1, 1000),
+            meeting("quota-oldest", 3, 1000),
+            meeting("quota-middle", 2, 1000),
+            meeting("quota-active", 5, 1000),
+        ]
+        .into_iter()
+        .unzip();
+        let policy = StoragePolicy {
+            // Room for two of the four recordings
+            max_total_gb: Some(2000.0 / (1024.0 * 1024.0 * 1024.0)),
+            ..StoragePolicy::default()
+        };
+
+        let deletions = plan(&policy, &records, &usage, 0, Some("quota-active"));
+        assert_eq!(
+            planned(&deletions),
+            vec![
+                ("quota-oldest", DeletionReason::OverQuota),
+                ("quota-middle", DeletionReason::OverQuota),
+            ]
+        );
+        assert!(deletions.iter().all(|d| d.bytes == 1000));
+    }
+
+    #[test]
+    fn applied_deletions_remove_waveform_caches() {
+        for (session_id, reason) in [
+            ("apply-audio-expired", DeletionReason::AudioExpired),
+            ("apply-session-expired", DeletionReason::SessionExpired),
+        ] {
+            let (record, _) = meeting(session_id, 40, 16);
+            let audio = PathBuf::from(&record.audio_files[0]);
+            let peaks = audio.with_file_name(format!(".{}.wav.mixed.peaks", session_id));
+            std::fs::write(&peaks, b"peaks").unwrap();
+            library::create(record).unwrap();
+
+            let deletion = PlannedDeletion {
+                session_id: Some(session_id.to_string()),
+                path: audio.to_string_lossy().into_owned(),
+                bytes: 16,
+                reason,
+            };
+            apply(&deletion, None).unwrap();
+            assert!(!audio.exists());
+            assert!(!peaks.exists(), "{:?}", reason);
+        }
+    }
+
+    #[test]
+    fn dry_run_reports_deletions_without_deleting() {
+        let (record, _) = meeting("dry-run-expired", 40, 64);
+        let audio = record.audio_files[0].clone();
+        library::create(record).unwrap();
+        save_policy(&StoragePolicy {
+            audio_retention_days: Some(7),
+            ..StoragePolicy::default()
+        })
+        .unwrap();
+
+        let report = enforce(true, None).unwrap();
+        assert!(report.dry_run);
+        let deletion = report
+            .deletions
+            .iter()
+            .find(|d| d.session_id.as_deref() == Some("dry-run-expired"))
+            .expect("expired audio is planned");
+        assert_eq!(deletion.path, audio);
+        assert_eq!(deletion.reason, DeletionReason::AudioExpired);
+        assert_eq!(deletion.bytes, 64);
+        assert_eq!(report.freed_bytes, report.deletions.iter().map(|d| d.bytes).sum::<u64>());
+        assert!(Path::new(&audio).is_file());
+        assert_eq!(library::get("dry-run-expired").unwrap().audio_files, vec![audio]);
+    }
+}
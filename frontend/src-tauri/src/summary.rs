@@ -0,0 +1,494 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::export::{bookmark_label, format_clock};
+use crate::journal::{self, SessionTranscript, TranscriptLine};
+use crate::ollama::{parse_json_reply, ChatMessage, ModelOptions, OllamaClient};
+use crate::vault;
+
+const SUMMARY_FILE_NAME: &str = "summary.json";
+// Rough English average; only used to size chunks, never to enforce hard limits
+const CHARS_PER_TOKEN: usize = 4;
+// Tokens kept free for instructions and the model's reply in each request
+const PROMPT_RESERVE_TOKENS: usize = 1024;
+pub(crate) const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+// Upper bound on reduce rounds so a model that never shrinks its notes cannot loop forever
+const MAX_REDUCE_ROUNDS: usize = 4;
+
+const MAP_SYSTEM_PROMPT: &str = "You are an assistant that takes meeting notes. \
+Summarize the transcript excerpt you are given as short bullet points grouped under \
+'Key points', 'Decisions' and 'Action items'. For action items, name the owner when \
+the transcript says who is responsible. Do not invent details.";
+
+const REDUCE_SYSTEM_PROMPT: &str = "You combine partial meeting notes into one summary. \
+Merge duplicates and keep every distinct decision and action item. Do not invent details.";
+
+const HIGHLIGHTS_PROMPT: &str = "The user bookmarked some moments while recording; they are \
+listed as user-highlighted moments. Make sure each one is reflected in the summary.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionItem {
+    pub description: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeetingSummary {
+    pub session_id: String,
+    pub model: String,
+    pub generated_at: DateTime<Utc>,
+    pub key_points: Vec<String>,
+    pub decisions: Vec<String>,
+    pub action_items: Vec<ActionItem>,
+}
+
+// Shape the model is asked to produce in the final reduce step
+#[derive(Debug, Deserialize)]
+struct SummaryBody {
+    #[serde(default)]
+    key_points: Vec<String>,
+    #[serde(default)]
+    decisions: Vec<String>,
+    #[serde(default)]
+    action_items: Vec<ActionItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryStage {
+    Map,
+    Reduce,
+    Final,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryProgress {
+    pub session_id: String,
+    pub stage: SummaryStage,
+    pub step: usize,
+    pub total_steps: usize,
+    pub token: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SummaryOptions {
+    pub model: String,
+    pub context_tokens: usize,
+}
+
+impl SummaryOptions {
+    pub fn new(model: impl Into<String>, context_tokens: Option<usize>) -> Self {
+        Self {
+            model: model.into(),
+            context_tokens: context_tokens.unwrap_or(DEFAULT_CONTEXT_TOKENS),
+        }
+    }
+
+    fn chunk_chars(&self) -> usize {
+        chunk_chars(self.context_tokens, PROMPT_RESERVE_TOKENS)
+    }
+
+    fn model_options(&self) -> ModelOptions {
+        ModelOptions {
+            num_ctx: Some(self.context_tokens),
+            temperature: Some(0.2),
+        }
+    }
+}
+
+pub fn summary_path(session_id: &str) -> PathBuf {
+    journal::session_dir(session_id).join(SUMMARY_FILE_NAME)
+}
+
+pub fn load_summary(session_id: &str) -> Result<Option<MeetingSummary>> {
+    journal::validate_session_id(session_id)?;
+    let path = summary_path(session_id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&vault::read(path)?)?))
+}
+
+// Characters of transcript that fit in one request alongside `reserve_tokens` of
+// instructions and reply
+pub(crate) fn chunk_chars(context_tokens: usize, reserve_tokens: usize) -> usize {
+    context_tokens.saturating_sub(reserve_tokens).max(256) * CHARS_PER_TOKEN
+}
+
+// A transcript line as the model sees it: "[HH:MM:SS] Speaker: text"
+pub(crate) fn format_line(line: &TranscriptLine) -> String {
+    let stamp = format_clock(line.start);
+    match &line.speaker {
+        Some(speaker) => format!("[{}] {}: {}", stamp, speaker, line.text.trim()),
+        None => format!("[{}] {}", stamp, line.text.trim()),
+    }
+}
+
+// Bookmarks with the line being spoken at the time, for the final prompt
+fn format_highlights(transcript: &SessionTranscript) -> Option<String> {
+    if transcript.bookmarks.is_empty() {
+        return None;
+    }
+    let mut out = String::from("User-highlighted moments:\n");
+    for bookmark in &transcript.bookmarks {
+        out.push_str(&format!("- [{}] {}", format_clock(bookmark.offset), bookmark_label(bookmark)));
+        // The last line that started before the bookmark
+        let said = transcript.lines.iter().rev().find(|line| line.start <= bookmark.offset);
+        if let Some(line) = said {
+            out.push_str(&format!(" (said: \"{}\")", line.text.trim()));
+        }
+        out.push('\n');
+    }
+    Some(out)
+}
+
+// Packs whole entries into chunks of at most `max_chars`; an oversized entry gets a chunk to itself
+pub fn chunk_texts<I: IntoIterator<Item = String>>(entries: I, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for entry in entries {
+        if !current.is_empty() && current.len() + entry.len() + 1 > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&entry);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn summary_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "key_points": { "type": "array", "items": { "type": "string" } },
+            "decisions": { "type": "array", "items": { "type": "string" } },
+            "action_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "description": { "type": "string" },
+                        "owner": { "type": ["string", "null"] }
+                    },
+                    "required": ["description"]
+                }
+            }
+        },
+        "required": ["key_points", "decisions", "action_items"]
+    })
+}
+
+pub async fn summarize_session(
+    client: &OllamaClient,
+    session_id: &str,
+    options: &SummaryOptions,
+    mut on_progress: impl FnMut(SummaryProgress),
+) -> Result<MeetingSummary> {
+    let transcript = journal::load_transcript(session_id)?;
+    if transcript.lines.is_empty() {
+        return Err(anyhow!("Session {} has no transcript to summarize", session_id));
+    }
+
+    let model_options = options.model_options();
+    let chunk_chars = options.chunk_chars();
+
+    // Map: condense each transcript chunk into notes
+    let chunks = chunk_texts(transcript.lines.iter().map(format_line), chunk_chars);
+    info!("Summarizing session {} in {} chunk(s) with {}", session_id, chunks.len(), options.model);
+
+    let mut notes = Vec::with_capacity(chunks.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        let prompt = format!(
+            "Transcript excerpt {} of {}:\n\n{}",
+            index + 1,
+            chunks.len(),
+            chunk
+        );
+        let note = client
+            .generate(&options.model, Some(MAP_SYSTEM_PROMPT), &prompt, &model_options, |token| {
+                on_progress(SummaryProgress {
+                    session_id: session_id.to_string(),
+                    stage: SummaryStage::Map,
+                    step: index + 1,
+                    total_steps: chunks.len(),
+                    token: token.to_string(),
+                })
+            })
+            .await?;
+        notes.push(note);
+    }
+
+    // Reduce: merge notes until they fit in a single request
+    let mut round = 0;
+    while notes.len() > 1 && notes.iter().map(String::len).sum::<usize>() > chunk_chars {
+        round += 1;
+        if round > MAX_REDUCE_ROUNDS {
+            warn!("Notes still exceed the context after {} reduce rounds; truncating", MAX_REDUCE_ROUNDS);
+            break;
+        }
+
+        let groups = chunk_texts(notes.drain(..), chunk_chars);
+        for (index, group) in groups.iter().enumerate() {
+            let note = client
+                .generate(&options.model, Some(REDUCE_SYSTEM_PROMPT), group, &model_options, |token| {
+                    on_progress(SummaryProgress {
+                        session_id: session_id.to_string(),
+                        stage: SummaryStage::Reduce,
+                        step: index + 1,
+                        total_steps: groups.len(),
+                        token: token.to_string(),
+                    })
+                })
+                .await?;
+            notes.push(note);
+        }
+    }
+
+    let mut combined = notes.join("\n\n");
+    if combined.len() > chunk_chars {
+        let mut cut = chunk_chars;
+        while !combined.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        combined.truncate(cut);
+    }
+    let highlights = format_highlights(&transcript);
+    if let Some(highlights) = &highlights {
+        combined = format!("{}\n{}", highlights, combined);
+    }
+
+    // Final: ask for the structured summary as JSON
+    let schema = summary_schema();
+    let messages = [
+        ChatMessage::system(format!(
+            "{} {}Reply only with JSON containing \"key_points\", \"decisions\" and \"action_items\" \
+             (each action item has \"description\" and \"owner\", owner null if unknown).",
+            REDUCE_SYSTEM_PROMPT,
+            if highlights.is_some() { format!("{} ", HIGHLIGHTS_PROMPT) } else { String::new() }
+        )),
+        ChatMessage::user(combined),
+    ];
+    let reply = client
+        .chat(&options.model, &messages, Some(&schema), &model_options, |token| {
+            on_progress(SummaryProgress {
+                session_id: session_id.to_string(),
+                stage: SummaryStage::Final,
+                step: 1,
+                total_steps: 1,
+                token: token.to_string(),
+            })
+        })
+        .await?;
+
+    let body: SummaryBody = parse_json_reply(&reply)?;
+
+    let summary = MeetingSummary {
+        session_id: session_id.to_string(),
+        model: options.model.clone(),
+        generated_at: Utc::now(),
+        key_points: body.key_points,
+        decisions: body.decisions,
+        action_items: body.action_items,
+    };
+
+    vault::write(summary_path(session_id), &serde_json::to_vec_pretty(&summary)?)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::SessionJournal;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    const SUMMARY_JSON: &str = r#"{"key_points": ["Budget approved"], "decisions": ["Ship in May"], "action_items": [{"description": "Draft the plan", "owner": "Ana"}]}"#;
+
+    fn ndjson(lines: &[serde_json::Value]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    // A local stand-in for Ollama: `/api/generate` streams "notes N" for the Nth request
+    // and `/api/chat` streams `SUMMARY_JSON` in two pieces. Reports each path and body.
+    fn serve_ollama(listener: TcpListener) -> mpsc::Receiver<(String, serde_json::Value)> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut generated = 0;
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+                let reply = match path.as_str() {
+                    "/api/generate" => {
+                        generated += 1;
+                        ndjson(&[
+                            json!({ "response": "notes " }),
+                            json!({ "response": generated.to_string() }),
+                            json!({ "response": "", "done": true }),
+                        ])
+                    }
+                    "/api/chat" => {
+                        let (first, second) = SUMMARY_JSON.split_at(SUMMARY_JSON.len() / 2);
+                        ndjson(&[
+                            json!({ "message": { "role": "assistant", "content": first } }),
+                            json!({ "message": { "role": "assistant", "content": second } }),
+                            json!({ "done": true }),
+                        ])
+                    }
+                    _ => String::new(),
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    reply.len(),
+                    reply
+                );
+                // Reported before answering, so the request is seen by the time the reply is
+                let _ = tx.send((path, serde_json::from_slice(&body).unwrap_or_default()));
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        rx
+    }
+
+    #[tokio::test]
+    async fn summarizes_through_map_and_final_requests() {
+        let session_id = "summary-pipeline";
+        let journal = SessionJournal::create(session_id).unwrap();
+        for i in 0..40 {
+            let text = format!("Line {} of the budget discussion, with enough words to fill the context", i);
+            journal.append_line(&text, i as f32 * 5.0, i as f32 * 5.0 + 4.0, "mic").unwrap();
+        }
+        journal.append_bookmark("budget", 42.0).unwrap();
+        journal.finish().unwrap();
+        drop(journal);
+
+        // The smallest context, so the transcript has to be split
+        let options = SummaryOptions::new("stand-in", Some(512));
+        let transcript = journal::load_transcript(session_id).unwrap();
+        let expected_chunks = chunk_texts(transcript.lines.iter().map(format_line), options.chunk_chars()).len();
+        assert!(expected_chunks > 1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = OllamaClient::new(format!("http://{}", listener.local_addr().unwrap()));
+        let requests = serve_ollama(listener);
+        let mut progress = Vec::new();
+        let summary = summarize_session(&client, session_id, &options, |update| progress.push(update))
+            .await
+            .unwrap();
+
+        let requests: Vec<_> = requests.try_iter().collect();
+        let map_requests: Vec<_> = requests.iter().filter(|(path, _)| path == "/api/generate").collect();
+        assert_eq!(map_requests.len(), expected_chunks);
+        assert_eq!(map_requests[0].1["system"], MAP_SYSTEM_PROMPT);
+        assert_eq!(map_requests[0].1["options"]["num_ctx"], 512);
+        let (last_path, final_request) = requests.last().unwrap();
+        assert_eq!(last_path, "/api/chat");
+        assert_eq!(final_request["format"], summary_schema());
+        let final_prompt = final_request["messages"][1]["content"].as_str().unwrap();
+        assert!(final_prompt.starts_with("User-highlighted moments:\n- [00:00:42] budget"), "{}", final_prompt);
+        assert!(final_prompt.contains("notes 1\n\nnotes 2"), "{}", final_prompt);
+
+        // Each map step streams its own tokens, then the final reply streams in order
+        for step in 1..=expected_chunks {
+            let tokens: String = progress
+                .iter()
+                .filter(|p| p.stage == SummaryStage::Map && p.step == step)
+                .inspect(|p| assert_eq!(p.total_steps, expected_chunks))
+                .map(|p| p.token.as_str())
+                .collect();
+            assert_eq!(tokens, format!("notes {}", step));
+        }
+        let final_tokens: String = progress
+            .iter()
+            .filter(|p| p.stage == SummaryStage::Final)
+            .map(|p| p.token.as_str())
+            .collect();
+        assert_eq!(final_tokens, SUMMARY_JSON);
+        assert!(progress.iter().all(|p| p.session_id == session_id && p.stage != SummaryStage::Reduce));
+
+        assert_eq!(summary.model, "stand-in");
+        assert_eq!(summary.key_points, vec!["Budget approved"]);
+        assert_eq!(summary.decisions, vec!["Ship in May"]);
+        assert_eq!(summary.action_items[0].description, "Draft the plan");
+        assert_eq!(summary.action_items[0].owner.as_deref(), Some("Ana"));
+        assert_eq!(load_summary(session_id).unwrap().unwrap().decisions, summary.decisions);
+    }
+
+    fn entries(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn chunks_count_the_separator() {
+        // "aaaa\nbbbb" is exactly 9 characters
+        assert_eq!(chunk_texts(entries(&["aaaa", "bbbb"]), 9), vec!["aaaa\nbbbb"]);
+        assert_eq!(chunk_texts(entries(&["aaaa", "bbbb"]), 8), vec!["aaaa", "bbbb"]);
+        for chunk in chunk_texts(entries(&["ab", "cd", "ef", "gh", "ij"]), 5) {
+            assert!(chunk.len() <= 5, "{:?} is over the limit", chunk);
+        }
+    }
+
+    #[test]
+    fn oversized_entries_get_a_chunk_of_their_own() {
+        let chunks = chunk_texts(entries(&["short", "a much longer entry", "tail"]), 10);
+        assert_eq!(chunks, vec!["short", "a much longer entry", "tail"]);
+    }
+
+    #[test]
+    fn no_entries_make_no_chunks() {
+        assert!(chunk_texts(Vec::new(), 100).is_empty());
+    }
+
+    #[test]
+    fn chunk_chars_leaves_room_for_the_prompt() {
+        assert_eq!(SummaryOptions::new("m", None).chunk_chars(), (4096 - 1024) * CHARS_PER_TOKEN);
+        assert_eq!(SummaryOptions::new("m", Some(8192)).chunk_chars(), (8192 - 1024) * CHARS_PER_TOKEN);
+        // A context smaller than the reserve still leaves a usable chunk
+        assert_eq!(SummaryOptions::new("m", Some(512)).chunk_chars(), 256 * CHARS_PER_TOKEN);
+    }
+
+    #[test]
+    fn summary_body_parses_from_wrapped_replies() {
+        let fenced = "```json\n{\"key_points\": [\"a\"], \"decisions\": [], \"action_items\": \
+                      [{\"description\": \"ship\", \"owner\": null},]}\n```";
+        let body: SummaryBody = parse_json_reply(fenced).unwrap();
+        assert_eq!(body.key_points, vec!["a"]);
+        assert_eq!(body.action_items[0].description, "ship");
+
+        let prose = "Here is the summary you asked for: {\"decisions\": [\"go\"]} Let me know!";
+        let body: SummaryBody = parse_json_reply(prose).unwrap();
+        assert_eq!(body.decisions, vec!["go"]);
+        assert!(body.key_points.is_empty());
+
+        assert!(parse_json_reply::<SummaryBody>("no json here").is_err());
+    }
+}
@@ -0,0 +1,962 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::journal;
+
+const SETTINGS_FILE_NAME: &str = "encryption.json";
+const KEYRING_SERVICE: &str = "com.meetily.ai.storage";
+
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const DATA_KEY_AAD: &[u8] = b"meetily-data-key";
+
+// Encrypted files: magic, version, random file id, then length-prefixed sealed records.
+// Each record's AAD binds the file id, its index and whether it is the last one, so
+// records can't be reordered, spliced between files or silently truncated.
+const FILE_MAGIC: &[u8; 4] = b"MMVT";
+const FILE_VERSION: u8 = 1;
+const FILE_ID_LEN: usize = 16;
+const HEADER_LEN: usize = FILE_MAGIC.len() + 1 + FILE_ID_LEN;
+const RECORD_LEN: usize = 64 * 1024;
+// Length prefix, nonce and tag around each record's plaintext
+const RECORD_OVERHEAD: usize = 4 + NONCE_LEN + TAG_LEN;
+// Append-only text files (journals) seal each line separately and hex-encode it
+const LINE_PREFIX: &str = "enc2:";
+
+static VAULT: Lazy<RwLock<VaultState>> = Lazy::new(|| {
+    let settings = load_settings().unwrap_or_else(|e| {
+        warn!("Failed to read encryption settings: {}", e);
+        None
+    });
+    RwLock::new(VaultState { settings, key: None })
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Passphrase,
+    // OS keyring where one is reachable; falls back to `File` otherwise
+    Keyring,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptionSettings {
+    key_source: KeySource,
+    // Names the keyring entry or key file; a re-key writes new key material under a new
+    // id so a crash part way through never leaves the settings pointing at a lost key
+    key_id: String,
+    // Hex; only used for passphrase-derived keys
+    salt: String,
+    iterations: u32,
+    // The data key sealed with the key-encryption key, hex
+    wrapped_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+    pub key_source: Option<KeySource>,
+}
+
+struct VaultState {
+    settings: Option<EncryptionSettings>,
+    key: Option<Arc<DataKey>>,
+}
+
+struct DataKey {
+    raw: [u8; KEY_LEN],
+    key: LessSafeKey,
+}
+
+impl DataKey {
+    fn new(raw: [u8; KEY_LEN]) -> Result<Self> {
+        Ok(Self { raw, key: aead_key(&raw)? })
+    }
+}
+
+fn aead_key(bytes: &[u8]) -> Result<LessSafeKey> {
+    let unbound = UnboundKey::new(&AES_256_GCM, bytes).map_err(|_| anyhow!("Invalid key length"))?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("System random number generator failed"))?;
+    Ok(bytes)
+}
+
+// Returns nonce || ciphertext || tag
+fn seal(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let mut sealed = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&nonce);
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(anyhow!("Encrypted record is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("Invalid nonce"))?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow!("Decryption failed: wrong key or corrupted data"))?;
+    Ok(plaintext.to_vec())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+        return Err(anyhow!("Invalid hex string"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|e| anyhow!("Invalid hex string: {}", e)))
+        .collect()
+}
+
+fn settings_path() -> PathBuf {
+    journal::app_data_dir().join(SETTINGS_FILE_NAME)
+}
+
+fn key_file_path(key_id: &str) -> PathBuf {
+    journal::app_data_dir().join(format!("encryption-{}.key", key_id))
+}
+
+fn keyring_account(key_id: &str) -> String {
+    format!("storage-key-{}", key_id)
+}
+
+fn load_settings() -> Result<Option<EncryptionSettings>> {
+    let path = settings_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn save_settings(settings: &EncryptionSettings) -> Result<()> {
+    write_private(&settings_path(), &serde_json::to_vec_pretty(settings)?)
+}
+
+fn run_with_stdin(program: &str, args: &[&str], input: &str) -> Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The secret goes through stdin so it never shows up in the process list
+#[cfg(target_os = "macos")]
+fn keyring_store(account: &str, secret: &str) -> Result<()> {
+    let command = format!(
+        "add-generic-password -U -a {} -s {} -w {}\n",
+        account, KEYRING_SERVICE, secret
+    );
+    run_with_stdin("security", &["-i"], &command).map(|_| ())
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_load(account: &str) -> Result<String> {
+    run_with_stdin(
+        "security",
+        &["find-generic-password", "-a", account, "-s", KEYRING_SERVICE, "-w"],
+        "",
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_delete(account: &str) -> Result<()> {
+    run_with_stdin(
+        "security",
+        &["delete-generic-password", "-a", account, "-s", KEYRING_SERVICE],
+        "",
+    )
+    .map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_store(account: &str, secret: &str) -> Result<()> {
+    run_with_stdin(
+        "secret-tool",
+        &["store", "--label=Meetily storage key", "service", KEYRING_SERVICE, "account", account],
+        secret,
+    )
+    .map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_load(account: &str) -> Result<String> {
+    run_with_stdin("secret-tool", &["lookup", "service", KEYRING_SERVICE, "account", account], "")
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_delete(account: &str) -> Result<()> {
+    run_with_stdin("secret-tool", &["clear", "service", KEYRING_SERVICE, "account", account], "")
+        .map(|_| ())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_store(_account: &str, _secret: &str) -> Result<()> {
+    Err(anyhow!("No OS keyring available on this platform"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_load(_account: &str) -> Result<String> {
+    Err(anyhow!("No OS keyring available on this platform"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_delete(_account: &str) -> Result<()> {
+    Ok(())
+}
+
+// Creates a fresh key-encryption key for `source`; returns it with the salt used and
+// the source actually in effect after any keyring fallback
+fn new_kek(
+    source: KeySource,
+    key_id: &str,
+    passphrase: Option<&str>,
+) -> Result<([u8; KEY_LEN], Vec<u8>, KeySource)> {
+    match source {
+        KeySource::Passphrase => {
+            let passphrase = passphrase
+                .filter(|p| !p.is_empty())
+                .ok_or_else(|| anyhow!("A passphrase is required"))?;
+            let salt = random_bytes::<SALT_LEN>()?.to_vec();
+            Ok((derive_kek(passphrase, &salt, PBKDF2_ITERATIONS)?, salt, source))
+        }
+        KeySource::Keyring => {
+            let kek = random_bytes::<KEY_LEN>()?;
+            match keyring_store(&keyring_account(key_id), &to_hex(&kek)) {
+                Ok(()) => Ok((kek, Vec::new(), KeySource::Keyring)),
+                Err(e) => {
+                    warn!("OS keyring unavailable, storing the key in a file instead: {}", e);
+                    write_private(&key_file_path(key_id), to_hex(&kek).as_bytes())?;
+                    Ok((kek, Vec::new(), KeySource::File))
+                }
+            }
+        }
+        KeySource::File => {
+            let kek = random_bytes::<KEY_LEN>()?;
+            write_private(&key_file_path(key_id), to_hex(&kek).as_bytes())?;
+            Ok((kek, Vec::new(), KeySource::File))
+        }
+    }
+}
+
+fn delete_key_material(settings: &EncryptionSettings) {
+    let result = match settings.key_source {
+        KeySource::Passphrase => Ok(()),
+        KeySource::Keyring => keyring_delete(&keyring_account(&settings.key_id)),
+        KeySource::File => std::fs::remove_file(key_file_path(&settings.key_id)).map_err(Into::into),
+    };
+    if let Err(e) = result {
+        warn!("Failed to remove old {:?} key: {}", settings.key_source, e);
+    }
+}
+
+fn derive_kek(passphrase: &str, salt: &[u8], iterations: u32) -> Result<[u8; KEY_LEN]> {
+    let iterations = NonZeroU32::new(iterations).ok_or_else(|| anyhow!("Invalid iteration count"))?;
+    let mut kek = [0u8; KEY_LEN];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut kek);
+    Ok(kek)
+}
+
+fn load_kek(settings: &EncryptionSettings, passphrase: Option<&str>) -> Result<Vec<u8>> {
+    match settings.key_source {
+        KeySource::Passphrase => {
+            let passphrase = passphrase.ok_or_else(|| anyhow!("A passphrase is required"))?;
+            Ok(derive_kek(passphrase, &from_hex(&settings.salt)?, settings.iterations)?.to_vec())
+        }
+        KeySource::Keyring => from_hex(&keyring_load(&keyring_account(&settings.key_id))?),
+        KeySource::File => from_hex(std::fs::read_to_string(key_file_path(&settings.key_id))?.trim()),
+    }
+}
+
+fn unwrap_data_key(settings: &EncryptionSettings, passphrase: Option<&str>) -> Result<DataKey> {
+    let kek = aead_key(&load_kek(settings, passphrase)?)?;
+    let raw = open(&kek, DATA_KEY_AAD, &from_hex(&settings.wrapped_key)?).map_err(|_| {
+        match settings.key_source {
+            KeySource::Passphrase => anyhow!("Incorrect passphrase"),
+            _ => anyhow!("Stored key does not match the encrypted data key"),
+        }
+    })?;
+    let raw: [u8; KEY_LEN] = raw.try_into().map_err(|_| anyhow!("Corrupt data key"))?;
+    DataKey::new(raw)
+}
+
+fn wrap_data_key(data_key: &DataKey, source: KeySource, passphrase: Option<&str>) -> Result<EncryptionSettings> {
+    let key_id = to_hex(&random_bytes::<8>()?);
+    let (kek, salt, key_source) = new_kek(source, &key_id, passphrase)?;
+    Ok(EncryptionSettings {
+        key_source,
+        key_id,
+        salt: to_hex(&salt),
+        iterations: PBKDF2_ITERATIONS,
+        wrapped_key: to_hex(&seal(&aead_key(&kek)?, DATA_KEY_AAD, &data_key.raw)?),
+    })
+}
+
+fn read_state() -> Result<std::sync::RwLockReadGuard<'static, VaultState>> {
+    VAULT.read().map_err(|_| anyhow!("Vault lock poisoned"))
+}
+
+fn write_state() -> Result<std::sync::RwLockWriteGuard<'static, VaultState>> {
+    VAULT.write().map_err(|_| anyhow!("Vault lock poisoned"))
+}
+
+#[cfg(test)]
+thread_local! {
+    // Lets one test encrypt with its own key without turning encryption on for the
+    // others running alongside it
+    static TEST_KEY: std::cell::RefCell<Option<Arc<DataKey>>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+pub(crate) fn use_test_key() {
+    let key = DataKey::new(random_bytes::<KEY_LEN>().unwrap()).unwrap();
+    TEST_KEY.with(|test_key| *test_key.borrow_mut() = Some(Arc::new(key)));
+}
+
+#[cfg(test)]
+fn thread_test_key() -> Option<Arc<DataKey>> {
+    TEST_KEY.with(|test_key| test_key.borrow().clone())
+}
+
+#[cfg(not(test))]
+fn thread_test_key() -> Option<Arc<DataKey>> {
+    None
+}
+
+// The key new data must be written with: None when encryption is off, an error when
+// it is on but still locked so nothing sensitive falls back to plain text
+fn write_key() -> Result<Option<Arc<DataKey>>> {
+    if let Some(key) = thread_test_key() {
+        return Ok(Some(key));
+    }
+    let state = read_state()?;
+    match (&state.settings, &state.key) {
+        (None, _) => Ok(None),
+        (Some(_), Some(key)) => Ok(Some(key.clone())),
+        (Some(_), None) => Err(anyhow!("Encrypted storage is locked")),
+    }
+}
+
+fn read_key() -> Result<Arc<DataKey>> {
+    if let Some(key) = thread_test_key() {
+        return Ok(key);
+    }
+    read_state()?
+        .key
+        .clone()
+        .ok_or_else(|| anyhow!("Encrypted storage is locked"))
+}
+
+pub fn status() -> Result<EncryptionStatus> {
+    let state = read_state()?;
+    Ok(EncryptionStatus {
+        enabled: state.settings.is_some(),
+        unlocked: state.key.is_some(),
+        key_source: state.settings.as_ref().map(|s| s.key_source),
+    })
+}
+
+pub fn is_unlocked() -> bool {
+    thread_test_key().is_some() || read_state().map(|state| state.key.is_some()).unwrap_or(false)
+}
+
+// Turns encryption on for everything written from now on; existing plain files stay readable
+pub fn enable(source: KeySource, passphrase: Option<&str>) -> Result<EncryptionStatus> {
+    let mut state = write_state()?;
+    if state.settings.is_some() {
+        return Err(anyhow!("Encryption is already enabled"));
+    }
+    let data_key = DataKey::new(random_bytes::<KEY_LEN>()?)?;
+    let settings = wrap_data_key(&data_key, source, passphrase)?;
+    save_settings(&settings)?;
+    info!("Enabled encrypted storage with {:?} key", settings.key_source);
+    state.settings = Some(settings);
+    state.key = Some(Arc::new(data_key));
+    drop(state);
+    status()
+}
+
+pub fn unlock(passphrase: Option<&str>) -> Result<()> {
+    let mut state = write_state()?;
+    let settings = state
+        .settings
+        .clone()
+        .ok_or_else(|| anyhow!("Encryption is not enabled"))?;
+    state.key = Some(Arc::new(unwrap_data_key(&settings, passphrase)?));
+    info!("Unlocked encrypted storage");
+    Ok(())
+}
+
+// Keyring and file keys need no user input, so they are unlocked at startup
+pub fn auto_unlock() {
+    let source = match read_state() {
+        Ok(state) => state.settings.as_ref().map(|s| s.key_source),
+        Err(_) => None,
+    };
+    match source {
+        Some(KeySource::Keyring) | Some(KeySource::File) => {
+            if let Err(e) = unlock(None) {
+                warn!("Failed to unlock encrypted storage: {}", e);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Re-wraps the data key under a new passphrase or key source. Files don't need to be
+// rewritten because they are encrypted with the data key, which stays the same.
+pub fn rekey(
+    current_passphrase: Option<&str>,
+    source: KeySource,
+    passphrase: Option<&str>,
+) -> Result<EncryptionStatus> {
+    let mut state = write_state()?;
+    let old = state
+        .settings
+        .clone()
+        .ok_or_else(|| anyhow!("Encryption is not enabled"))?;
+    // Always prove knowledge of the current key, even when already unlocked
+    let data_key = unwrap_data_key(&old, current_passphrase)?;
+
+    // Stale key material is only removed once the new wrapping is safely on disk
+    let settings = wrap_data_key(&data_key, source, passphrase)?;
+    save_settings(&settings)?;
+    delete_key_material(&old);
+
+    info!("Re-keyed encrypted storage to {:?}", settings.key_source);
+    state.settings = Some(settings);
+    state.key = Some(Arc::new(data_key));
+    drop(state);
+    status()
+}
+
+fn record_aad(file_id: &[u8], index: u64, last: bool) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(FILE_ID_LEN + 9);
+    aad.extend_from_slice(file_id);
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad.push(last as u8);
+    aad
+}
+
+fn encrypt_bytes(key: &DataKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let file_id = random_bytes::<FILE_ID_LEN>()?;
+    let records = plaintext.len().div_ceil(RECORD_LEN).max(1);
+    let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + records * (4 + NONCE_LEN + TAG_LEN));
+    out.extend_from_slice(FILE_MAGIC);
+    out.push(FILE_VERSION);
+    out.extend_from_slice(&file_id);
+
+    for index in 0..records {
+        let start = index * RECORD_LEN;
+        let chunk = &plaintext[start..(start + RECORD_LEN).min(plaintext.len())];
+        let aad = record_aad(&file_id, index as u64, index + 1 == records);
+        let sealed = seal(&key.key, &aad, chunk)?;
+        out.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        out.extend_from_slice(&sealed);
+    }
+    Ok(out)
+}
+
+fn decrypt_bytes(key: &DataKey, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LEN || data[FILE_MAGIC.len()] != FILE_VERSION {
+        return Err(anyhow!("Unsupported encrypted file format"));
+    }
+    let file_id = &data[FILE_MAGIC.len() + 1..HEADER_LEN];
+    let mut plaintext = Vec::with_capacity(data.len());
+    let mut offset = HEADER_LEN;
+    let mut index = 0u64;
+
+    while offset < data.len() {
+        let len_bytes: [u8; 4] = data
+            .get(offset..offset + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("Encrypted file is truncated"))?;
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        let sealed = data
+            .get(offset + 4..offset + 4 + len)
+            .ok_or_else(|| anyhow!("Encrypted file is truncated"))?;
+        offset += 4 + len;
+        let aad = record_aad(file_id, index, offset == data.len());
+        plaintext.extend(open(&key.key, &aad, sealed)?);
+        index += 1;
+    }
+    Ok(plaintext)
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(FILE_MAGIC)
+}
+
+pub fn is_encrypted_file(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; FILE_MAGIC.len()];
+    let mut file = std::fs::File::open(path)?;
+    match std::io::Read::read_exact(&mut file, &mut magic) {
+        Ok(()) => Ok(is_encrypted(&magic)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Random access to a file written by `write` or `encrypt_file_in_place`. Every record but
+// the last holds RECORD_LEN bytes, so a range only decrypts the records it overlaps.
+pub struct RangeReader {
+    file: std::fs::File,
+    // Plaintext length
+    len: u64,
+    // None for plain files
+    encrypted: Option<EncryptedLayout>,
+}
+
+struct EncryptedLayout {
+    key: Arc<DataKey>,
+    file_id: [u8; FILE_ID_LEN],
+    records: u64,
+}
+
+impl RangeReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut header = [0u8; HEADER_LEN];
+        if size < HEADER_LEN as u64 || {
+            file.read_exact(&mut header)?;
+            !is_encrypted(&header)
+        } {
+            return Ok(RangeReader { file, len: size, encrypted: None });
+        }
+        if header[FILE_MAGIC.len()] != FILE_VERSION {
+            return Err(anyhow!("Unsupported encrypted file format"));
+        }
+
+        let sealed_len = (RECORD_LEN + RECORD_OVERHEAD) as u64;
+        let body = size - HEADER_LEN as u64;
+        let (full, rest) = (body / sealed_len, body % sealed_len);
+        let (records, len) = match rest {
+            0 if full > 0 => (full, full * RECORD_LEN as u64),
+            rest if rest >= RECORD_OVERHEAD as u64 => (full + 1, full * RECORD_LEN as u64 + rest - RECORD_OVERHEAD as u64),
+            _ => return Err(anyhow!("Encrypted file is truncated")),
+        };
+        let mut file_id = [0u8; FILE_ID_LEN];
+        file_id.copy_from_slice(&header[FILE_MAGIC.len() + 1..]);
+        Ok(RangeReader {
+            file,
+            len,
+            encrypted: Some(EncryptedLayout {
+                key: read_key()?,
+                file_id,
+                records,
+            }),
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Reads up to `len` bytes of plaintext starting at `offset`
+    pub fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let end = offset.saturating_add(len as u64).min(self.len);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let Some(layout) = &self.encrypted else {
+            let mut out = vec![0u8; (end - offset) as usize];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut out)?;
+            return Ok(out);
+        };
+
+        let record_len = RECORD_LEN as u64;
+        let mut out = Vec::with_capacity((end - offset) as usize);
+        for index in offset / record_len..=(end - 1) / record_len {
+            self.file
+                .seek(SeekFrom::Start(HEADER_LEN as u64 + index * (record_len + RECORD_OVERHEAD as u64)))?;
+            let mut len_bytes = [0u8; 4];
+            self.file.read_exact(&mut len_bytes)?;
+            let sealed_len = u32::from_le_bytes(len_bytes) as usize;
+            if sealed_len > RECORD_LEN + NONCE_LEN + TAG_LEN {
+                return Err(anyhow!("Encrypted record is too long"));
+            }
+            let mut sealed = vec![0u8; sealed_len];
+            self.file.read_exact(&mut sealed)?;
+            let aad = record_aad(&layout.file_id, index, index + 1 == layout.records);
+            let plaintext = open(&layout.key.key, &aad, &sealed)?;
+
+            let record_start = index * record_len;
+            let from = (offset.saturating_sub(record_start) as usize).min(plaintext.len());
+            let to = ((end - record_start) as usize).min(plaintext.len());
+            out.extend_from_slice(&plaintext[from..to]);
+        }
+        Ok(out)
+    }
+}
+
+// Reads a file written by `write`, decrypting it when needed; plain files pass through
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    if !is_encrypted(&data) {
+        return Ok(data);
+    }
+    let key = read_key()?;
+    decrypt_bytes(&key, &data)
+}
+
+pub fn write(path: impl AsRef<Path>, contents: &[u8]) -> Result<()> {
+    match write_key()? {
+        Some(key) => std::fs::write(path, encrypt_bytes(&key, contents)?)?,
+        None => std::fs::write(path, contents)?,
+    }
+    Ok(())
+}
+
+// Encrypts a file produced by an external tool such as ffmpeg, when encryption is on
+pub fn encrypt_file_in_place(path: &Path) -> Result<()> {
+    let Some(key) = write_key()? else {
+        return Ok(());
+    };
+    let data = std::fs::read(path)?;
+    if is_encrypted(&data) {
+        return Ok(());
+    }
+    let tmp = path.with_extension("enc.tmp");
+    std::fs::write(&tmp, encrypt_bytes(&key, &data)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn io_error(error: anyhow::Error) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
+
+// Encrypts a stream as it is written, in the format `write` produces, so long recordings
+// never sit on disk in the clear. Full records are sealed as they fill and `finish` seals
+// the rest; a crash loses the unsealed tail and `finish_sealed_file` repairs the file.
+pub struct SealingWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    key: Arc<DataKey>,
+    file_id: [u8; FILE_ID_LEN],
+    buffer: Vec<u8>,
+    // Records sealed so far
+    index: u64,
+    // Plaintext of record 0 once sealed, so its start can still be patched
+    first: Option<Vec<u8>>,
+}
+
+impl SealingWriter {
+    // None when encryption is off; the caller then writes `path` in the clear
+    pub fn create(path: &Path) -> Result<Option<Self>> {
+        match write_key()? {
+            Some(key) => Ok(Some(Self::create_with(path, key)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn create_with(path: &Path, key: Arc<DataKey>) -> Result<Self> {
+        let file_id = random_bytes::<FILE_ID_LEN>()?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(FILE_MAGIC)?;
+        file.write_all(&[FILE_VERSION])?;
+        file.write_all(&file_id)?;
+        Ok(SealingWriter {
+            file,
+            key,
+            file_id,
+            buffer: Vec::with_capacity(RECORD_LEN),
+            index: 0,
+            first: None,
+        })
+    }
+
+    fn seal_record(&mut self, plaintext: &[u8], index: u64, last: bool) -> Result<Vec<u8>> {
+        let sealed = seal(&self.key.key, &record_aad(&self.file_id, index, last), plaintext)?;
+        let mut record = Vec::with_capacity(4 + sealed.len());
+        record.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        record.extend_from_slice(&sealed);
+        Ok(record)
+    }
+
+    // Overwrites plaintext bytes at `offset`, which must fall in the first record; used
+    // to keep a header's length fields current
+    pub fn patch_start(&mut self, offset: usize, bytes: &[u8]) -> Result<()> {
+        if offset + bytes.len() > RECORD_LEN {
+            return Err(anyhow!("Only the first record can be patched"));
+        }
+        let Some(mut first) = self.first.take() else {
+            let end = (offset + bytes.len()).min(self.buffer.len());
+            if offset < end {
+                self.buffer[offset..end].copy_from_slice(&bytes[..end - offset]);
+            }
+            return Ok(());
+        };
+        first[offset..offset + bytes.len()].copy_from_slice(bytes);
+        // A full record seals to the same length, so it can be replaced in place
+        let record = self.seal_record(&first, 0, false)?;
+        self.first = Some(first);
+        self.file.seek(SeekFrom::Start(HEADER_LEN as u64))?;
+        self.file.write_all(&record)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    // Seals the remaining bytes as the last record and flushes the file
+    pub fn finish(mut self) -> Result<()> {
+        let rest = std::mem::take(&mut self.buffer);
+        let record = self.seal_record(&rest, self.index, true)?;
+        self.file.write_all(&record)?;
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        Ok(())
+    }
+}
+
+impl Write for SealingWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        while self.buffer.len() >= RECORD_LEN {
+            let rest = self.buffer.split_off(RECORD_LEN);
+            let full = std::mem::replace(&mut self.buffer, rest);
+            let record = self.seal_record(&full, self.index, false).map_err(io_error)?;
+            self.file.write_all(&record)?;
+            if self.index == 0 {
+                self.first = Some(full);
+            }
+            self.index += 1;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Closes a file a `SealingWriter` never finished, dropping a torn record and sealing an
+// empty last one. Returns false when the file was already complete.
+pub fn finish_sealed_file(path: &Path) -> Result<bool> {
+    let key = read_key()?;
+    finish_sealed_file_with(path, &key)
+}
+
+fn finish_sealed_file_with(path: &Path, key: &DataKey) -> Result<bool> {
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let size = file.metadata()?.len();
+    let mut header = [0u8; HEADER_LEN];
+    file.read_exact(&mut header)?;
+    if !is_encrypted(&header) || header[FILE_MAGIC.len()] != FILE_VERSION {
+        return Err(anyhow!("Unsupported encrypted file format"));
+    }
+    let file_id = &header[FILE_MAGIC.len() + 1..];
+
+    let sealed_len = (RECORD_LEN + RECORD_OVERHEAD) as u64;
+    let body = size - HEADER_LEN as u64;
+    let (whole, rest) = (body / sealed_len, body % sealed_len);
+    // The final record opens as last only if the writer finished
+    let mut last_opens = |index: u64| -> Result<bool> {
+        file.seek(SeekFrom::Start(HEADER_LEN as u64 + index * sealed_len))?;
+        let mut len_bytes = [0u8; 4];
+        file.read_exact(&mut len_bytes)?;
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        if len > RECORD_LEN + NONCE_LEN + TAG_LEN || HEADER_LEN as u64 + index * sealed_len + 4 + len as u64 > size {
+            return Ok(false);
+        }
+        let mut sealed = vec![0u8; len];
+        file.read_exact(&mut sealed)?;
+        Ok(open(&key.key, &record_aad(file_id, index, true), &sealed).is_ok())
+    };
+    let finished = match rest {
+        0 => whole > 0 && last_opens(whole - 1)?,
+        rest if rest >= RECORD_OVERHEAD as u64 => last_opens(whole)?,
+        _ => false,
+    };
+    if finished {
+        return Ok(false);
+    }
+
+    file.set_len(HEADER_LEN as u64 + whole * sealed_len)?;
+    file.seek(SeekFrom::End(0))?;
+    let sealed = seal(&key.key, &record_aad(file_id, whole, true), &[])?;
+    file.write_all(&(sealed.len() as u32).to_le_bytes())?;
+    file.write_all(&sealed)?;
+    file.sync_data()?;
+    Ok(true)
+}
+
+fn line_aad(context: &str, index: u64) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(context.len() + 8);
+    aad.extend_from_slice(context.as_bytes());
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad
+}
+
+// Seals one line of an append-only text file. `context` ties the line to its file and
+// `index` to its position in it, so a line moved, replayed or spliced in from another
+// file fails to open.
+pub fn seal_line(context: &str, index: u64, line: &str) -> Result<String> {
+    match write_key()? {
+        Some(key) => Ok(format!(
+            "{}{}:{}",
+            LINE_PREFIX,
+            index,
+            to_hex(&seal(&key.key, &line_aad(context, index), line.as_bytes())?)
+        )),
+        None => Ok(line.to_string()),
+    }
+}
+
+// Returns the line with the position it was sealed at, or None for a plain line
+pub fn open_line(context: &str, line: &str) -> Result<(Option<u64>, String)> {
+    let Some(sealed) = line.strip_prefix(LINE_PREFIX) else {
+        return Ok((None, line.to_string()));
+    };
+    let (index, sealed) = sealed
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Sealed line has no index"))?;
+    let index: u64 = index.parse()?;
+    let plaintext = open(&read_key()?.key, &line_aad(context, index), &from_hex(sealed)?)?;
+    Ok((Some(index), String::from_utf8(plaintext)?))
+}
+
+// Writes a plain copy of an encrypted file for use outside the app
+pub fn export_decrypted(source: &Path, destination: &Path) -> Result<()> {
+    let data = read(source)?;
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(destination, data)?;
+    info!("Exported decrypted copy of {:?} to {:?}", source, destination);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> Arc<DataKey> {
+        Arc::new(DataKey::new(random_bytes::<KEY_LEN>().unwrap()).unwrap())
+    }
+
+    fn test_path(name: &str) -> PathBuf {
+        let dir = journal::app_data_dir().join("vault-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn sealing_writer_matches_the_vault_format() {
+        let key = test_key();
+        for (name, len) in [("empty", 0), ("short", 100), ("exact", RECORD_LEN * 2), ("long", RECORD_LEN * 2 + 7)] {
+            let path = test_path(&format!("sealed-{}", name));
+            let mut writer = SealingWriter::create_with(&path, key.clone()).unwrap();
+            // Uneven writes cross record boundaries
+            for chunk in pattern(len).chunks(10_000) {
+                writer.write_all(chunk).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let data = std::fs::read(&path).unwrap();
+            assert!(is_encrypted(&data));
+            assert_eq!(decrypt_bytes(&key, &data).unwrap(), pattern(len), "{}", name);
+            assert!(!finish_sealed_file_with(&path, &key).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn patch_start_rewrites_a_sealed_header() {
+        let key = test_key();
+        for len in [40, RECORD_LEN + 40] {
+            let path = test_path(&format!("patched-{}", len));
+            let mut writer = SealingWriter::create_with(&path, key.clone()).unwrap();
+            writer.write_all(&pattern(len)).unwrap();
+            writer.patch_start(4, b"size").unwrap();
+            writer.finish().unwrap();
+
+            let mut expected = pattern(len);
+            expected[4..8].copy_from_slice(b"size");
+            assert_eq!(decrypt_bytes(&key, &std::fs::read(&path).unwrap()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn unfinished_file_is_repaired_to_its_sealed_records() {
+        let key = test_key();
+        let path = test_path("crashed");
+        let mut writer = SealingWriter::create_with(&path, key.clone()).unwrap();
+        writer.write_all(&pattern(RECORD_LEN * 2 + 500)).unwrap();
+        // Dropped without `finish`, as in a crash; then a torn partial record
+        drop(writer);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        drop(file);
+        assert!(decrypt_bytes(&key, &std::fs::read(&path).unwrap()).is_err());
+
+        assert!(finish_sealed_file_with(&path, &key).unwrap());
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(decrypt_bytes(&key, &data).unwrap(), pattern(RECORD_LEN * 2));
+        assert!(!finish_sealed_file_with(&path, &key).unwrap());
+    }
+}
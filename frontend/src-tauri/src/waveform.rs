@@ -0,0 +1,508 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::audio::decode::{MediaDecoder, TrackSelection};
+use crate::audio::{AudioTrack, TrackLayout};
+use crate::journal;
+use crate::library::{self, MeetingSource};
+use crate::vault;
+
+// Resolution of the cached peaks; requests for fewer buckets are folded down from these
+const PEAKS_PER_SECOND: u32 = 50;
+const MAX_BUCKETS: usize = 100_000;
+
+// Sidecar: magic, version, sample rate, samples per peak, source size and mtime, peak
+// count, then min/max as i16 and RMS as u16 per peak, all little-endian
+const SIDECAR_MAGIC: &[u8; 4] = b"MMWF";
+// Version 2: peak ranges no longer include zero when the samples never reach it
+const SIDECAR_VERSION: u8 = 2;
+const SIDECAR_HEADER_LEN: usize = 4 + 1 + 4 + 4 + 8 + 8 + 4;
+const SIDECAR_PEAK_LEN: usize = 6;
+
+// The waveform of the session being recorded, built as samples arrive
+static LIVE: Lazy<Mutex<Option<LiveWaveform>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Waveform {
+    pub duration_secs: f32,
+    pub peaks: Vec<Peak>,
+    // True while the session is still recording and more audio will follow
+    pub live: bool,
+}
+
+// Folds samples into peaks of a fixed number of samples each
+#[derive(Debug, Clone)]
+pub struct PeakBuilder {
+    sample_rate: u32,
+    samples_per_peak: usize,
+    peaks: Vec<Peak>,
+    min: f32,
+    max: f32,
+    sum_squares: f64,
+    count: usize,
+}
+
+impl PeakBuilder {
+    pub fn new(sample_rate: u32) -> Self {
+        PeakBuilder {
+            sample_rate,
+            samples_per_peak: (sample_rate / PEAKS_PER_SECOND).max(1) as usize,
+            peaks: Vec::new(),
+            min: 0.0,
+            max: 0.0,
+            sum_squares: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            // Seeded from the first sample so a peak that never crosses zero keeps its range
+            if self.count == 0 {
+                self.min = sample;
+                self.max = sample;
+            }
+            self.min = self.min.min(sample);
+            self.max = self.max.max(sample);
+            self.sum_squares += (sample as f64) * (sample as f64);
+            self.count += 1;
+            if self.count == self.samples_per_peak {
+                self.peaks.push(self.pending());
+                self.sum_squares = 0.0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn pending(&self) -> Peak {
+        Peak {
+            min: self.min,
+            max: self.max,
+            rms: (self.sum_squares / self.count.max(1) as f64).sqrt() as f32,
+        }
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        (self.peaks.len() * self.samples_per_peak + self.count) as f32 / self.sample_rate as f32
+    }
+
+    // Complete peaks plus the partial one still being filled
+    pub fn peaks(&self) -> Vec<Peak> {
+        let mut peaks = self.peaks.clone();
+        if self.count > 0 {
+            peaks.push(self.pending());
+        }
+        peaks
+    }
+}
+
+// Folds `peaks` into at most `buckets` evenly sized buckets
+fn downsample(peaks: &[Peak], buckets: usize) -> Vec<Peak> {
+    if peaks.len() <= buckets {
+        return peaks.to_vec();
+    }
+    (0..buckets)
+        .map(|bucket| {
+            let slice = &peaks[bucket * peaks.len() / buckets..(bucket + 1) * peaks.len() / buckets];
+            let mean_square = slice.iter().map(|p| p.rms * p.rms).sum::<f32>() / slice.len().max(1) as f32;
+            Peak {
+                min: slice.iter().map(|p| p.min).fold(f32::INFINITY, f32::min),
+                max: slice.iter().map(|p| p.max).fold(f32::NEG_INFINITY, f32::max),
+                rms: mean_square.sqrt(),
+            }
+        })
+        .collect()
+}
+
+struct LiveWaveform {
+    session_id: String,
+    mixed: PeakBuilder,
+    mic: PeakBuilder,
+    system: PeakBuilder,
+}
+
+pub fn start_live(session_id: &str, sample_rate: u32) {
+    *LIVE.lock().unwrap_or_else(PoisonError::into_inner) = Some(LiveWaveform {
+        session_id: session_id.to_string(),
+        mixed: PeakBuilder::new(sample_rate),
+        mic: PeakBuilder::new(sample_rate),
+        system: PeakBuilder::new(sample_rate),
+    });
+}
+
+// Takes the same samples the recording loop mixes; the shorter side is padded with
+// silence the way the mix is
+pub fn push_live(mixed: &[f32], mic: &[f32], system: &[f32]) {
+    let mut live = LIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(live) = live.as_mut() else {
+        return;
+    };
+    let pad = |side: &[f32]| -> Vec<f32> {
+        side.iter().copied().chain(std::iter::repeat(0.0)).take(mixed.len()).collect()
+    };
+    live.mixed.push(mixed);
+    live.mic.push(&pad(mic));
+    live.system.push(&pad(system));
+}
+
+pub fn stop_live() {
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner).take();
+}
+
+fn live_waveform(session_id: &str, track: Option<AudioTrack>, buckets: usize) -> Option<Waveform> {
+    let live = LIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    let live = live.as_ref().filter(|live| live.session_id == session_id)?;
+    let builder = match track {
+        None => &live.mixed,
+        Some(AudioTrack::Mic) => &live.mic,
+        Some(AudioTrack::System) => &live.system,
+    };
+    Some(Waveform {
+        duration_secs: builder.duration_secs(),
+        peaks: downsample(&builder.peaks(), buckets),
+        live: true,
+    })
+}
+
+fn quantize(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+// Cached peaks for one audio file, checked against the file's size and mtime
+struct PeakFile {
+    sample_rate: u32,
+    samples_per_peak: u32,
+    peaks: Vec<Peak>,
+}
+
+impl PeakFile {
+    fn duration_secs(&self) -> f32 {
+        (self.peaks.len() as u64 * self.samples_per_peak as u64) as f32 / self.sample_rate.max(1) as f32
+    }
+}
+
+fn source_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+fn sidecar_path(path: &Path, selection: TrackSelection, cache_dir: &Path) -> PathBuf {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("recording");
+    let suffix = match selection {
+        TrackSelection::Mixed => "mixed".to_string(),
+        TrackSelection::Channel(index) => format!("channel{}", index),
+        TrackSelection::Stream(index) => format!("stream{}", index),
+    };
+    cache_dir.join(format!(".{}.{}.peaks", name, suffix))
+}
+
+// Removes the cached peaks of a recording kept next to it, for every track selection.
+// An imported original's cache lives in the session directory and goes with it.
+pub fn remove_sidecars(path: &Path) {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return;
+    };
+    let prefix = format!(".{}.", name);
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        // The remainder must be a selection suffix, or `a.m4a` would match `a.m4a.part2.m4a`'s cache
+        let selection = file_name.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix(".peaks"));
+        let owned = selection.is_some_and(|selection| {
+            let index = selection
+                .strip_prefix("channel")
+                .or_else(|| selection.strip_prefix("stream"));
+            selection == "mixed"
+                || index.is_some_and(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+        });
+        if owned {
+            if let Err(e) = std::fs::remove_file(entry.path()) {
+                warn!("Failed to delete waveform cache {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
+
+fn encode_sidecar(peaks: &PeakFile, stamp: (u64, u64)) -> Vec<u8> {
+    let mut out = Vec::with_capacity(SIDECAR_HEADER_LEN + peaks.peaks.len() * SIDECAR_PEAK_LEN);
+    out.extend_from_slice(SIDECAR_MAGIC);
+    out.push(SIDECAR_VERSION);
+    out.extend_from_slice(&peaks.sample_rate.to_le_bytes());
+    out.extend_from_slice(&peaks.samples_per_peak.to_le_bytes());
+    out.extend_from_slice(&stamp.0.to_le_bytes());
+    out.extend_from_slice(&stamp.1.to_le_bytes());
+    out.extend_from_slice(&(peaks.peaks.len() as u32).to_le_bytes());
+    for peak in &peaks.peaks {
+        out.extend_from_slice(&quantize(peak.min).to_le_bytes());
+        out.extend_from_slice(&quantize(peak.max).to_le_bytes());
+        out.extend_from_slice(&(quantize(peak.rms.abs()) as u16).to_le_bytes());
+    }
+    out
+}
+
+// None when the sidecar is for another version of the source or can't be parsed
+fn decode_sidecar(data: &[u8], stamp: (u64, u64)) -> Option<PeakFile> {
+    if data.len() < SIDECAR_HEADER_LEN || !data.starts_with(SIDECAR_MAGIC) || data[4] != SIDECAR_VERSION {
+        return None;
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    if (u64_at(13), u64_at(21)) != stamp {
+        return None;
+    }
+    let count = u32_at(29) as usize;
+    let body = &data[SIDECAR_HEADER_LEN..];
+    if body.len() != count * SIDECAR_PEAK_LEN {
+        return None;
+    }
+    let scale = i16::MAX as f32;
+    let peaks = body
+        .chunks_exact(SIDECAR_PEAK_LEN)
+        .map(|chunk| Peak {
+            min: i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / scale,
+            max: i16::from_le_bytes([chunk[2], chunk[3]]) as f32 / scale,
+            rms: u16::from_le_bytes([chunk[4], chunk[5]]) as f32 / scale,
+        })
+        .collect();
+    Some(PeakFile {
+        sample_rate: u32_at(5),
+        samples_per_peak: u32_at(9),
+        peaks,
+    })
+}
+
+// Loads the cached peaks for `path`, decoding the file and writing the sidecar on a miss
+fn file_peaks(path: &Path, selection: TrackSelection, cache_dir: &Path) -> Result<PeakFile> {
+    let stamp = source_stamp(path)?;
+    let sidecar = sidecar_path(path, selection, cache_dir);
+    if sidecar.is_file() {
+        match vault::read(&sidecar) {
+            Ok(data) => {
+                if let Some(peaks) = decode_sidecar(&data, stamp) {
+                    return Ok(peaks);
+                }
+            }
+            Err(e) => warn!("Failed to read waveform cache {:?}: {}", sidecar, e),
+        }
+    }
+
+    let mut decoder = MediaDecoder::open_track(path, selection)?;
+    let mut builder = PeakBuilder::new(decoder.sample_rate());
+    while let Some(block) = decoder.next_block()? {
+        builder.push(&block);
+    }
+    let peaks = PeakFile {
+        sample_rate: builder.sample_rate,
+        samples_per_peak: builder.samples_per_peak as u32,
+        peaks: builder.peaks(),
+    };
+    // Encrypted like the recording itself when encryption is on
+    if let Err(e) = vault::write(&sidecar, &encode_sidecar(&peaks, stamp)) {
+        warn!("Failed to write waveform cache {:?}: {}", sidecar, e);
+    } else {
+        info!("Cached {} waveform peaks for {:?}", peaks.peaks.len(), path);
+    }
+    Ok(peaks)
+}
+
+// Peaks across every segment of a recording, or of an imported meeting's original
+fn session_peaks(session_id: &str, track: Option<AudioTrack>) -> Result<(Vec<Peak>, f32)> {
+    let record = library::get(session_id)?;
+    // Recordings are cached next to the audio; an imported original belongs to the user,
+    // so its cache goes in the session directory instead
+    let mut cache_dir = None;
+    let (files, layout) = match &record.source {
+        MeetingSource::Import { path } if Path::new(path).is_file() => {
+            cache_dir = Some(journal::session_dir(session_id));
+            (vec![PathBuf::from(path)], TrackLayout::Mixed)
+        }
+        _ => (
+            record.audio_files.iter().map(PathBuf::from).filter(|p| p.is_file()).collect(),
+            record.track_layout,
+        ),
+    };
+    if files.is_empty() {
+        return Err(anyhow!("Session {} has no audio file", session_id));
+    }
+
+    let mut peaks = Vec::new();
+    let mut duration = 0.0;
+    for path in &files {
+        let dir = match &cache_dir {
+            Some(dir) => dir.clone(),
+            None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let file = match (track, layout) {
+            (Some(track), layout) => file_peaks(path, TrackSelection::for_track(layout, track)?, &dir)?,
+            // A mixed decode would only see the first stream, so both are combined
+            (None, TrackLayout::MultiTrack) => {
+                let mic = file_peaks(path, TrackSelection::Stream(AudioTrack::Mic.index()), &dir)?;
+                let system = file_peaks(path, TrackSelection::Stream(AudioTrack::System.index()), &dir)?;
+                let combined = (0..mic.peaks.len().max(system.peaks.len()))
+                    .map(|i| {
+                        let a = mic.peaks.get(i).copied().unwrap_or_default();
+                        let b = system.peaks.get(i).copied().unwrap_or_default();
+                        Peak {
+                            min: a.min.min(b.min),
+                            max: a.max.max(b.max),
+                            rms: ((a.rms * a.rms + b.rms * b.rms) / 2.0).sqrt(),
+                        }
+                    })
+                    .collect();
+                PeakFile { peaks: combined, ..mic }
+            }
+            (None, _) => file_peaks(path, TrackSelection::Mixed, &dir)?,
+        };
+        duration += file.duration_secs();
+        peaks.extend(file.peaks);
+    }
+    Ok((peaks, duration))
+}
+
+// Peaks for the player: `buckets` evenly spaced over the recording, from the live
+// recording when the session is still being captured
+pub fn get_waveform(session_id: &str, buckets: usize, track: Option<AudioTrack>) -> Result<Waveform> {
+    let buckets = buckets.clamp(1, MAX_BUCKETS);
+    if let Some(waveform) = live_waveform(session_id, track, buckets) {
+        return Ok(waveform);
+    }
+    let (peaks, duration_secs) = session_peaks(session_id, track)?;
+    Ok(Waveform {
+        duration_secs,
+        peaks: downsample(&peaks, buckets),
+        live: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak(min: f32, max: f32, rms: f32) -> Peak {
+        Peak { min, max, rms }
+    }
+
+    fn peak_file() -> PeakFile {
+        PeakFile {
+            sample_rate: 16000,
+            samples_per_peak: 320,
+            peaks: vec![peak(-0.5, 0.25, 0.125), peak(-1.0, 1.0, 0.75), peak(0.0, 0.0, 0.0)],
+        }
+    }
+
+    #[test]
+    fn sidecar_round_trips() {
+        let stamp = (4096, 1_700_000_000);
+        let decoded = decode_sidecar(&encode_sidecar(&peak_file(), stamp), stamp).unwrap();
+        assert_eq!(decoded.sample_rate, 16000);
+        assert_eq!(decoded.samples_per_peak, 320);
+        assert_eq!(decoded.peaks.len(), 3);
+        // Quantized to i16, so within one step of the original
+        for (decoded, original) in decoded.peaks.iter().zip(&peak_file().peaks) {
+            assert!((decoded.min - original.min).abs() <= 1.0 / i16::MAX as f32, "{:?}", decoded);
+            assert!((decoded.max - original.max).abs() <= 1.0 / i16::MAX as f32, "{:?}", decoded);
+            assert!((decoded.rms - original.rms).abs() <= 1.0 / i16::MAX as f32, "{:?}", decoded);
+        }
+    }
+
+    #[test]
+    fn sidecar_for_another_version_of_the_source_is_ignored() {
+        let data = encode_sidecar(&peak_file(), (4096, 1_700_000_000));
+        assert!(decode_sidecar(&data, (4097, 1_700_000_000)).is_none());
+        assert!(decode_sidecar(&data, (4096, 1_700_000_001)).is_none());
+    }
+
+    #[test]
+    fn malformed_sidecar_is_ignored() {
+        let stamp = (1, 2);
+        let data = encode_sidecar(&peak_file(), stamp);
+        assert!(decode_sidecar(&data[..data.len() - 1], stamp).is_none());
+        assert!(decode_sidecar(&data[..SIDECAR_HEADER_LEN - 1], stamp).is_none());
+        let mut wrong_version = data.clone();
+        wrong_version[4] = SIDECAR_VERSION + 1;
+        assert!(decode_sidecar(&wrong_version, stamp).is_none());
+    }
+
+    #[test]
+    fn peak_range_is_seeded_from_the_first_sample() {
+        let mut builder = PeakBuilder::new(PEAKS_PER_SECOND * 4);
+        // One peak entirely above zero, one entirely below, then a partial one
+        builder.push(&[0.25, 0.5, 0.75, 0.5, -0.25, -0.5, -0.75, -0.5, 0.125]);
+        let peaks = builder.peaks();
+        assert_eq!(peaks.len(), 3);
+        assert_eq!((peaks[0].min, peaks[0].max), (0.25, 0.75));
+        assert_eq!((peaks[1].min, peaks[1].max), (-0.75, -0.25));
+        assert_eq!((peaks[2].min, peaks[2].max), (0.125, 0.125));
+
+        let buckets = downsample(&peaks[..2], 1);
+        assert_eq!((buckets[0].min, buckets[0].max), (-0.75, 0.75));
+        assert_eq!(downsample(&[peaks[0], peaks[2]], 1)[0].min, 0.125);
+    }
+
+    #[test]
+    fn sidecars_are_removed_with_their_audio() {
+        let dir = journal::app_data_dir().join("waveform-sidecars");
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio = dir.join("call.m4a");
+        let other = dir.join("call.m4a.part2.m4a");
+        let cached = [
+            sidecar_path(&audio, TrackSelection::Mixed, &dir),
+            sidecar_path(&audio, TrackSelection::Stream(1), &dir),
+            sidecar_path(&audio, TrackSelection::Channel(0), &dir),
+        ];
+        let kept = sidecar_path(&other, TrackSelection::Mixed, &dir);
+        for path in cached.iter().chain([&kept]) {
+            std::fs::write(path, b"peaks").unwrap();
+        }
+
+        remove_sidecars(&audio);
+        assert!(cached.iter().all(|path| !path.exists()));
+        assert!(kept.exists());
+    }
+
+    #[test]
+    fn downsample_keeps_short_input() {
+        let peaks = peak_file().peaks;
+        assert_eq!(downsample(&peaks, 3), peaks);
+        assert_eq!(downsample(&peaks, 10), peaks);
+    }
+
+    #[test]
+    fn downsample_splits_at_bucket_boundaries() {
+        // 10 peaks into 3 buckets: [0, 3), [3, 6), [6, 10)
+        let peaks: Vec<Peak> = (0..10).map(|i| peak(-(i as f32) / 10.0, i as f32 / 10.0, 0.5)).collect();
+        let buckets = downsample(&peaks, 3);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!((buckets[0].min, buckets[0].max), (-0.2, 0.2));
+        assert_eq!((buckets[1].min, buckets[1].max), (-0.5, 0.5));
+        assert_eq!((buckets[2].min, buckets[2].max), (-0.9, 0.9));
+        assert!(buckets.iter().all(|b| (b.rms - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn downsample_combines_rms_as_power() {
+        let peaks = vec![peak(0.0, 0.0, 0.0), peak(0.0, 0.0, 1.0)];
+        let bucket = downsample(&peaks, 1)[0];
+        assert!((bucket.rms - 0.5f32.sqrt()).abs() < 1e-6, "{:?}", bucket);
+    }
+}
+
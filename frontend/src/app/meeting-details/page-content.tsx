@@ -6,6 +6,8 @@ import { TranscriptView } from '@/components/TranscriptView';
 import { AISummary } from '@/components/AISummary';
 import { CurrentMeeting, useSidebar } from '@/components/Sidebar/SidebarProvider';
 import { ModelSettingsModal, ModelConfig } from '@/components/ModelSettingsModal';
+import { AudioPlayer } from '@/components/AudioPlayer';
+import { invoke } from '@tauri-apps/api/core';
 
 type SummaryStatus = 'idle' | 'processing' | 'summarizing' | 'regenerating' | 'completed' | 'error';
 
@@ -27,6 +29,7 @@ export default function PageContent({ meeting, summaryData }: { meeting: any, su
   const [showModelSettings, setShowModelSettings] = useState(false);
   const [originalTranscript, setOriginalTranscript] = useState<string>('');
   const [error, setError] = useState<string>('');
+  const [sessionId, setSessionId] = useState<string | null>(null);
   const { setCurrentMeeting, setMeetings } = useSidebar();
 
   useEffect(() => {
@@ -49,6 +52,13 @@ export default function PageContent({ meeting, summaryData }: { meeting: any, su
     console.log('Model config:', modelConfig);
   }, [modelConfig]);
 
+  // Meetings recorded in this app keep their audio in a local session
+  useEffect(() => {
+    invoke<string | null>('get_meeting_session', { meetingId: meeting.id })
+      .then(setSessionId)
+      .catch((error) => console.error('Failed to look up recording session:', error));
+  }, [meeting.id]);
+
   const generateAISummary = useCallback(async () => {
     setSummaryStatus('processing');
     setSummaryError(null);
@@ -449,6 +459,7 @@ export default function PageContent({ meeting, summaryData }: { meeting: any, su
                   onChange={handleTitleChange}
                 />
               </div>
+              {sessionId && <AudioPlayer sessionId={sessionId} />}
               <div className="flex items-center space-x-2">
                 <button
                   onClick={handleCopyTranscript}
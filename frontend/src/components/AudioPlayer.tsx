@@ -0,0 +1,50 @@
+'use client';
+
+import { Play, Pause } from 'lucide-react';
+import { useAudioPlayer } from '@/hooks/useAudioPlayer';
+
+interface AudioPlayerProps {
+  // Local session whose recording is streamed through the `recording` URI scheme
+  sessionId: string;
+  segment?: number;
+}
+
+const formatTime = (time: number) => {
+  const minutes = Math.floor(time / 60);
+  const seconds = Math.floor(time % 60);
+  return `${minutes}:${seconds.toString().padStart(2, '0')}`;
+};
+
+export const AudioPlayer: React.FC<AudioPlayerProps> = ({ sessionId, segment = 0 }) => {
+  const { isPlaying, currentTime, duration, error, play, pause, seek } = useAudioPlayer(sessionId, segment);
+
+  return (
+    <div className="flex flex-col space-y-1">
+      <div className="flex items-center space-x-2">
+        <button
+          onClick={isPlaying ? pause : play}
+          disabled={!!error}
+          className={`w-8 h-8 flex items-center justify-center rounded-full text-white transition-colors ${
+            error ? 'bg-gray-300 cursor-not-allowed' : 'bg-blue-500 hover:bg-blue-600'
+          }`}
+          title={isPlaying ? 'Pause recording' : 'Play recording'}
+        >
+          {isPlaying ? <Pause size={14} /> : <Play size={14} />}
+        </button>
+        <div className="text-sm text-gray-600 min-w-[40px]">{formatTime(currentTime)}</div>
+        <input
+          type="range"
+          min={0}
+          max={duration || 0}
+          step={0.1}
+          value={Math.min(currentTime, duration || 0)}
+          onChange={(e) => seek(Number(e.target.value))}
+          disabled={!duration}
+          className="flex-1 h-1 accent-blue-500"
+        />
+        <div className="text-sm text-gray-600 min-w-[40px]">{formatTime(duration)}</div>
+      </div>
+      {error && <div className="text-sm text-red-600">{error}</div>}
+    </div>
+  );
+};